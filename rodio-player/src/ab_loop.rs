@@ -0,0 +1,309 @@
+//! A-B repeat for practising along with a recording.
+//!
+//! A and B are positions in the file or CD track, as reported by the
+//! [`TimeStretch`](crate::time_stretch::TimeStretch) below [`AbLoopSource`].
+//! When playback reaches B, the source seeks back to A, so the position
+//! stays that of the audio played.  The jump from B back to A is crossfaded
+//! with the audio following B, so it doesn't click.
+
+use std::num::NonZero;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+
+use rodio::Source;
+use rodio::source::SeekError;
+use tracing::warn;
+
+use crate::time_stretch::PlaybackPosition;
+
+/// Length of the crossfade from B to A.
+const CROSSFADE: Duration = Duration::from_millis(10);
+
+/// State of the A-B repeat.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AbLoop {
+    #[default]
+    Off,
+    /// A is set, B not yet.
+    StartSet,
+    /// The segment between A and B is repeated.
+    Looping,
+}
+
+impl AbLoop {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => AbLoop::StartSet,
+            2 => AbLoop::Looping,
+            _ => AbLoop::Off,
+        }
+    }
+}
+
+/// Requested A-B repeat state, shared between the player and its sources.
+#[derive(Clone, Default)]
+pub(crate) struct AbLoopControl(Arc<AtomicU8>);
+
+impl AbLoopControl {
+    pub(crate) fn get(&self) -> AbLoop {
+        AbLoop::from_u8(self.0.load(Ordering::Relaxed))
+    }
+
+    fn set(&self, state: AbLoop) {
+        self.0.store(state as u8, Ordering::SeqCst);
+    }
+
+    /// Set A, then B; a third mark ends the loop.  Returns the new state.
+    pub(crate) fn mark(&self) -> AbLoop {
+        let next = match self.get() {
+            AbLoop::Off => AbLoop::StartSet,
+            AbLoop::StartSet => AbLoop::Looping,
+            AbLoop::Looping => AbLoop::Off,
+        };
+        self.set(next);
+        next
+    }
+
+    pub(crate) fn clear(&self) {
+        self.set(AbLoop::Off);
+    }
+}
+
+/// Passes a source on and repeats a segment of it on request.
+pub(crate) struct AbLoopSource<S> {
+    source: S,
+    control: AbLoopControl,
+    /// Position of the audio passed on, in the source.
+    position: PlaybackPosition,
+    channels: usize,
+    state: AbLoop,
+    a: Duration,
+    b: Duration,
+    /// Length of the crossfade in samples.
+    crossfade: usize,
+    /// Audio read past B, faded out over the start of the repetition.
+    fade: Vec<f32>,
+    /// Next sample of `fade`.
+    fade_pos: usize,
+    /// Whether `fade` is mixed with the audio from A, or only played
+    /// because the source could not seek back.
+    mixing: bool,
+    /// Samples of the current frame already passed on.
+    frame_pos: usize,
+}
+
+impl<S: Source> AbLoopSource<S> {
+    pub(crate) fn new(source: S, control: AbLoopControl, position: PlaybackPosition) -> Self {
+        let channels = source.channels().get() as usize;
+        let crossfade =
+            (CROSSFADE.as_secs_f32() * source.sample_rate().get() as f32) as usize * channels;
+        Self {
+            channels,
+            crossfade,
+            source,
+            control,
+            position,
+            state: AbLoop::Off,
+            a: Duration::ZERO,
+            b: Duration::ZERO,
+            fade: Vec::with_capacity(crossfade),
+            fade_pos: 0,
+            mixing: false,
+            frame_pos: 0,
+        }
+    }
+
+    /// Follow the requested state.  Only called on frame boundaries.
+    fn update_state(&mut self) {
+        let requested = self.control.get();
+        if requested == self.state {
+            return;
+        }
+        match (self.state, requested) {
+            (AbLoop::Off, AbLoop::StartSet) => {
+                self.a = self.position.get();
+                self.state = AbLoop::StartSet;
+            }
+            (AbLoop::StartSet, AbLoop::Looping) if self.position.get() > self.a + CROSSFADE => {
+                self.b = self.position.get();
+                self.state = AbLoop::Looping;
+            }
+            // Playback continues from where it is.
+            (AbLoop::Looping, AbLoop::Off) => self.state = AbLoop::Off,
+            _ => {
+                // Cancelled, or B was set too close after A.
+                self.control.clear();
+                self.state = AbLoop::Off;
+            }
+        }
+    }
+
+    /// B is reached: read the audio for the crossfade and seek back to A.
+    fn jump_to_start(&mut self) {
+        self.fade.clear();
+        self.fade.extend(self.source.by_ref().take(self.crossfade));
+        self.fade_pos = 0;
+        self.mixing = match self.source.try_seek(self.a) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("A-B repeat ended, can't seek back to A: {e}");
+                self.control.clear();
+                self.state = AbLoop::Off;
+                false
+            }
+        };
+    }
+}
+
+impl<S: Source> Iterator for AbLoopSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.frame_pos == 0 {
+            self.update_state();
+            if self.state == AbLoop::Looping
+                && self.fade_pos == self.fade.len()
+                && self.position.get() >= self.b
+            {
+                self.jump_to_start();
+            }
+        }
+        self.frame_pos = (self.frame_pos + 1) % self.channels;
+        if self.fade_pos < self.fade.len() {
+            let faded = self.fade[self.fade_pos];
+            let fade_in =
+                (self.fade_pos / self.channels) as f32 / (self.fade.len() / self.channels) as f32;
+            self.fade_pos += 1;
+            if !self.mixing {
+                return Some(faded);
+            }
+            let sample = self.source.next().unwrap_or(0.0);
+            return Some(sample * fade_in + faded * (1.0 - fade_in));
+        }
+        self.source.next()
+    }
+}
+
+impl<S: Source> Source for AbLoopSource<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> NonZero<u16> {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> NonZero<u32> {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)?;
+        // Seeking ends the loop.
+        self.control.clear();
+        self.state = AbLoop::Off;
+        self.fade.clear();
+        self.fade_pos = 0;
+        self.frame_pos = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time_stretch::{SpeedControl, TimeStretch};
+    use rodio::buffer::SamplesBuffer;
+
+    /// Stereo source counting 1, 1, 2, 2, 3, 3, … at 1 kHz.
+    fn counter(frames: usize) -> SamplesBuffer {
+        SamplesBuffer::new(
+            NonZero::new(2).unwrap(),
+            NonZero::new(1000).unwrap(),
+            (1..=frames).flat_map(|n| [n as f32; 2]).collect::<Vec<_>>(),
+        )
+    }
+
+    /// Loop over the counter, with the position reported by a time stretch
+    /// at normal speed as in the player.
+    fn looped(
+        frames: usize,
+        control: &AbLoopControl,
+    ) -> (AbLoopSource<TimeStretch<SamplesBuffer>>, PlaybackPosition) {
+        let position = PlaybackPosition::default();
+        let source = TimeStretch::new(counter(frames), SpeedControl::default(), position.clone());
+        (
+            AbLoopSource::new(source, control.clone(), position.clone()),
+            position,
+        )
+    }
+
+    #[test]
+    fn test_marks_cycle() {
+        let control = AbLoopControl::default();
+        assert_eq!(control.mark(), AbLoop::StartSet);
+        assert_eq!(control.mark(), AbLoop::Looping);
+        assert_eq!(control.mark(), AbLoop::Off);
+    }
+
+    #[test]
+    fn test_segment_repeats_until_cleared() {
+        let control = AbLoopControl::default();
+        let (mut source, _) = looped(1000, &control);
+        assert_eq!(source.by_ref().take(20).last(), Some(10.0));
+        control.mark();
+        // A at frame 11, B after frame 110
+        let segment: Vec<f32> = source.by_ref().take(200).collect();
+        control.mark();
+        // 10 ms crossfade = 10 frames at 1 kHz
+        let first: Vec<f32> = source.by_ref().take(200).collect();
+        let second: Vec<f32> = source.by_ref().take(200).collect();
+        assert_eq!(first, second);
+        assert_eq!(first[20..], segment[20..]);
+        // The crossfade starts with the audio after B.
+        assert_eq!(first[0], 111.0);
+
+        control.clear();
+        // The loop was at B, so the next samples are from after it.
+        let after: Vec<f32> = source.by_ref().take(22).collect();
+        assert_eq!(after[..2], [111.0, 111.0]);
+        assert_eq!(after[20..], [121.0, 121.0]);
+    }
+
+    #[test]
+    fn test_empty_segment_ends_loop() {
+        let control = AbLoopControl::default();
+        let (mut source, _) = looped(100, &control);
+        control.mark();
+        control.mark();
+        assert_eq!(source.next(), Some(1.0));
+        assert_eq!(control.get(), AbLoop::Off);
+    }
+
+    #[test]
+    fn test_position_stays_in_segment() {
+        let control = AbLoopControl::default();
+        let (mut source, position) = looped(1000, &control);
+        source.by_ref().take(200).count();
+        control.mark();
+        source.by_ref().take(200).count();
+        control.mark();
+        for _ in 0..5 {
+            source.by_ref().take(150).count();
+            assert_eq!(position.get(), Duration::from_millis(175));
+            source.by_ref().take(50).count();
+            assert_eq!(position.get(), Duration::from_millis(200));
+        }
+
+        // Cleared within the segment, playback goes on from there.
+        source.by_ref().take(40).count();
+        control.clear();
+        assert_eq!(source.by_ref().take(200).last(), Some(220.0));
+        assert_eq!(position.get(), Duration::from_millis(220));
+    }
+}
@@ -0,0 +1,217 @@
+//! Announcements over the music: doorbell chimes, timer beeps or recorded
+//! messages.
+//!
+//! The music plays into its own mixer, whose output passes the night mode
+//! compressor and a [`Ducking`] stage on the way to the output mixer.
+//! Announcements are played by a second player directly into the output
+//! mixer.  While an announcement plays, the music is turned down by the
+//! configured amount and faded back afterwards; the queue and streams keep
+//! running.
+
+use std::f32::consts::PI;
+use std::num::NonZero;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use rodio::Source;
+use rodio::buffer::SamplesBuffer;
+
+use crate::output::{CHANNELS, SAMPLE_RATE};
+
+/// How long fading the music down or back up takes.
+const DUCK_FADE: Duration = Duration::from_millis(300);
+
+/// Music level while an announcement plays, unless configured otherwise.
+pub const DEFAULT_DUCK_DB: f32 = -15.0;
+
+/// Gain of the [`Ducking`] stage, shared with the player.
+#[derive(Clone)]
+pub(crate) struct DuckControl {
+    /// Target gain as `f32` bits.
+    target: Arc<AtomicU32>,
+    /// Gain while ducked as `f32` bits.
+    ducked: Arc<AtomicU32>,
+}
+
+impl DuckControl {
+    fn new() -> Self {
+        Self {
+            target: Arc::new(AtomicU32::new(1f32.to_bits())),
+            ducked: Arc::new(AtomicU32::new(db_to_gain(DEFAULT_DUCK_DB).to_bits())),
+        }
+    }
+
+    /// Set how much the music is turned down during announcements.
+    pub(crate) fn set_duck_db(&self, db: f32) {
+        self.ducked
+            .store(db_to_gain(db.min(0.0)).to_bits(), Ordering::SeqCst);
+    }
+
+    /// Fade the music down (`true`) or back up (`false`).
+    pub(crate) fn duck(&self, duck: bool) {
+        let gain = if duck {
+            f32::from_bits(self.ducked.load(Ordering::SeqCst))
+        } else {
+            1.0
+        };
+        self.target.store(gain.to_bits(), Ordering::SeqCst);
+    }
+
+    /// Whether the music is (being) turned down.
+    #[cfg(test)]
+    pub(crate) fn is_ducked(&self) -> bool {
+        f32::from_bits(self.target.load(Ordering::SeqCst)) < 1.0
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Applies the ducking gain to the music, fading smoothly between levels.
+/// Never ends, so the output mixer keeps it while the music pauses.
+pub(crate) struct Ducking<S> {
+    source: S,
+    control: DuckControl,
+    gain: f32,
+    /// Gain change per sample.
+    step: f32,
+}
+
+impl<S> Ducking<S>
+where
+    S: Iterator<Item = f32>,
+{
+    /// Wrap the music `source`, which has the channels and sample rate of the
+    /// output mixer.
+    pub(crate) fn new(source: S) -> (Self, DuckControl) {
+        let control = DuckControl::new();
+        let ducking = Self {
+            source,
+            control: control.clone(),
+            gain: 1.0,
+            step: 1.0 / (DUCK_FADE.as_secs_f32() * SAMPLE_RATE as f32 * CHANNELS as f32),
+        };
+        (ducking, control)
+    }
+}
+
+impl<S> Iterator for Ducking<S>
+where
+    S: Iterator<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let target = f32::from_bits(self.control.target.load(Ordering::Relaxed));
+        if self.gain < target {
+            self.gain = (self.gain + self.step).min(target);
+        } else if self.gain > target {
+            self.gain = (self.gain - self.step).max(target);
+        }
+        Some(self.source.next().unwrap_or(0.0) * self.gain)
+    }
+}
+
+impl<S> Source for Ducking<S>
+where
+    S: Iterator<Item = f32>,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> NonZero<u16> {
+        NonZero::new(CHANNELS).unwrap()
+    }
+
+    fn sample_rate(&self) -> NonZero<u32> {
+        NonZero::new(SAMPLE_RATE).unwrap()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// A short double beep, e.g. for a kitchen timer.
+pub fn beep() -> SamplesBuffer {
+    tones(&[(1000.0, 150), (0.0, 100), (1000.0, 150)])
+}
+
+/// A two-tone doorbell chime.
+pub fn chime() -> SamplesBuffer {
+    tones(&[(659.25, 500), (523.25, 800)])
+}
+
+/// Render a sequence of sine tones (frequency in Hz, length in ms) as mono
+/// samples.  A frequency of zero is a pause.
+fn tones(tones: &[(f32, u64)]) -> SamplesBuffer {
+    /// Fade in and out of each tone, to avoid clicks.
+    const FADE_SAMPLES: f32 = SAMPLE_RATE as f32 / 100.0;
+    let mut samples = Vec::new();
+    for &(frequency, millis) in tones {
+        let len = (SAMPLE_RATE as u64 * millis / 1000) as usize;
+        samples.extend((0..len).map(|n| {
+            let envelope = (n as f32 / FADE_SAMPLES)
+                .min((len - n) as f32 / FADE_SAMPLES)
+                .min(1.0);
+            let phase = 2.0 * PI * frequency * n as f32 / SAMPLE_RATE as f32;
+            0.5 * envelope * phase.sin()
+        }));
+    }
+    SamplesBuffer::new(
+        NonZero::new(1).unwrap(),
+        NonZero::new(SAMPLE_RATE).unwrap(),
+        samples,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn music_mixer() -> (rodio::mixer::Mixer, rodio::mixer::MixerSource) {
+        rodio::mixer::mixer(
+            NonZero::new(CHANNELS).unwrap(),
+            NonZero::new(SAMPLE_RATE).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_ducking_fades_to_target() {
+        let (mixer, source) = music_mixer();
+        let (mut ducking, control) = Ducking::new(source);
+        let fade_samples = (DUCK_FADE.as_secs_f32() * SAMPLE_RATE as f32) as usize * 2;
+        mixer.add(SamplesBuffer::new(
+            NonZero::new(CHANNELS).unwrap(),
+            NonZero::new(SAMPLE_RATE).unwrap(),
+            vec![1.0; fade_samples * 3],
+        ));
+        assert_eq!(ducking.next(), Some(1.0));
+
+        control.set_duck_db(-20.0);
+        control.duck(true);
+        let ducked = ducking.by_ref().take(fade_samples + 10).last().unwrap();
+        assert!((ducked - 0.1).abs() < 1e-3);
+
+        control.duck(false);
+        let restored = ducking.by_ref().take(fade_samples + 10).last().unwrap();
+        assert_eq!(restored, 1.0);
+    }
+
+    #[test]
+    fn test_ducking_never_ends() {
+        let (_mixer, source) = music_mixer();
+        let (mut ducking, _control) = Ducking::new(source);
+        assert!(ducking.by_ref().take(1000).all(|sample| sample == 0.0));
+    }
+
+    #[test]
+    fn test_tones_length() {
+        let beep = beep();
+        assert_eq!(beep.total_duration(), Some(Duration::from_millis(400)));
+        assert!(beep.into_iter().all(|sample| sample.abs() <= 0.5));
+    }
+}
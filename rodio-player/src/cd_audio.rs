@@ -2,6 +2,8 @@
 //!
 //! This module provides functionality to:
 //! - Read the Table of Contents (TOC) from an audio CD
+//! - Read CD-Text (album, performer and track titles) where the disc has it
+//! - Compute the MusicBrainz and FreeDB disc IDs from the TOC
 //! - Stream raw PCM audio data from CD tracks via a [`rodio::Source`] implementation
 //! - Eject the CD tray
 //!
@@ -13,14 +15,22 @@
 //! when the tray is open or no disc is inserted).  This matches the behaviour
 //! of the standard `eject` command.
 
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
 use std::io;
+use std::ops::Range;
 use std::os::unix::fs::OpenOptionsExt;
 use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 
 use anyhow::{Context, Error, anyhow};
-use tracing::{debug, error};
+use tracing::debug;
+
+pub use crate::cd_read::{CdReadSettings, CdReadStats, DEFAULT_READ_RETRIES};
+use crate::cd_read::{CorrectingReader, SectorReader};
+use crate::de_emphasis::DeEmphasis;
 
 // ---------------------------------------------------------------------------
 // Linux CDROM ioctl constants (from <linux/cdrom.h>)
@@ -42,22 +52,40 @@ const CDROM_DRIVE_STATUS: libc::c_ulong = 0x5326;
 /// Address format: Logical Block Address.
 const CDROM_LBA: u8 = 0x01;
 
+/// Control bits of a TOC entry: the track holds data, not audio.
+const CTRL_DATA: u8 = 0x04;
+/// Control bits of a TOC entry: the audio was mastered with pre-emphasis.
+const CTRL_PRE_EMPHASIS: u8 = 0x01;
+
 /// Pseudo-track number representing the lead-out area (end of disc).
 const CDROM_LEADOUT: u8 = 0xAA;
 
 /// Number of CD sectors (frames) per second (Red Book standard).
 const CD_FRAMES_PER_SECOND: i32 = 75;
 
+/// Sectors before LBA 0 (the 2 second pregap of the first track).  Disc IDs
+/// count positions from the very start of the disc.
+const PREGAP_SECTORS: i32 = 150;
+
+/// Pregaps of the first track shorter than this (4 seconds) are silence or
+/// an offset of the mastering, not a hidden track.
+const MIN_HIDDEN_TRACK_SECTORS: i32 = 4 * CD_FRAMES_PER_SECOND;
+
+/// Sectors between the audio session and the data session of an enhanced CD.
+const DATA_SESSION_GAP: i32 = 11_400;
+
 /// Size of one raw audio sector in bytes (2352 = 588 stereo 16-bit frames).
 const SECTOR_SIZE: usize = 2352;
 
 /// Number of 16-bit samples in one sector (2352 / 2).
-const SAMPLES_PER_SECTOR: usize = SECTOR_SIZE / 2;
+pub(crate) const SAMPLES_PER_SECTOR: usize = SECTOR_SIZE / 2;
 
-/// How many sectors to read per ioctl call.
-/// 25 sectors ≈ 1/3 second of audio – a good trade-off between latency and
-/// system-call overhead.
-const SECTORS_PER_READ: i32 = 25;
+/// Sectors read to push a track out of the read cache of the drive: about
+/// 10 MB, more than common drives cache.
+const CACHE_FLUSH_SECTORS: i32 = 4_500;
+
+/// Sectors read at a time while flushing the cache.
+const CACHE_FLUSH_BATCH: i32 = 25;
 
 /// CD audio sample rate (Red Book).
 const CD_SAMPLE_RATE: u32 = 44_100;
@@ -65,9 +93,30 @@ const CD_SAMPLE_RATE: u32 = 44_100;
 /// CD audio channel count (stereo).
 const CD_CHANNELS: u16 = 2;
 
-/// Drive status: disc is present and the tray is closed.
+/// Drive status values returned by `CDROM_DRIVE_STATUS` (from <linux/cdrom.h>).
+const CDS_NO_DISC: libc::c_int = 1;
+const CDS_TRAY_OPEN: libc::c_int = 2;
+const CDS_DRIVE_NOT_READY: libc::c_int = 3;
 const CDS_DISC_OK: libc::c_int = 4;
 
+/// Send a SCSI/MMC command to the drive (from <scsi/sg.h>).
+const SG_IO: libc::c_ulong = 0x2285;
+/// Data is transferred from the drive to us.
+const SG_DXFER_FROM_DEV: libc::c_int = -3;
+/// MMC command READ TOC/PMA/ATIP.
+const MMC_READ_TOC: u8 = 0x43;
+/// READ TOC format returning the CD-Text packs of the lead-in.
+const READ_TOC_FORMAT_CD_TEXT: u8 = 0x05;
+/// Timeout for MMC commands in milliseconds.
+const MMC_TIMEOUT_MS: libc::c_uint = 5_000;
+
+/// Size of a CD-Text pack: header, 12 characters and a CRC.
+const CD_TEXT_PACK_SIZE: usize = 18;
+/// CD-Text pack type: album or track title.
+const CD_TEXT_TITLE: u8 = 0x80;
+/// CD-Text pack type: album or track performer.
+const CD_TEXT_PERFORMER: u8 = 0x81;
+
 // ---------------------------------------------------------------------------
 // C-compatible structs for the CDROM ioctls (repr(C))
 // ---------------------------------------------------------------------------
@@ -99,6 +148,33 @@ struct CdromTocEntry {
     cdte_datamode: u8,
 }
 
+/// Parameters for the `SG_IO` ioctl (`struct sg_io_hdr`).
+#[repr(C)]
+struct SgIoHdr {
+    interface_id: libc::c_int,
+    dxfer_direction: libc::c_int,
+    cmd_len: u8,
+    mx_sb_len: u8,
+    iovec_count: u16,
+    dxfer_len: libc::c_uint,
+    dxferp: *mut u8,
+    cmdp: *const u8,
+    sbp: *mut u8,
+    timeout: libc::c_uint,
+    flags: libc::c_uint,
+    pack_id: libc::c_int,
+    usr_ptr: *mut libc::c_void,
+    status: u8,
+    masked_status: u8,
+    msg_status: u8,
+    sb_len_wr: u8,
+    host_status: u16,
+    driver_status: u16,
+    resid: libc::c_int,
+    duration: libc::c_uint,
+    info: libc::c_uint,
+}
+
 /// Parameters for the `CDROMREADAUDIO` ioctl.
 ///
 /// Uses `#[repr(C)]` so the compiler inserts the correct alignment padding
@@ -119,10 +195,24 @@ struct CdromReadAudio {
 // Public data types
 // ---------------------------------------------------------------------------
 
+/// State of a CD drive, as reported by [`drive_status`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DriveStatus {
+    /// The drive could not be asked, e.g. because it is missing.
+    NoInfo,
+    /// The tray is closed but empty.
+    NoDisc,
+    TrayOpen,
+    /// A disc was just inserted and is being spun up.
+    NotReady,
+    /// A disc is in the drive and can be read.
+    DiscOk,
+}
+
 /// Information about a single CD track, obtained from the TOC.
 #[derive(Clone, Debug)]
 pub struct CdTrackInfo {
-    /// Track number (1-based).
+    /// Track number (1-based; the hidden track before track 1 is 0).
     pub number: u8,
     /// First sector of the track (LBA).
     pub start_lba: i32,
@@ -132,6 +222,17 @@ pub struct CdTrackInfo {
     pub duration: Duration,
     /// `true` if this is an audio track, `false` for data tracks.
     pub is_audio: bool,
+    /// The audio was mastered with pre-emphasis and is de-emphasised when
+    /// played.
+    pub pre_emphasis: bool,
+    /// Sectors before the track that belong to no other track and can be
+    /// read.  The TOC only tells this for the first track, whose pregap
+    /// starts at LBA 0.
+    pub pregap: i32,
+    /// Track title from CD-Text, if the disc has one.
+    pub title: Option<String>,
+    /// Track performer from CD-Text, if the disc has one.
+    pub performer: Option<String>,
 }
 
 impl CdTrackInfo {
@@ -147,10 +248,17 @@ impl CdTrackInfo {
         let secs = total_secs % 60;
         format!("{mins}:{secs:02}")
     }
+
+    /// The CD-Text title, or `Track N`.
+    pub fn display_title(&self) -> String {
+        self.title
+            .clone()
+            .unwrap_or_else(|| format!("Track {}", self.number))
+    }
 }
 
 /// Overall information about an audio CD.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct CdInfo {
     /// First track number on the disc (usually 1).
     pub first_track: u8,
@@ -158,9 +266,54 @@ pub struct CdInfo {
     pub last_track: u8,
     /// Information for each track.
     pub tracks: Vec<CdTrackInfo>,
+    /// Album title from CD-Text, if the disc has one.
+    pub title: Option<String>,
+    /// Album performer from CD-Text, if the disc has one.
+    pub performer: Option<String>,
+    /// Path of a cover image, if one is known for the disc.
+    pub cover: Option<String>,
+}
+
+/// IDs identifying a disc in online databases, computed from its TOC.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscId {
+    /// MusicBrainz disc ID (28 characters, URL-safe base64).
+    pub musicbrainz: String,
+    /// FreeDB/CDDB disc ID (8 hex digits).
+    pub freedb: String,
 }
 
 impl CdInfo {
+    /// Audio hidden in the pregap of the first track (HTOA), as track 0.
+    /// It isn't part of `tracks`, so it doesn't change the disc IDs.
+    pub fn hidden_track(&self) -> Option<CdTrackInfo> {
+        let first = self.tracks.first()?;
+        if !first.is_audio || first.pregap < MIN_HIDDEN_TRACK_SECTORS {
+            return None;
+        }
+        let start_lba = first.start_lba - first.pregap;
+        Some(CdTrackInfo {
+            number: 0,
+            start_lba,
+            end_lba: first.start_lba,
+            duration: Duration::from_secs_f64(first.pregap as f64 / CD_FRAMES_PER_SECOND as f64),
+            is_audio: true,
+            pre_emphasis: first.pre_emphasis,
+            pregap: 0,
+            title: None,
+            performer: None,
+        })
+    }
+
+    /// Where the files of the disc are: the start of the last data track,
+    /// which is the data session of an enhanced CD.  `None` for audio CDs.
+    pub fn data_session_lba(&self) -> Option<u32> {
+        self.tracks
+            .iter()
+            .rfind(|t| !t.is_audio)
+            .map(|t| t.start_lba.max(0) as u32)
+    }
+
     /// Return only the audio tracks.
     pub fn audio_tracks(&self) -> Vec<&CdTrackInfo> {
         self.tracks.iter().filter(|t| t.is_audio).collect()
@@ -174,6 +327,87 @@ impl CdInfo {
             .map(|t| t.duration)
             .sum()
     }
+
+    /// The disc IDs, or `None` if the disc has no audio tracks.
+    pub fn disc_id(&self) -> Option<DiscId> {
+        Some(DiscId {
+            musicbrainz: self.musicbrainz_disc_id()?,
+            freedb: self.freedb_disc_id()?,
+        })
+    }
+
+    /// The MusicBrainz disc ID: a SHA-1 over the first and last audio track
+    /// numbers and the track offsets.  On enhanced CDs the data track at the
+    /// end doesn't count, and the audio session ends before its gap.
+    fn musicbrainz_disc_id(&self) -> Option<String> {
+        let audio = self.audio_tracks();
+        let (first, last) = (audio.first()?, audio.last()?);
+        let data_follows = self
+            .tracks
+            .iter()
+            .any(|t| !t.is_audio && t.number > last.number);
+        let leadout = if data_follows {
+            last.end_lba - DATA_SESSION_GAP
+        } else {
+            last.end_lba
+        };
+
+        let mut offsets = [0i32; 100];
+        offsets[0] = leadout + PREGAP_SECTORS;
+        for track in &audio {
+            offsets[track.number as usize] = track.start_lba + PREGAP_SECTORS;
+        }
+        let mut toc = format!("{:02X}{:02X}", first.number, last.number);
+        for offset in offsets {
+            toc.push_str(&format!("{offset:08X}"));
+        }
+        let digest = sha1_smol::Sha1::from(toc).digest().bytes();
+        Some(musicbrainz_base64(&digest))
+    }
+
+    /// The FreeDB/CDDB disc ID: a checksum over the track start times, the
+    /// length of the disc in seconds and the number of tracks.
+    fn freedb_disc_id(&self) -> Option<String> {
+        let (first, last) = (self.tracks.first()?, self.tracks.last()?);
+        let seconds = |lba: i32| ((lba + PREGAP_SECTORS) / CD_FRAMES_PER_SECOND) as u32;
+        let digit_sum = |mut n: u32| {
+            let mut sum = 0;
+            while n > 0 {
+                sum += n % 10;
+                n /= 10;
+            }
+            sum
+        };
+        let checksum: u32 = self
+            .tracks
+            .iter()
+            .map(|t| digit_sum(seconds(t.start_lba)))
+            .sum();
+        let length = seconds(last.end_lba) - seconds(first.start_lba);
+        let id = (checksum % 255) << 24 | length << 8 | self.tracks.len() as u32;
+        Some(format!("{id:08x}"))
+    }
+}
+
+/// Base64 with the alphabet MusicBrainz uses for disc IDs, where `.`, `_`
+/// and `-` replace `+`, `/` and `=` so the ID can go into URLs.
+fn musicbrainz_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789._";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('-');
+            }
+        }
+    }
+    out
 }
 
 // ---------------------------------------------------------------------------
@@ -214,19 +448,151 @@ fn read_toc_entry(fd: libc::c_int, track_num: u8) -> Result<CdromTocEntry, Error
     Ok(entry)
 }
 
+/// Read the raw CD-Text packs from the lead-in of the disc.
+///
+/// The kernel has no CDROM ioctl for CD-Text, so this sends the MMC command
+/// READ TOC/PMA/ATIP with format 5 through `SG_IO`: once for the length of
+/// the data, and once for the data itself.
+fn read_cd_text_packs(fd: libc::c_int) -> Result<Vec<u8>, Error> {
+    let mut header = [0u8; 4];
+    read_toc_mmc(fd, READ_TOC_FORMAT_CD_TEXT, &mut header)?;
+    let len = u16::from_be_bytes([header[0], header[1]]) as usize + 2;
+    if len <= header.len() {
+        return Ok(Vec::new());
+    }
+    let mut data = vec![0u8; len];
+    read_toc_mmc(fd, READ_TOC_FORMAT_CD_TEXT, &mut data)?;
+    Ok(data.split_off(header.len()))
+}
+
+/// Send READ TOC/PMA/ATIP with the given format, reading into `buf`.
+fn read_toc_mmc(fd: libc::c_int, format: u8, buf: &mut [u8]) -> Result<(), Error> {
+    let len = buf.len() as u16;
+    let [len_hi, len_lo] = len.to_be_bytes();
+    let cmd = [MMC_READ_TOC, 0, format, 0, 0, 0, 0, len_hi, len_lo, 0];
+    let mut sense = [0u8; 32];
+    let mut hdr = SgIoHdr {
+        interface_id: b'S' as libc::c_int,
+        dxfer_direction: SG_DXFER_FROM_DEV,
+        cmd_len: cmd.len() as u8,
+        mx_sb_len: sense.len() as u8,
+        iovec_count: 0,
+        dxfer_len: len as libc::c_uint,
+        dxferp: buf.as_mut_ptr(),
+        cmdp: cmd.as_ptr(),
+        sbp: sense.as_mut_ptr(),
+        timeout: MMC_TIMEOUT_MS,
+        flags: 0,
+        pack_id: 0,
+        usr_ptr: std::ptr::null_mut(),
+        status: 0,
+        masked_status: 0,
+        msg_status: 0,
+        sb_len_wr: 0,
+        host_status: 0,
+        driver_status: 0,
+        resid: 0,
+        duration: 0,
+        info: 0,
+    };
+
+    let ret = unsafe { libc::ioctl(fd, SG_IO, &mut hdr as *mut SgIoHdr) };
+    if ret < 0 {
+        return Err(anyhow!(
+            "SG_IO ioctl failed: {}",
+            io::Error::last_os_error()
+        ));
+    }
+    if hdr.status != 0 || hdr.host_status != 0 || hdr.driver_status != 0 {
+        return Err(anyhow!(
+            "READ TOC format {format} failed: status {:#x}, sense key {:#x}",
+            hdr.status,
+            sense[2] & 0x0F
+        ));
+    }
+    Ok(())
+}
+
+/// Titles and performers from CD-Text, by track number (0 is the album).
+#[derive(Debug, Default, PartialEq)]
+struct CdText {
+    titles: HashMap<u8, String>,
+    performers: HashMap<u8, String>,
+}
+
+/// Parse CD-Text packs.
+///
+/// The text of one pack type runs on from pack to pack as a sequence of
+/// NUL-terminated strings, the first one for the track number of the first
+/// pack, then one per track.  A single tab stands for "same as the previous
+/// track".  Only the first block (usually English) in single-byte characters
+/// is read; it is decoded as ISO 8859-1, which covers ASCII as well.
+fn parse_cd_text(packs: &[u8]) -> CdText {
+    let mut text = CdText::default();
+    for (pack_type, strings) in [
+        (CD_TEXT_TITLE, &mut text.titles),
+        (CD_TEXT_PERFORMER, &mut text.performers),
+    ] {
+        let mut bytes = Vec::new();
+        let mut first_track = None;
+        for pack in packs.chunks_exact(CD_TEXT_PACK_SIZE) {
+            let block = (pack[3] >> 4) & 0x07;
+            let double_byte = pack[3] & 0x80 != 0;
+            if pack[0] != pack_type || block != 0 || double_byte {
+                continue;
+            }
+            first_track.get_or_insert(pack[1] & 0x7F);
+            bytes.extend_from_slice(&pack[4..16]);
+        }
+        let Some(mut track) = first_track else {
+            continue;
+        };
+        let mut previous: Option<String> = None;
+        let mut parts: Vec<&[u8]> = bytes.split(|&b| b == 0).collect();
+        // The last part follows the last NUL: padding or a cut-off string.
+        parts.pop();
+        for part in parts {
+            let value = if part == b"\t" {
+                previous.clone()
+            } else {
+                let value: String = part.iter().map(|&b| b as char).collect();
+                Some(value.trim().to_string())
+            };
+            if let Some(value) = value.filter(|value| !value.is_empty()) {
+                strings.insert(track, value.clone());
+                previous = Some(value);
+            }
+            track = track.saturating_add(1);
+        }
+    }
+    text
+}
+
 // ---------------------------------------------------------------------------
 // Public functions
 // ---------------------------------------------------------------------------
 
 /// Check whether a disc is present in the given CD drive.
 pub fn is_disc_present(device: &str) -> bool {
+    drive_status(device) == DriveStatus::DiscOk
+}
+
+/// Ask the drive whether its tray is open and whether a disc is in it.
+/// Drives that cannot be opened report [`DriveStatus::NoInfo`].
+pub fn drive_status(device: &str) -> DriveStatus {
     let file = match open_cd_device(device) {
         Ok(f) => f,
-        Err(_) => return false,
+        Err(_) => return DriveStatus::NoInfo,
     };
     let fd = file.as_raw_fd();
     let status = unsafe { libc::ioctl(fd, CDROM_DRIVE_STATUS, 0 as libc::c_int) };
-    status == CDS_DISC_OK
+    match status {
+        CDS_NO_DISC => DriveStatus::NoDisc,
+        CDS_TRAY_OPEN => DriveStatus::TrayOpen,
+        CDS_DRIVE_NOT_READY => DriveStatus::NotReady,
+        CDS_DISC_OK => DriveStatus::DiscOk,
+        _ => DriveStatus::NoInfo,
+    }
 }
 
 /// Read the Table of Contents from the CD in the given device.
@@ -255,6 +621,7 @@ pub fn read_cd_toc(device: &str) -> Result<CdInfo, Error> {
         number: u8,
         start_lba: i32,
         is_audio: bool,
+        pre_emphasis: bool,
     }
 
     let mut entries: Vec<RawEntry> = Vec::new();
@@ -262,15 +629,17 @@ pub fn read_cd_toc(device: &str) -> Result<CdInfo, Error> {
     for track_num in first_track..=last_track {
         let entry = read_toc_entry(fd, track_num)?;
         let ctrl = (entry.cdte_adr_ctrl >> 4) & 0x0F;
-        let is_audio = ctrl & 0x04 == 0;
+        let is_audio = ctrl & CTRL_DATA == 0;
+        let pre_emphasis = is_audio && ctrl & CTRL_PRE_EMPHASIS != 0;
         debug!(
-            "  Track {track_num}: LBA={}, audio={}",
-            entry.cdte_addr_lba, is_audio
+            "  Track {track_num}: LBA={}, audio={}, pre-emphasis={}",
+            entry.cdte_addr_lba, is_audio, pre_emphasis
         );
         entries.push(RawEntry {
             number: track_num,
             start_lba: entry.cdte_addr_lba,
             is_audio,
+            pre_emphasis,
         });
     }
 
@@ -296,13 +665,33 @@ pub fn read_cd_toc(device: &str) -> Result<CdInfo, Error> {
             end_lba,
             duration: Duration::from_secs_f64(duration_secs),
             is_audio: raw.is_audio,
+            pre_emphasis: raw.pre_emphasis,
+            pregap: if i == 0 { raw.start_lba.max(0) } else { 0 },
+            title: None,
+            performer: None,
         });
     }
 
+    // 4. CD-Text is optional: most discs and some drives don't have it.
+    let mut text = match read_cd_text_packs(fd) {
+        Ok(packs) => parse_cd_text(&packs),
+        Err(e) => {
+            debug!("No CD-Text: {e}");
+            CdText::default()
+        }
+    };
+    for track in &mut tracks {
+        track.title = text.titles.remove(&track.number);
+        track.performer = text.performers.remove(&track.number);
+    }
+
     Ok(CdInfo {
         first_track,
         last_track,
         tracks,
+        title: text.titles.remove(&0),
+        performer: text.performers.remove(&0),
+        cover: None,
     })
 }
 
@@ -335,12 +724,85 @@ pub fn eject_cd(device: &str) -> Result<(), Error> {
     Ok(())
 }
 
-/// Create a [`CdTrackSource`] that streams PCM audio for the given track.
-pub fn open_track(device: &str, track: &CdTrackInfo) -> Result<CdTrackSource, Error> {
-    if !track.is_audio {
+/// Read the audio of `disc` farthest away from `track`, so the drive no
+/// longer has the track in its read cache and reading it again really reads
+/// the disc.  Sectors that can't be read are skipped.
+pub fn flush_drive_cache(device: &str, disc: &CdInfo, track: &CdTrackInfo) -> Result<(), Error> {
+    let audio = disc.audio_tracks();
+    let (Some(first), Some(last)) = (audio.first(), audio.last()) else {
+        return Ok(());
+    };
+    let range = cache_flush_range(first.start_lba, last.end_lba, track);
+    debug!(
+        "Flushing the drive cache: LBA {}–{}",
+        range.start, range.end
+    );
+    let file = open_cd_device(device)?;
+    let mut buf = vec![0u8; CACHE_FLUSH_BATCH as usize * SECTOR_SIZE];
+    for lba in range.clone().step_by(CACHE_FLUSH_BATCH as usize) {
+        let count = CACHE_FLUSH_BATCH.min(range.end - lba);
+        if let Err(e) = read_audio_sectors(&file, lba, count, &mut buf) {
+            debug!("Could not read LBA {lba} to flush the cache: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// The sectors [`flush_drive_cache`] reads: up to [`CACHE_FLUSH_SECTORS`] at
+/// the end of the audio area (`start..end`) farther away from `track`.
+fn cache_flush_range(start: i32, end: i32, track: &CdTrackInfo) -> Range<i32> {
+    if track.start_lba - start > end - track.end_lba {
+        start..(start + CACHE_FLUSH_SECTORS).min(track.start_lba)
+    } else {
+        (end - CACHE_FLUSH_SECTORS).max(track.end_lba)..end
+    }
+}
+
+/// Create a [`CdTrackSource`] that streams PCM audio for the given track,
+/// read as `read` says.
+pub fn open_track(
+    device: &str,
+    track: &CdTrackInfo,
+    read: &CdReadSettings,
+) -> Result<CdTrackSource, Error> {
+    open_tracks(device, std::slice::from_ref(track), read)
+}
+
+/// Create a [`CdTrackSource`] that streams consecutive tracks without a gap,
+/// like the disc itself.  The tracks must follow each other on the disc and
+/// all have pre-emphasis or none.
+pub fn open_tracks(
+    device: &str,
+    tracks: &[CdTrackInfo],
+    read: &CdReadSettings,
+) -> Result<CdTrackSource, Error> {
+    let (Some(first), Some(last)) = (tracks.first(), tracks.last()) else {
+        return Err(anyhow!("No tracks to play"));
+    };
+    if let Some(track) = tracks.iter().find(|t| !t.is_audio) {
         return Err(anyhow!("Track {} is not an audio track", track.number));
     }
-    CdTrackSource::new(device, track.start_lba, track.end_lba)
+    if let Some(pair) = tracks.windows(2).find(|pair| {
+        pair[0].end_lba != pair[1].start_lba || pair[0].pre_emphasis != pair[1].pre_emphasis
+    }) {
+        return Err(anyhow!(
+            "Tracks {} and {} can't be played without a gap",
+            pair[0].number,
+            pair[1].number
+        ));
+    }
+    let track_ends = tracks
+        .iter()
+        .map(|t| (t.end_lba - first.start_lba) as usize * SAMPLES_PER_SECTOR)
+        .collect();
+    CdTrackSource::new(
+        device,
+        first.start_lba,
+        last.end_lba,
+        first.pre_emphasis,
+        track_ends,
+        read,
+    )
 }
 
 // ---------------------------------------------------------------------------
@@ -350,16 +812,19 @@ pub fn open_track(device: &str, track: &CdTrackInfo) -> Result<CdTrackSource, Er
 /// A [`rodio::Source`] that reads raw 16-bit PCM audio directly from a CD track.
 ///
 /// Audio CDs store data as 44 100 Hz, 16-bit signed, stereo PCM – which is
-/// exactly what rodio expects, so no transcoding is necessary.
+/// exactly what rodio expects, so no transcoding is necessary.  Jitter
+/// between reads is corrected and unreadable sectors are concealed, as
+/// [`CdReadSettings`] says.  Tracks with pre-emphasis are de-emphasised.
+///
+/// Several consecutive tracks can be read as one source, so there is no gap
+/// between them; [`CdTrackSource::current_track`] tells which one is playing.
 pub struct CdTrackSource {
-    /// Open file handle to the CD device.
-    file: File,
-    /// Next sector to read from disc.
-    current_lba: i32,
-    /// Past-the-end sector for this track.
-    end_lba: i32,
-    /// Reusable byte buffer for raw sector reads (avoids per-call allocation).
-    raw_buf: Vec<u8>,
+    /// Reads the sectors of the track from the CD device.
+    reader: CorrectingReader<File>,
+    /// First sector of the track.
+    start_lba: i32,
+    /// Turns the treble down for tracks mastered with pre-emphasis.
+    de_emphasis: Option<DeEmphasis>,
     /// Buffer of decoded i16 samples.
     buffer: Vec<i16>,
     /// Current read position within `buffer`.
@@ -368,10 +833,26 @@ pub struct CdTrackSource {
     total_samples: usize,
     /// Number of samples yielded so far via the iterator.
     samples_yielded: usize,
+    /// Samples to skip at the start of the next read, to seek within a
+    /// sector.
+    skip: usize,
+    /// Sample offset at which each of the tracks ends.
+    track_ends: Vec<usize>,
+    /// Index of the track the next sample belongs to.
+    track: usize,
+    /// `track`, for whoever wants to know about track changes.
+    current_track: Arc<AtomicUsize>,
 }
 
 impl CdTrackSource {
-    fn new(device: &str, start_lba: i32, end_lba: i32) -> Result<Self, Error> {
+    fn new(
+        device: &str,
+        start_lba: i32,
+        end_lba: i32,
+        pre_emphasis: bool,
+        track_ends: Vec<usize>,
+        read: &CdReadSettings,
+    ) -> Result<Self, Error> {
         let file = open_cd_device(device)?;
 
         let sector_count = (end_lba - start_lba) as usize;
@@ -381,75 +862,66 @@ impl CdTrackSource {
             "CdTrackSource: LBA {start_lba}–{end_lba} ({sector_count} sectors, {total_samples} samples)"
         );
 
-        // Pre-allocate the raw byte buffer for the maximum read size so
-        // `fill_buffer` never has to allocate on the heap.
-        let max_bytes = SECTORS_PER_READ as usize * SECTOR_SIZE;
-
         Ok(Self {
-            file,
-            current_lba: start_lba,
-            end_lba,
-            raw_buf: vec![0u8; max_bytes],
+            reader: CorrectingReader::new(file, start_lba, end_lba, read.clone()),
+            start_lba,
+            de_emphasis: pre_emphasis.then(|| DeEmphasis::new(CD_CHANNELS, CD_SAMPLE_RATE)),
             buffer: Vec::new(),
             buffer_pos: 0,
             total_samples,
             samples_yielded: 0,
+            skip: 0,
+            track_ends,
+            track: 0,
+            current_track: Arc::default(),
         })
     }
 
+    /// Index of the track that is playing, among the tracks the source was
+    /// opened with.  It changes with the first sample of the next track, and
+    /// when seeking into another track.
+    pub fn current_track(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.current_track)
+    }
+
+    fn update_track(&mut self) {
+        let track = track_at(&self.track_ends, self.samples_yielded);
+        if track != self.track {
+            self.track = track;
+            self.current_track.store(track, Ordering::SeqCst);
+        }
+    }
+
     /// Fill the internal buffer by reading the next batch of sectors from disc.
     /// Returns `true` if samples were read, `false` if the track is finished.
+    /// Sectors that can't be read are concealed, so a scratch doesn't end
+    /// the track.
     fn fill_buffer(&mut self) -> bool {
-        if self.current_lba >= self.end_lba {
+        self.buffer_pos = std::mem::take(&mut self.skip);
+        if !self.reader.read(&mut self.buffer) {
             return false;
         }
-
-        let remaining_sectors = self.end_lba - self.current_lba;
-        let sectors_to_read = remaining_sectors.min(SECTORS_PER_READ);
-
-        let byte_count = sectors_to_read as usize * SECTOR_SIZE;
-        self.raw_buf.resize(byte_count, 0u8);
-
-        match read_audio_sectors(
-            &self.file,
-            self.current_lba,
-            sectors_to_read,
-            &mut self.raw_buf[..byte_count],
-        ) {
-            Ok(()) => {
-                self.current_lba += sectors_to_read;
-
-                // Convert raw bytes to i16 samples (little-endian on Linux).
-                let sample_count = byte_count / 2;
-                self.buffer.clear();
-                self.buffer.reserve(sample_count);
-                for chunk in self.raw_buf[..byte_count].chunks_exact(2) {
-                    let sample = i16::from_le_bytes([chunk[0], chunk[1]]);
-                    self.buffer.push(sample);
-                }
-                self.buffer_pos = 0;
-                true
-            }
-            Err(e) => {
-                // On read error, log and skip ahead by the failed sectors to
-                // avoid getting stuck in a loop on a scratched region.
-                error!(
-                    "CD read error at LBA {}: {e} – skipping {sectors_to_read} sectors",
-                    self.current_lba
-                );
-                self.current_lba += sectors_to_read;
-
-                // Insert silence for the skipped sectors so playback continues.
-                let sample_count = sectors_to_read as usize * SAMPLES_PER_SECTOR;
-                self.buffer.clear();
-                self.buffer.resize(sample_count, 0i16);
-                self.buffer_pos = 0;
-                true
-            }
+        if let Some(de_emphasis) = &mut self.de_emphasis {
+            de_emphasis.process(&mut self.buffer);
         }
+        true
     }
 }
 
+impl SectorReader for File {
+    fn read_sectors(&mut self, lba: i32, count: i32, buf: &mut [u8]) -> Result<(), Error> {
+        read_audio_sectors(self, lba, count, buf)
+    }
+}
+
+/// Index of the track with the sample at `offset`, given where each track
+/// ends.  Offsets past the end belong to the last track.
+fn track_at(track_ends: &[usize], offset: usize) -> usize {
+    track_ends
+        .partition_point(|&end| end <= offset)
+        .min(track_ends.len() - 1)
+}
+
 /// Perform the `CDROMREADAUDIO` ioctl on the given CD device file.
 fn read_audio_sectors(
     file: &File,
@@ -491,6 +963,9 @@ impl Iterator for CdTrackSource {
             }
         }
 
+        if self.samples_yielded >= self.track_ends[self.track] {
+            self.update_track();
+        }
         let sample = self.buffer[self.buffer_pos];
         self.buffer_pos += 1;
         self.samples_yielded += 1;
@@ -530,4 +1005,175 @@ impl rodio::Source for CdTrackSource {
         let secs = total_frames as f64 / CD_SAMPLE_RATE as f64;
         Some(Duration::from_secs_f64(secs))
     }
+
+    /// Seek to the sector with the frame at `pos` and skip to the frame
+    /// within it.  The sector is read with the next sample, so seeking
+    /// doesn't wait for the drive.  Seeking past the end ends the track.
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        let channels = CD_CHANNELS as usize;
+        let frames_per_sector = SAMPLES_PER_SECTOR / channels;
+        let total_frames = self.total_samples / channels;
+        let frame = ((pos.as_secs_f64() * CD_SAMPLE_RATE as f64) as usize).min(total_frames);
+        let lba = self.start_lba + (frame / frames_per_sector) as i32;
+        debug!("Seeking to {pos:?} (LBA {lba})");
+        self.reader.seek(lba);
+        if let Some(de_emphasis) = &mut self.de_emphasis {
+            de_emphasis.reset();
+        }
+        self.buffer.clear();
+        self.buffer_pos = 0;
+        self.skip = frame % frames_per_sector * channels;
+        self.samples_yielded = frame * channels;
+        self.update_track();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A disc with audio tracks starting at the given LBAs, a data track
+    /// starting at `data` (if any), and the lead-out at `leadout`.
+    fn disc(starts: &[i32], data: Option<i32>, leadout: i32) -> CdInfo {
+        let mut all: Vec<(i32, bool)> = starts.iter().map(|&lba| (lba, true)).collect();
+        all.extend(data.map(|lba| (lba, false)));
+        let tracks = all
+            .iter()
+            .enumerate()
+            .map(|(i, &(start_lba, is_audio))| {
+                let end_lba = all.get(i + 1).map_or(leadout, |next| next.0);
+                CdTrackInfo {
+                    number: i as u8 + 1,
+                    start_lba,
+                    end_lba,
+                    duration: Duration::ZERO,
+                    is_audio,
+                    pre_emphasis: false,
+                    pregap: if i == 0 { start_lba } else { 0 },
+                    title: None,
+                    performer: None,
+                }
+            })
+            .collect();
+        CdInfo {
+            first_track: 1,
+            last_track: all.len() as u8,
+            tracks,
+            ..CdInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_disc_ids() {
+        let disc = disc(&[0, 15213, 32164, 46442, 63264, 83145], None, 95312);
+        let id = disc.disc_id().unwrap();
+        assert_eq!(id.musicbrainz, "tjQHTzG0kBph8NGlojLTGGH0844-");
+        assert_eq!(id.freedb, "2c04f606");
+    }
+
+    #[test]
+    fn test_disc_ids_of_enhanced_cd() {
+        let disc = disc(&[0, 20000], Some(40000), 60000);
+        let id = disc.disc_id().unwrap();
+        assert_eq!(id.musicbrainz, "biFr6nJ6fFShZCccKBzsY.omYDM-");
+        assert_eq!(id.freedb, "1f032003");
+        assert_eq!(CdInfo::default().disc_id(), None);
+    }
+
+    #[test]
+    fn test_hidden_track() {
+        let plain = disc(&[0, 15213], None, 30000);
+        assert!(plain.hidden_track().is_none());
+        // A short offset of the first track is no hidden track.
+        let offset = disc(&[32, 15213], None, 30000);
+        assert!(offset.hidden_track().is_none());
+
+        let hidden = disc(&[4500, 15213], None, 30000);
+        let track = hidden.hidden_track().unwrap();
+        assert_eq!(track.number, 0);
+        assert_eq!((track.start_lba, track.end_lba), (0, 4500));
+        assert_eq!(track.duration, Duration::from_secs(60));
+        // The hidden track doesn't change the disc IDs.
+        assert_eq!(hidden.audio_tracks().len(), 2);
+
+        let data_first = disc(&[], Some(4500), 30000);
+        assert!(data_first.hidden_track().is_none());
+    }
+
+    #[test]
+    fn test_data_session() {
+        assert_eq!(disc(&[0, 20000], None, 40000).data_session_lba(), None);
+        assert_eq!(
+            disc(&[0, 20000], Some(40000), 60000).data_session_lba(),
+            Some(40000)
+        );
+        assert_eq!(disc(&[], Some(0), 60000).data_session_lba(), Some(0));
+    }
+
+    #[test]
+    fn test_track_at() {
+        let ends = [1176, 2352, 4704];
+        assert_eq!(track_at(&ends, 0), 0);
+        assert_eq!(track_at(&ends, 1175), 0);
+        assert_eq!(track_at(&ends, 1176), 1);
+        assert_eq!(track_at(&ends, 2352), 2);
+        assert_eq!(track_at(&ends, 4703), 2);
+        assert_eq!(track_at(&ends, 10_000), 2);
+        assert_eq!(track_at(&[1176], 500), 0);
+    }
+
+    #[test]
+    fn test_cache_flush_range() {
+        let disc = disc(&[0, 15000, 30000, 60000], None, 90000);
+        let flush = |n: usize| cache_flush_range(0, 90000, &disc.tracks[n]);
+        assert_eq!(flush(0), 85500..90000);
+        assert_eq!(flush(3), 0..4500);
+        // Short of room, only the sectors up to the track are read.
+        let short = self::disc(&[0, 2000], None, 4000);
+        assert_eq!(cache_flush_range(0, 4000, &short.tracks[1]), 0..2000);
+    }
+
+    /// Build CD-Text packs of one type from `text`, starting at `track`.
+    fn packs(pack_type: u8, track: u8, text: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (seq, chunk) in text.chunks(12).enumerate() {
+            let mut pack = vec![pack_type, track, seq as u8, 0];
+            pack.extend_from_slice(chunk);
+            pack.resize(CD_TEXT_PACK_SIZE, 0);
+            data.extend(pack);
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_titles_and_performers() {
+        let mut data = packs(
+            CD_TEXT_TITLE,
+            0,
+            b"Die Zauberfl\xf6te\0Ouvert\xfcre\0Der Vogelf\xe4nger\0",
+        );
+        data.extend(packs(CD_TEXT_PERFORMER, 0, b"Mozart\0\t\0\t\0"));
+        let text = parse_cd_text(&data);
+        assert_eq!(text.titles[&0], "Die Zauberflöte");
+        assert_eq!(text.titles[&1], "Ouvertüre");
+        assert_eq!(text.titles[&2], "Der Vogelfänger");
+        assert_eq!(text.performers[&2], "Mozart");
+    }
+
+    #[test]
+    fn test_parse_skips_other_blocks_and_empty_titles() {
+        let mut data = packs(CD_TEXT_TITLE, 0, b"\0One\0Two\0");
+        // A second language block must not overwrite the first.
+        let mut german = packs(CD_TEXT_TITLE, 0, b"Album\0Eins\0Zwei\0");
+        for pack in german.chunks_exact_mut(CD_TEXT_PACK_SIZE) {
+            pack[3] = 0x10;
+        }
+        data.extend(german);
+        let text = parse_cd_text(&data);
+        assert!(!text.titles.contains_key(&0));
+        assert_eq!(text.titles[&1], "One");
+        assert_eq!(text.titles[&2], "Two");
+        assert!(text.performers.is_empty());
+    }
 }
@@ -0,0 +1,239 @@
+//! Album, artist and track titles of audio CDs from online databases.
+//!
+//! A [`MetadataProvider`] resolves the [`DiscId`] of a disc to its
+//! [`DiscMetadata`].  [`MusicBrainz`] asks the MusicBrainz web service and
+//! fetches the front cover from the Cover Art Archive.  Both base URLs are
+//! configurable, so a mirror or a local stand-in can take their place.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{Error, anyhow};
+use reqwest::StatusCode;
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::cd_audio::DiscId;
+
+/// Default base URL of the MusicBrainz web service.
+pub const MUSICBRAINZ_URL: &str = "https://musicbrainz.org";
+
+/// Default base URL of the Cover Art Archive.
+pub const COVER_ART_ARCHIVE_URL: &str = "https://coverartarchive.org";
+
+/// MusicBrainz asks every client to identify itself.
+const USER_AGENT: &str = concat!(
+    "homeplayer/",
+    env!("CARGO_PKG_VERSION"),
+    " ( https://github.com/kayhannay/homeplayer )"
+);
+
+/// Timeout for a single request to the web service or the cover archive.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// What a database knows about a disc.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiscMetadata {
+    pub artist: String,
+    pub album: String,
+    /// Track titles by track number.
+    pub tracks: BTreeMap<u8, String>,
+    /// The front cover image as downloaded (JPEG or PNG).
+    pub cover: Option<Vec<u8>>,
+}
+
+/// Resolves disc IDs to album, artist and track titles.
+pub trait MetadataProvider: Send + Sync {
+    /// Look the disc up.  `Ok(None)` means the database doesn't know it.
+    fn lookup(&self, id: &DiscId) -> Result<Option<DiscMetadata>, Error>;
+}
+
+/// Looks discs up in the MusicBrainz database.
+pub struct MusicBrainz {
+    base_url: String,
+    cover_art_url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl MusicBrainz {
+    /// Create a provider for the web service at `base_url` and the cover
+    /// archive at `cover_art_url`, e.g. [`MUSICBRAINZ_URL`] and
+    /// [`COVER_ART_ARCHIVE_URL`].
+    pub fn new(base_url: &str, cover_art_url: &str) -> Result<Self, Error> {
+        let client = reqwest::blocking::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            cover_art_url: cover_art_url.trim_end_matches('/').to_string(),
+            client,
+        })
+    }
+
+    fn download_cover(&self, release_id: &str) -> Result<Vec<u8>, Error> {
+        let url = format!("{}/release/{release_id}/front-500", self.cover_art_url);
+        let bytes = self.client.get(&url).send()?.error_for_status()?.bytes()?;
+        Ok(bytes.to_vec())
+    }
+}
+
+impl MetadataProvider for MusicBrainz {
+    fn lookup(&self, id: &DiscId) -> Result<Option<DiscMetadata>, Error> {
+        let url = format!(
+            "{}/ws/2/discid/{}?inc=artist-credits+recordings&fmt=json",
+            self.base_url, id.musicbrainz
+        );
+        debug!("Looking up disc at {url}");
+        let response = self.client.get(&url).send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let json: Value = serde_json::from_str(&response.error_for_status()?.text()?)?;
+        let Some(release) = json["releases"].as_array().and_then(|r| r.first()) else {
+            return Ok(None);
+        };
+        let mut metadata = parse_release(release, &id.musicbrainz)?;
+        if release["cover-art-archive"]["front"].as_bool() == Some(true)
+            && let Some(release_id) = release["id"].as_str()
+        {
+            match self.download_cover(release_id) {
+                Ok(cover) => metadata.cover = Some(cover),
+                Err(e) => warn!("Could not download cover of release {release_id}: {e}"),
+            }
+        }
+        Ok(Some(metadata))
+    }
+}
+
+/// Extract the metadata from a MusicBrainz release.  Of a release with
+/// several discs, the titles of the medium with the given disc ID are taken.
+fn parse_release(release: &Value, disc_id: &str) -> Result<DiscMetadata, Error> {
+    let media = release["media"]
+        .as_array()
+        .ok_or_else(|| anyhow!("Release without media"))?;
+    let has_disc = |medium: &&Value| {
+        medium["discs"]
+            .as_array()
+            .is_some_and(|discs| discs.iter().any(|disc| disc["id"] == disc_id))
+    };
+    let medium = media
+        .iter()
+        .find(has_disc)
+        .or_else(|| media.first())
+        .ok_or_else(|| anyhow!("Release without media"))?;
+    let tracks = medium["tracks"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|track| {
+            let number = u8::try_from(track["position"].as_u64()?).ok()?;
+            Some((number, track["title"].as_str()?.to_string()))
+        })
+        .collect();
+    Ok(DiscMetadata {
+        artist: artist_credit(&release["artist-credit"]),
+        album: release["title"].as_str().unwrap_or_default().to_string(),
+        tracks,
+        cover: None,
+    })
+}
+
+/// Join an artist credit like "Simon & Garfunkel" from its parts.
+fn artist_credit(credit: &Value) -> String {
+    credit
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|part| {
+            format!(
+                "{}{}",
+                part["name"].as_str().unwrap_or_default(),
+                part["joinphrase"].as_str().unwrap_or_default()
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    fn release() -> Value {
+        json!({
+            "id": "0d5c1a8f",
+            "title": "Bridge over Troubled Water",
+            "artist-credit": [
+                {"name": "Simon", "joinphrase": " & "},
+                {"name": "Garfunkel", "joinphrase": ""}
+            ],
+            "cover-art-archive": {"front": false},
+            "media": [
+                {"discs": [{"id": "other"}], "tracks": [{"position": 1, "title": "Wrong"}]},
+                {"discs": [{"id": "disc-id"}], "tracks": [
+                    {"position": 1, "title": "Bridge over Troubled Water"},
+                    {"position": 2, "title": "El Condor Pasa"}
+                ]}
+            ]
+        })
+    }
+
+    #[test]
+    fn test_parse_release_takes_medium_of_disc() {
+        let metadata = parse_release(&release(), "disc-id").unwrap();
+        assert_eq!(metadata.artist, "Simon & Garfunkel");
+        assert_eq!(metadata.album, "Bridge over Troubled Water");
+        assert_eq!(metadata.tracks[&2], "El Condor Pasa");
+        assert_eq!(metadata.tracks.len(), 2);
+    }
+
+    /// Serve `responses` (status line, body) to one request each.
+    fn serve(responses: Vec<(&'static str, String)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    fn disc_id() -> DiscId {
+        DiscId {
+            musicbrainz: "disc-id".to_string(),
+            freedb: "2c04f606".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_lookup_at_configured_url() {
+        let body = json!({"releases": [release()]}).to_string();
+        let url = serve(vec![("200 OK", body)]);
+        let provider = MusicBrainz::new(&url, &url).unwrap();
+        let metadata = provider.lookup(&disc_id()).unwrap().unwrap();
+        assert_eq!(metadata.tracks[&1], "Bridge over Troubled Water");
+        assert_eq!(metadata.cover, None);
+    }
+
+    #[test]
+    fn test_lookup_unknown_disc() {
+        let url = serve(vec![("404 Not Found", String::new())]);
+        let provider = MusicBrainz::new(&url, &url).unwrap();
+        assert_eq!(provider.lookup(&disc_id()).unwrap(), None);
+    }
+}
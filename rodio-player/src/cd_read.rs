@@ -0,0 +1,482 @@
+//! Error-correcting reads of audio sectors, in the spirit of cdparanoia.
+//!
+//! Audio CDs have no sector addresses a drive can seek to exactly, so a read
+//! may start a few samples early or late ("jitter"), and scratches make reads
+//! fail.  Every read after the first of a track therefore starts one sector
+//! early, and the overlap is matched against the end of the audio delivered
+//! before, which puts the new data in its exact place.  Failed and unmatched
+//! reads are repeated; sectors that still can't be read are interpolated
+//! from their neighbours if the gap is short, and muted otherwise, so a
+//! scratch costs a moment of audio instead of the rest of the track.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::Error;
+use tracing::{debug, warn};
+
+use crate::cd_audio::SAMPLES_PER_SECTOR;
+
+/// How often a failed or unmatched read is repeated by default.
+pub const DEFAULT_READ_RETRIES: u32 = 5;
+
+/// Sectors read at a time: about a third of a second of audio.
+const SECTORS_PER_READ: i32 = 25;
+
+/// Sectors read again before the new ones, to find the jitter in.
+const OVERLAP_SECTORS: i32 = 1;
+
+/// Samples at the end of the delivered audio that are searched for in the
+/// overlap.
+const MATCH_SAMPLES: usize = 512;
+
+/// Largest jitter that is corrected, in samples (150 stereo frames).
+const MAX_JITTER_SAMPLES: usize = 300;
+
+/// Longest gap of unreadable sectors that is interpolated; longer ones are
+/// muted.
+const MAX_INTERPOLATED_SECTORS: usize = 3;
+
+/// Reads raw audio sectors: `count` sectors of 2352 bytes from `lba` on.
+pub(crate) trait SectorReader {
+    fn read_sectors(&mut self, lba: i32, count: i32, buf: &mut [u8]) -> Result<(), Error>;
+}
+
+/// Read problems of a disc, counted since it was inserted.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CdReadStats {
+    /// Reads repeated because they failed or the overlap didn't match.
+    pub rereads: u32,
+    /// Reads that started a few samples off and were put in place.
+    pub jitter_corrections: u32,
+    /// Unreadable sectors filled in from their neighbours.
+    pub interpolated_sectors: u32,
+    /// Unreadable sectors replaced by silence.
+    pub muted_sectors: u32,
+}
+
+impl CdReadStats {
+    /// Whether the disc was read without any problem.
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// How audio sectors are read, and where read problems are counted.
+#[derive(Clone, Debug)]
+pub struct CdReadSettings {
+    /// How often a failed or unmatched read is repeated before the sectors
+    /// are read one by one and the unreadable ones concealed.
+    pub retries: u32,
+    pub stats: Arc<Mutex<CdReadStats>>,
+}
+
+impl Default for CdReadSettings {
+    fn default() -> Self {
+        Self {
+            retries: DEFAULT_READ_RETRIES,
+            stats: Arc::default(),
+        }
+    }
+}
+
+/// Reads the sectors of a track, correcting jitter and concealing errors.
+pub(crate) struct CorrectingReader<R: SectorReader> {
+    reader: R,
+    /// Next sector to deliver.
+    next_lba: i32,
+    /// Past-the-end sector of the track.
+    end_lba: i32,
+    settings: CdReadSettings,
+    /// The last samples delivered, matched against the overlap of the next
+    /// read.  Empty when there is nothing to match against.
+    tail: Vec<i16>,
+    /// Reusable buffer for raw sector reads.
+    raw: Vec<u8>,
+}
+
+impl<R: SectorReader> CorrectingReader<R> {
+    pub fn new(reader: R, start_lba: i32, end_lba: i32, settings: CdReadSettings) -> Self {
+        Self {
+            reader,
+            next_lba: start_lba,
+            end_lba,
+            settings,
+            tail: Vec::with_capacity(MATCH_SAMPLES),
+            raw: Vec::new(),
+        }
+    }
+
+    /// Replace `out` with the samples of the next sectors.  Returns `false`
+    /// at the end of the track.
+    pub fn read(&mut self, out: &mut Vec<i16>) -> bool {
+        if self.next_lba >= self.end_lba {
+            return false;
+        }
+        let count = (self.end_lba - self.next_lba).min(SECTORS_PER_READ);
+        out.clear();
+        if self.read_matched(count, out) {
+            self.tail.clear();
+            self.tail
+                .extend_from_slice(&out[out.len().saturating_sub(MATCH_SAMPLES)..]);
+        } else {
+            self.read_sector_by_sector(count, out);
+        }
+        self.next_lba += count;
+        true
+    }
+
+    /// Continue reading at `lba`.  There is nothing to match the first read
+    /// there against.
+    pub fn seek(&mut self, lba: i32) {
+        self.next_lba = lba.min(self.end_lba);
+        self.tail.clear();
+    }
+
+    /// Read `count` sectors in one go, placed by the overlap with the audio
+    /// delivered before.  Returns `false` if no read succeeded.
+    fn read_matched(&mut self, count: i32, out: &mut Vec<i16>) -> bool {
+        let overlap = if self.tail.is_empty() {
+            0
+        } else {
+            OVERLAP_SECTORS
+        };
+        let start = self.next_lba - overlap;
+        let sectors = overlap + count;
+        let wanted = count as usize * SAMPLES_PER_SECTOR;
+        let at_end = self.next_lba + count == self.end_lba;
+        let mut samples = Vec::new();
+
+        for attempt in 0..=self.settings.retries {
+            if attempt > 0 {
+                self.stats().rereads += 1;
+            }
+            // One more sector after the new ones, for reads that start
+            // early.  After the last track that is the lead-out, which
+            // can't be read.
+            let result = match self.read_raw(start, sectors + 1, &mut samples) {
+                Err(_) if at_end => self.read_raw(start, sectors, &mut samples),
+                result => result,
+            };
+            if let Err(e) = result {
+                debug!("Read of LBA {start} ({sectors} sectors) failed: {e}");
+                continue;
+            }
+            if overlap == 0 {
+                out.extend_from_slice(&samples[..wanted]);
+                return true;
+            }
+            // Where the tail would be without jitter.
+            let expected = overlap as usize * SAMPLES_PER_SECTOR - self.tail.len();
+            let Some(found) = self.find_tail(&samples, expected) else {
+                debug!("Overlap at LBA {start} doesn't match");
+                if attempt == self.settings.retries {
+                    // Keep going without jitter correction rather than
+                    // dropping audio that could be read.
+                    warn!("Could not match overlap at LBA {start}, reading on unverified");
+                    out.extend_from_slice(&samples[expected + self.tail.len()..][..wanted]);
+                    return true;
+                }
+                continue;
+            };
+            if found != expected {
+                debug!(
+                    "Jitter of {} samples at LBA {}",
+                    expected as isize - found as isize,
+                    self.next_lba
+                );
+                self.stats().jitter_corrections += 1;
+            }
+            let new = &samples[found + self.tail.len()..];
+            let available = new.len().min(wanted);
+            out.extend_from_slice(&new[..available]);
+            // A read that started early at the end of the disc.
+            out.resize(wanted, 0);
+            return true;
+        }
+        false
+    }
+
+    /// Position of the tail in `samples` closest to `expected`, within the
+    /// largest jitter that is corrected.
+    fn find_tail(&self, samples: &[i16], expected: usize) -> Option<usize> {
+        let matches = |pos: usize| {
+            samples
+                .get(pos..pos + self.tail.len())
+                .is_some_and(|window| window == self.tail.as_slice())
+        };
+        // Whole stereo frames only, nearest first.
+        (0..=MAX_JITTER_SAMPLES).step_by(2).find_map(|offset| {
+            [expected.checked_sub(offset), Some(expected + offset)]
+                .into_iter()
+                .flatten()
+                .find(|&pos| matches(pos))
+        })
+    }
+
+    /// Read `count` sectors one at a time, and conceal those that can't be
+    /// read at all.
+    fn read_sector_by_sector(&mut self, count: i32, out: &mut Vec<i16>) {
+        warn!(
+            "Reading LBA {}–{} sector by sector",
+            self.next_lba,
+            self.next_lba + count
+        );
+        let mut samples = Vec::new();
+        let mut bad = Vec::with_capacity(count as usize);
+        for lba in self.next_lba..self.next_lba + count {
+            let mut ok = false;
+            for attempt in 0..=self.settings.retries {
+                if attempt > 0 {
+                    self.stats().rereads += 1;
+                }
+                if self.read_raw(lba, 1, &mut samples).is_ok() {
+                    ok = true;
+                    break;
+                }
+            }
+            if ok {
+                out.extend_from_slice(&samples);
+            } else {
+                out.resize(out.len() + SAMPLES_PER_SECTOR, 0);
+            }
+            bad.push(!ok);
+        }
+        let previous = self.tail.rchunks_exact(2).next().map(|f| [f[0], f[1]]);
+        let (interpolated, muted) = conceal(out, &bad, previous);
+        if interpolated + muted > 0 {
+            warn!(
+                "Could not read {} sectors from LBA {}: {interpolated} interpolated, {muted} muted",
+                interpolated + muted,
+                self.next_lba
+            );
+        }
+        let mut stats = self.stats();
+        stats.interpolated_sectors += interpolated;
+        stats.muted_sectors += muted;
+        drop(stats);
+        // Single sectors aren't placed by an overlap, so the next read can't
+        // be matched against them either.
+        self.tail.clear();
+    }
+
+    fn read_raw(&mut self, lba: i32, count: i32, samples: &mut Vec<i16>) -> Result<(), Error> {
+        let bytes = count as usize * SAMPLES_PER_SECTOR * 2;
+        self.raw.resize(bytes, 0);
+        self.reader.read_sectors(lba, count, &mut self.raw)?;
+        // Raw audio is little-endian.
+        samples.clear();
+        samples.extend(
+            self.raw
+                .chunks_exact(2)
+                .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])),
+        );
+        Ok(())
+    }
+
+    fn stats(&self) -> std::sync::MutexGuard<'_, CdReadStats> {
+        self.settings.stats.lock().unwrap()
+    }
+}
+
+/// Fill in the sectors of `samples` marked in `bad`: short gaps are
+/// interpolated between the frames around them, long ones and those at the
+/// end are muted.  `previous` is the frame before `samples`.  Returns the
+/// numbers of interpolated and muted sectors.
+fn conceal(samples: &mut [i16], bad: &[bool], previous: Option<[i16; 2]>) -> (u32, u32) {
+    let (mut interpolated, mut muted) = (0, 0);
+    let mut sector = 0;
+    while sector < bad.len() {
+        if !bad[sector] {
+            sector += 1;
+            continue;
+        }
+        let first = sector;
+        while sector < bad.len() && bad[sector] {
+            sector += 1;
+        }
+        let gap = first * SAMPLES_PER_SECTOR..sector * SAMPLES_PER_SECTOR;
+        let before = if first == 0 {
+            previous
+        } else {
+            Some([samples[gap.start - 2], samples[gap.start - 1]])
+        };
+        let after = samples.get(gap.end..gap.end + 2).map(|f| [f[0], f[1]]);
+        let len = sector - first;
+        match (before, after) {
+            (Some(before), Some(after)) if len <= MAX_INTERPOLATED_SECTORS => {
+                let frames = gap.len() / 2;
+                for (i, frame) in samples[gap].chunks_exact_mut(2).enumerate() {
+                    let t = (i + 1) as f32 / (frames + 1) as f32;
+                    for channel in 0..2 {
+                        let (a, b) = (before[channel] as f32, after[channel] as f32);
+                        frame[channel] = (a + (b - a) * t).round() as i16;
+                    }
+                }
+                interpolated += len as u32;
+            }
+            _ => {
+                samples[gap].fill(0);
+                muted += len as u32;
+            }
+        }
+    }
+    (interpolated, muted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::collections::HashMap;
+
+    const SECTORS: i32 = 100;
+
+    /// A drive reading a disc of noise.  Reads after the first start
+    /// `jitter` samples late, and sectors fail as often as `failures` says
+    /// (`u32::MAX` for always).
+    struct FakeDrive {
+        disc: Vec<i16>,
+        jitter: isize,
+        failures: HashMap<i32, u32>,
+        reads: usize,
+    }
+
+    impl FakeDrive {
+        fn new() -> Self {
+            let mut seed = 7u32;
+            let disc = (0..SECTORS as usize * SAMPLES_PER_SECTOR)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    (seed >> 16) as i16
+                })
+                .collect();
+            Self {
+                disc,
+                jitter: 0,
+                failures: HashMap::new(),
+                reads: 0,
+            }
+        }
+    }
+
+    impl SectorReader for FakeDrive {
+        fn read_sectors(&mut self, lba: i32, count: i32, buf: &mut [u8]) -> Result<(), Error> {
+            for sector in lba..lba + count {
+                if let Some(left) = self.failures.get_mut(&sector)
+                    && *left > 0
+                {
+                    *left -= 1;
+                    return Err(anyhow!("Read error at sector {sector}"));
+                }
+            }
+            let jitter = if self.reads == 0 { 0 } else { self.jitter };
+            self.reads += 1;
+            let start = (lba as usize * SAMPLES_PER_SECTOR) as isize + jitter;
+            for (i, bytes) in buf.chunks_exact_mut(2).enumerate() {
+                let sample = usize::try_from(start + i as isize)
+                    .ok()
+                    .and_then(|pos| self.disc.get(pos))
+                    .copied()
+                    .unwrap_or(0);
+                bytes.copy_from_slice(&sample.to_le_bytes());
+            }
+            Ok(())
+        }
+    }
+
+    /// Read sectors `start..end` and return the samples and the stats.
+    fn read_all(drive: FakeDrive, start: i32, end: i32) -> (Vec<i16>, Vec<i16>, CdReadStats) {
+        let expected = drive.disc
+            [start as usize * SAMPLES_PER_SECTOR..end as usize * SAMPLES_PER_SECTOR]
+            .to_vec();
+        let settings = CdReadSettings::default();
+        let mut reader = CorrectingReader::new(drive, start, end, settings.clone());
+        let (mut all, mut chunk) = (Vec::new(), Vec::new());
+        while reader.read(&mut chunk) {
+            all.extend_from_slice(&chunk);
+        }
+        let stats = *settings.stats.lock().unwrap();
+        (all, expected, stats)
+    }
+
+    #[test]
+    fn test_clean_read() {
+        let (samples, expected, stats) = read_all(FakeDrive::new(), 3, 90);
+        assert_eq!(samples, expected);
+        assert!(stats.is_clean());
+    }
+
+    #[test]
+    fn test_jitter_is_corrected() {
+        for jitter in [-46, 6, 200] {
+            let mut drive = FakeDrive::new();
+            drive.jitter = jitter;
+            let (samples, expected, stats) = read_all(drive, 3, 90);
+            assert_eq!(samples, expected, "jitter {jitter}");
+            assert_eq!(stats.jitter_corrections, 3, "jitter {jitter}");
+        }
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut drive = FakeDrive::new();
+        drive.jitter = 6;
+        let disc = drive.disc.clone();
+        let mut reader = CorrectingReader::new(drive, 3, 90, CdReadSettings::default());
+        let mut chunk = Vec::new();
+        assert!(reader.read(&mut chunk));
+        reader.seek(60);
+        // The drive reads late now, with nothing to correct it by.
+        assert!(reader.read(&mut chunk));
+        let at = |lba: usize| lba * SAMPLES_PER_SECTOR;
+        assert_eq!(chunk[..], disc[at(60) + 6..at(85) + 6]);
+        // The next read is matched against that one again.
+        assert!(reader.read(&mut chunk));
+        assert_eq!(chunk[..], disc[at(85) + 6..at(90) + 6]);
+        reader.seek(95);
+        assert!(!reader.read(&mut chunk));
+    }
+
+    #[test]
+    fn test_failed_reads_are_repeated() {
+        let mut drive = FakeDrive::new();
+        drive.failures.insert(40, 2);
+        let (samples, expected, stats) = read_all(drive, 3, 90);
+        assert_eq!(samples, expected);
+        assert_eq!(stats.rereads, 2);
+        assert_eq!(stats.muted_sectors + stats.interpolated_sectors, 0);
+    }
+
+    #[test]
+    fn test_short_gap_is_interpolated() {
+        let mut drive = FakeDrive::new();
+        drive.failures.insert(40, u32::MAX);
+        let (samples, expected, stats) = read_all(drive, 3, 90);
+        assert_eq!(stats.interpolated_sectors, 1);
+        assert_eq!(stats.muted_sectors, 0);
+        let gap = (40 - 3) * SAMPLES_PER_SECTOR..(41 - 3) * SAMPLES_PER_SECTOR;
+        assert_eq!(samples.len(), expected.len());
+        assert_eq!(samples[..gap.start], expected[..gap.start]);
+        assert_eq!(samples[gap.end..], expected[gap.end..]);
+        // A straight line from the frame before the gap to the one after.
+        let (before, after) = (expected[gap.start - 2] as f32, expected[gap.end] as f32);
+        let frames = SAMPLES_PER_SECTOR / 2;
+        for (i, &sample) in samples[gap].iter().step_by(2).enumerate() {
+            let t = (i + 1) as f32 / (frames + 1) as f32;
+            assert_eq!(sample, (before + (after - before) * t).round() as i16);
+        }
+    }
+
+    #[test]
+    fn test_long_gap_is_muted() {
+        let mut drive = FakeDrive::new();
+        for sector in 40..45 {
+            drive.failures.insert(sector, u32::MAX);
+        }
+        let (samples, expected, stats) = read_all(drive, 3, 90);
+        assert_eq!(stats.muted_sectors, 5);
+        let gap = (40 - 3) * SAMPLES_PER_SECTOR..(45 - 3) * SAMPLES_PER_SECTOR;
+        assert!(samples[gap.clone()].iter().all(|&s| s == 0));
+        assert_eq!(samples[gap.end..], expected[gap.end..]);
+    }
+}
@@ -0,0 +1,216 @@
+//! Ripping audio CD tracks into lossless files.
+//!
+//! Tracks are read through [`CdTrackSource`](crate::cd_audio::CdTrackSource),
+//! like for playback, and written as FLAC or WAV.  The CRC32 of the PCM data
+//! of every track is returned, so a second read of the track can verify the
+//! rip: if both CRCs match, the drive read the same data twice.  Before the
+//! second read the drive cache is flushed, so the data really comes from
+//! the disc again.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Error, anyhow};
+use tracing::debug;
+
+use crate::cd_audio::{self, CdInfo, CdReadSettings, CdTrackInfo};
+use crate::flac::FlacWriter;
+
+/// Samples handled at a time: one second of CD audio.
+const CHUNK_SAMPLES: usize = 2 * 44_100;
+
+/// File format of ripped tracks.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RipFormat {
+    #[default]
+    Flac,
+    Wav,
+}
+
+impl RipFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RipFormat::Flac => "flac",
+            RipFormat::Wav => "wav",
+        }
+    }
+}
+
+/// Rip a track into a file at `path`.  Returns the CRC32 of the PCM data.
+///
+/// `progress` is called with the part of the track that is done.  Setting
+/// `cancel` stops the rip with an error; the incomplete file is removed.
+pub fn rip_track(
+    device: &str,
+    track: &CdTrackInfo,
+    read: &CdReadSettings,
+    path: &Path,
+    format: RipFormat,
+    cancel: &AtomicBool,
+    progress: impl FnMut(f32),
+) -> Result<u32, Error> {
+    let result = write_track(device, track, read, path, format, cancel, progress);
+    if result.is_err() {
+        let _ = std::fs::remove_file(path);
+    }
+    result
+}
+
+/// Read a track of `disc` again and return the CRC32 of its PCM data, to
+/// compare it with the one [`rip_track`] returned.  The track is read from
+/// the disc, not from the cache of the drive.
+pub fn read_track_crc(
+    device: &str,
+    disc: &CdInfo,
+    track: &CdTrackInfo,
+    read: &CdReadSettings,
+    cancel: &AtomicBool,
+    progress: impl FnMut(f32),
+) -> Result<u32, Error> {
+    cd_audio::flush_drive_cache(device, disc, track)?;
+    read_track(device, track, read, cancel, progress, |_| Ok(()))
+}
+
+fn write_track(
+    device: &str,
+    track: &CdTrackInfo,
+    read: &CdReadSettings,
+    path: &Path,
+    format: RipFormat,
+    cancel: &AtomicBool,
+    progress: impl FnMut(f32),
+) -> Result<u32, Error> {
+    debug!("Ripping track {} to {}", track.number, path.display());
+    let file = BufWriter::new(File::create(path)?);
+    let (channels, sample_rate) = (2, 44_100);
+    match format {
+        RipFormat::Flac => {
+            let mut writer = FlacWriter::new(file, channels, sample_rate)?;
+            let crc = read_track(device, track, read, cancel, progress, |chunk| {
+                writer.write(chunk)
+            })?;
+            writer.finish()?;
+            Ok(crc)
+        }
+        RipFormat::Wav => {
+            let mut writer = WavWriter::new(file, channels, sample_rate)?;
+            let crc = read_track(device, track, read, cancel, progress, |chunk| {
+                writer.write(chunk)
+            })?;
+            writer.finish()?;
+            Ok(crc)
+        }
+    }
+}
+
+/// Read the whole track, handing its samples to `output` chunk by chunk.
+/// Returns the CRC32 of the samples as little-endian bytes.
+fn read_track(
+    device: &str,
+    track: &CdTrackInfo,
+    read: &CdReadSettings,
+    cancel: &AtomicBool,
+    mut progress: impl FnMut(f32),
+    mut output: impl FnMut(&[i16]) -> io::Result<()>,
+) -> Result<u32, Error> {
+    let mut source = cd_audio::open_track(device, track, read)?;
+    let total = source.size_hint().0.max(1);
+    let mut hasher = crc32fast::Hasher::new();
+    let mut chunk = Vec::with_capacity(CHUNK_SAMPLES);
+    let mut done = 0;
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            return Err(anyhow!("Ripping cancelled"));
+        }
+        chunk.clear();
+        // The source has converted the samples to f32; this is exact.
+        chunk.extend(
+            source
+                .by_ref()
+                .take(CHUNK_SAMPLES)
+                .map(|sample| (sample * i16::MAX as f32).round() as i16),
+        );
+        if chunk.is_empty() {
+            break;
+        }
+        for sample in &chunk {
+            hasher.update(&sample.to_le_bytes());
+        }
+        output(&chunk)?;
+        done += chunk.len();
+        progress(done as f32 / total as f32);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Writes 16-bit PCM as a WAV file.
+struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_len: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    fn new(mut writer: W, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        let block_align = channels * 2;
+        writer.write_all(b"RIFF")?;
+        // File and data sizes are filled in by `finish`.
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            data_len: 0,
+        })
+    }
+
+    fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_len += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_wav_round_trip() {
+        let samples: Vec<i16> = (0..2000)
+            .map(|n| (n * 31 % 65_536 - 32_768) as i16)
+            .collect();
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 2, 44_100).unwrap();
+        writer.write(&samples).unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        assert_eq!(data.len(), 44 + samples.len() * 2);
+        let decoded: Vec<i16> = rodio::Decoder::new(Cursor::new(data))
+            .unwrap()
+            .map(|sample| (sample * 32_768.0).round() as i16)
+            .collect();
+        assert_eq!(decoded, samples);
+    }
+}
@@ -0,0 +1,126 @@
+//! Watching CD drives for discs being inserted and removed.
+//!
+//! A drive is asked for its status about once a second (the
+//! `CDROM_DRIVE_STATUS` ioctl), which works for every drive without needing
+//! udev.  Changes are reported as [`DiscEvent`]s to a callback running on the
+//! watcher thread.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+use tracing::{debug, info};
+
+use crate::cd_audio::{self, DriveStatus};
+
+/// How often the drive is asked for its status.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change of the disc in a drive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiscEvent {
+    /// A disc was already in the drive when watching started.
+    Found,
+    /// A disc was inserted and can be read.
+    Inserted,
+    /// The tray was opened or the disc removed.
+    Removed,
+}
+
+/// Watches one drive on a background thread until dropped.
+pub struct CdWatcher {
+    stop: Arc<AtomicBool>,
+}
+
+impl CdWatcher {
+    /// Start watching `device`.  `on_event` is called on the watcher thread.
+    pub fn start(device: &str, mut on_event: impl FnMut(DiscEvent) + Send + 'static) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let device = device.to_string();
+        let thread_stop = stop.clone();
+        let _ = spawn(move || {
+            debug!("Watching {device} for discs");
+            let mut tracker = DiscTracker::default();
+            while !thread_stop.load(Ordering::SeqCst) {
+                if let Some(event) = tracker.update(cd_audio::drive_status(&device)) {
+                    info!("{device}: {event:?}");
+                    on_event(event);
+                }
+                sleep(POLL_INTERVAL);
+            }
+            debug!("Stopped watching {device}");
+        });
+        Self { stop }
+    }
+}
+
+impl Drop for CdWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Turns the polled drive status into events.  A drive that is spinning up,
+/// or that gives no answer, keeps its previous state, so a disc is only
+/// reported once it can be read and a failed poll does not eject it.
+#[derive(Default)]
+struct DiscTracker {
+    /// Whether a disc is in the drive; `None` before the first answer.
+    present: Option<bool>,
+}
+
+impl DiscTracker {
+    fn update(&mut self, status: DriveStatus) -> Option<DiscEvent> {
+        let present = match status {
+            DriveStatus::NotReady | DriveStatus::NoInfo => return None,
+            DriveStatus::DiscOk => true,
+            DriveStatus::NoDisc | DriveStatus::TrayOpen => false,
+        };
+        let event = match (self.present, present) {
+            (None, true) => Some(DiscEvent::Found),
+            (Some(false), true) => Some(DiscEvent::Inserted),
+            (Some(true), false) => Some(DiscEvent::Removed),
+            _ => None,
+        };
+        self.present = Some(present);
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(statuses: &[DriveStatus]) -> Vec<DiscEvent> {
+        let mut tracker = DiscTracker::default();
+        statuses.iter().filter_map(|s| tracker.update(*s)).collect()
+    }
+
+    #[test]
+    fn test_disc_in_drive_at_start() {
+        use DriveStatus::*;
+        assert_eq!(events(&[DiscOk, DiscOk]), vec![DiscEvent::Found]);
+        assert_eq!(events(&[NotReady, DiscOk]), vec![DiscEvent::Found]);
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        use DriveStatus::*;
+        assert_eq!(
+            events(&[
+                NoDisc, TrayOpen, NoDisc, NotReady, NotReady, DiscOk, DiscOk, TrayOpen, TrayOpen,
+                NoDisc
+            ]),
+            vec![DiscEvent::Inserted, DiscEvent::Removed]
+        );
+    }
+
+    #[test]
+    fn test_no_info_keeps_state() {
+        use DriveStatus::*;
+        assert_eq!(events(&[NoInfo, NoInfo]), vec![]);
+        assert_eq!(events(&[DiscOk, NoInfo, DiscOk]), vec![DiscEvent::Found]);
+        assert_eq!(events(&[NoInfo, DiscOk]), vec![DiscEvent::Found]);
+    }
+}
@@ -0,0 +1,197 @@
+//! Dynamic range compression for night mode.
+//!
+//! A [`Compressor`] on every music source turns loud passages down and,
+//! with its makeup gain, quiet ones up, so that dialogue in audio books
+//! stays understandable at low volume without explosions or choruses waking
+//! anybody up.  A limiter at the end catches the peaks the compressor is too
+//! slow for.  It works ahead of the player volume, so the music is never
+//! louder than the volume allows.  When night mode is off, the samples pass
+//! unchanged.
+
+use std::num::NonZero;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use rodio::Source;
+
+/// Level above which the compressor turns the music down, in dBFS.
+const THRESHOLD_DB: f32 = -30.0;
+
+/// Compression ratio above the threshold.
+const RATIO: f32 = 4.0;
+
+/// Gain added after compression, so quiet passages get louder.
+const MAKEUP_DB: f32 = 10.0;
+
+/// How fast the compressor reacts to a louder signal.
+const ATTACK: Duration = Duration::from_millis(10);
+
+/// How fast the compressor recovers when the signal gets quieter.
+const RELEASE: Duration = Duration::from_millis(300);
+
+/// Peaks are limited to this level, in dBFS.
+const LIMITER_CEILING_DB: f32 = -1.0;
+
+/// Switches the compressor on and off while it runs.
+#[derive(Clone, Default)]
+pub(crate) struct DynamicsControl(Arc<AtomicBool>);
+
+impl DynamicsControl {
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::SeqCst);
+    }
+
+    fn enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Stereo-linked feed-forward compressor with a limiter.
+pub(crate) struct Compressor<S> {
+    source: S,
+    control: DynamicsControl,
+    channels: usize,
+    /// Current frame, already scaled.
+    frame: Vec<f32>,
+    /// Next sample of `frame` to return.
+    frame_pos: usize,
+    /// Smoothed gain reduction in dB.
+    reduction_db: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+}
+
+impl<S: Source> Compressor<S> {
+    pub(crate) fn new(source: S, control: DynamicsControl) -> Self {
+        let channels = source.channels().get() as usize;
+        let frame_rate = source.sample_rate().get() as f32;
+        let coeff = |time: Duration| (-1.0 / (time.as_secs_f32() * frame_rate)).exp();
+        Self {
+            source,
+            control,
+            channels,
+            frame: Vec::with_capacity(channels),
+            frame_pos: 0,
+            reduction_db: 0.0,
+            attack_coeff: coeff(ATTACK),
+            release_coeff: coeff(RELEASE),
+        }
+    }
+
+    /// Gain for a frame with the given peak level.
+    fn frame_gain(&mut self, peak: f32) -> f32 {
+        let level_db = 20.0 * peak.max(1e-6).log10();
+        let target = (level_db - THRESHOLD_DB).max(0.0) * (1.0 - 1.0 / RATIO);
+        let coeff = if target > self.reduction_db {
+            self.attack_coeff
+        } else {
+            self.release_coeff
+        };
+        self.reduction_db = coeff * self.reduction_db + (1.0 - coeff) * target;
+        let gain = 10f32.powf((MAKEUP_DB - self.reduction_db) / 20.0);
+        let ceiling = 10f32.powf(LIMITER_CEILING_DB / 20.0);
+        if peak * gain > ceiling {
+            ceiling / peak
+        } else {
+            gain
+        }
+    }
+}
+
+impl<S: Source> Iterator for Compressor<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.frame_pos < self.frame.len() {
+            self.frame_pos += 1;
+            return Some(self.frame[self.frame_pos - 1]);
+        }
+        if !self.control.enabled() {
+            self.reduction_db = 0.0;
+            return self.source.next();
+        }
+        // The gain depends on all channels of a frame, so read it at once.
+        self.frame.clear();
+        self.frame_pos = 1;
+        for _ in 0..self.channels {
+            match self.source.next() {
+                Some(sample) => self.frame.push(sample),
+                None => break,
+            }
+        }
+        let peak = self.frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let gain = self.frame_gain(peak);
+        self.frame.iter_mut().for_each(|sample| *sample *= gain);
+        self.frame.first().copied()
+    }
+}
+
+impl<S: Source> Source for Compressor<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.source.current_span_len()
+    }
+
+    fn channels(&self) -> NonZero<u16> {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> NonZero<u32> {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn constant(level: f32, frames: usize) -> SamplesBuffer {
+        SamplesBuffer::new(
+            NonZero::new(2).unwrap(),
+            NonZero::new(44_100).unwrap(),
+            vec![level; frames * 2],
+        )
+    }
+
+    /// Level after the compressor has settled, in dBFS.
+    fn settled_level_db(level: f32) -> f32 {
+        let control = DynamicsControl::default();
+        control.set_enabled(true);
+        let compressor = Compressor::new(constant(level, 44_100), control);
+        20.0 * compressor.last().unwrap().abs().log10()
+    }
+
+    #[test]
+    fn test_compressor_narrows_dynamic_range() {
+        let quiet = settled_level_db(0.01); // -40 dBFS
+        let loud = settled_level_db(0.5); // -6 dBFS
+        assert!(quiet > -40.0 + MAKEUP_DB - 0.5);
+        assert!(loud < -6.0);
+        assert!(loud - quiet < 34.0 / 2.0);
+    }
+
+    #[test]
+    fn test_limiter_keeps_peaks_below_ceiling() {
+        let control = DynamicsControl::default();
+        control.set_enabled(true);
+        let ceiling = 10f32.powf(LIMITER_CEILING_DB / 20.0);
+        // A sudden peak after silence, before the compressor reacts.
+        let source = SamplesBuffer::new(
+            NonZero::new(2).unwrap(),
+            NonZero::new(44_100).unwrap(),
+            [vec![0.0; 200], vec![1.0; 20]].concat(),
+        );
+        assert!(Compressor::new(source, control).all(|sample| sample.abs() <= ceiling + 1e-6));
+    }
+
+    #[test]
+    fn test_disabled_compressor_passes_samples() {
+        let mut compressor = Compressor::new(constant(0.25, 10), DynamicsControl::default());
+        assert!(compressor.all(|sample| sample == 0.25));
+    }
+}
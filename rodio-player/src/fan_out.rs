@@ -0,0 +1,275 @@
+//! Fan-out of the mixed audio to several outputs at once.
+//!
+//! Every output gets its own [`Branch`], a source that yields the same
+//! samples as all other branches.  The samples are pulled from the player's
+//! mixer once and buffered until every branch has read them.  Sound cards
+//! never run at exactly the same speed, so the fastest branch drives the
+//! mixer and a branch that falls too far behind (or stopped reading because
+//! its device is gone) skips ahead instead of letting the buffer grow.
+//!
+//! Branches read [`BLOCK_SAMPLES`] samples at a time, so the shared state is
+//! locked once per block rather than once per sample.
+
+use std::collections::VecDeque;
+use std::num::NonZero;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::output::{BLOCK_SAMPLES, CHANNELS, SAMPLE_RATE};
+
+/// How far a branch may fall behind the fastest one before it skips ahead.
+const MAX_LAG: Duration = Duration::from_secs(1);
+
+fn max_lag_samples() -> usize {
+    (MAX_LAG.as_secs_f64() * SAMPLE_RATE as f64) as usize * CHANNELS as usize
+}
+
+struct FanOutState {
+    source: Box<dyn Iterator<Item = f32> + Send>,
+    /// Samples not yet read by every branch.
+    buffer: VecDeque<f32>,
+    /// Absolute sample index of `buffer[0]`.
+    start: u64,
+    /// Absolute sample index of the next sample of each branch, indexed by
+    /// branch id.  `None` for ids of dropped branches, which are reused.
+    positions: Vec<Option<u64>>,
+}
+
+impl FanOutState {
+    fn end(&self) -> u64 {
+        self.start + self.buffer.len() as u64
+    }
+
+    /// Drop the samples every branch has read, and skip lagging branches
+    /// ahead if the buffer gets too long.
+    fn trim(&mut self) {
+        let excess = self.buffer.len().saturating_sub(max_lag_samples());
+        if excess > 0 {
+            self.buffer.drain(..excess);
+            self.start += excess as u64;
+            // Keep the channels in order: branches restart on a frame.
+            let first_frame = self.start.next_multiple_of(CHANNELS as u64);
+            for position in self.positions.iter_mut().flatten() {
+                if *position < self.start {
+                    *position = first_frame;
+                }
+            }
+        }
+        let Some(oldest) = self.positions.iter().flatten().min().copied() else {
+            self.start = self.end();
+            self.buffer.clear();
+            return;
+        };
+        let read_by_all = (oldest.saturating_sub(self.start) as usize).min(self.buffer.len());
+        if read_by_all > 0 {
+            self.buffer.drain(..read_by_all);
+            self.start += read_by_all as u64;
+        }
+    }
+}
+
+/// Distributes one source to any number of [`Branch`]es.
+#[derive(Clone)]
+pub(crate) struct FanOut(Arc<Mutex<FanOutState>>);
+
+impl FanOut {
+    pub(crate) fn new<S>(source: S) -> Self
+    where
+        S: Iterator<Item = f32> + Send + 'static,
+    {
+        Self(Arc::new(Mutex::new(FanOutState {
+            source: Box::new(source),
+            buffer: VecDeque::new(),
+            start: 0,
+            positions: Vec::new(),
+        })))
+    }
+
+    /// A new branch that starts at the current playback position, `delay`
+    /// later than the others and with its level changed by `trim_db`.
+    pub(crate) fn branch(&self, trim_db: f32, delay: Duration) -> Branch {
+        let mut state = self.0.lock().unwrap();
+        // Sample indices count the samples pulled from the source, so finish
+        // the current frame to start the branch on a left channel sample.
+        while !state.end().is_multiple_of(CHANNELS as u64) {
+            let sample = state.source.next().unwrap_or(0.0);
+            state.buffer.push_back(sample);
+        }
+        let position = Some(state.end());
+        let id = match state.positions.iter().position(Option::is_none) {
+            Some(id) => {
+                state.positions[id] = position;
+                id
+            }
+            None => {
+                state.positions.push(position);
+                state.positions.len() - 1
+            }
+        };
+        let delay_frames = (delay.as_secs_f64() * SAMPLE_RATE as f64).round() as usize;
+        Branch {
+            id,
+            state: Arc::clone(&self.0),
+            gain: 10f32.powf(trim_db / 20.0),
+            delay_samples: delay_frames * CHANNELS as usize,
+            block: Vec::with_capacity(BLOCK_SAMPLES),
+            block_pos: 0,
+        }
+    }
+}
+
+/// One output of a [`FanOut`].  Never ends; plays silence while there is
+/// nothing to play.
+pub(crate) struct Branch {
+    id: usize,
+    state: Arc<Mutex<FanOutState>>,
+    gain: f32,
+    /// Silence still to be played before the first sample.
+    delay_samples: usize,
+    /// The samples read from the shared buffer, with the gain applied.
+    block: Vec<f32>,
+    /// Index of the next sample in `block`.
+    block_pos: usize,
+}
+
+impl Branch {
+    /// Read the next block from the shared buffer, pulling from the source
+    /// as far as needed.
+    fn fill(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        let position = state.positions[self.id].expect("position of a live branch");
+        let end = position + BLOCK_SAMPLES as u64;
+        while state.end() < end {
+            let sample = state.source.next().unwrap_or(0.0);
+            state.buffer.push_back(sample);
+        }
+        let first = (position - state.start) as usize;
+        self.block.clear();
+        self.block.extend(
+            state
+                .buffer
+                .range(first..first + BLOCK_SAMPLES)
+                .map(|sample| sample * self.gain),
+        );
+        self.block_pos = 0;
+        state.positions[self.id] = Some(end);
+        state.trim();
+    }
+}
+
+impl Iterator for Branch {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.delay_samples > 0 {
+            self.delay_samples -= 1;
+            return Some(0.0);
+        }
+        if self.block_pos == self.block.len() {
+            self.fill();
+        }
+        let sample = self.block[self.block_pos];
+        self.block_pos += 1;
+        Some(sample)
+    }
+}
+
+impl Source for Branch {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> NonZero<u16> {
+        NonZero::new(CHANNELS).unwrap()
+    }
+
+    fn sample_rate(&self) -> NonZero<u32> {
+        NonZero::new(SAMPLE_RATE).unwrap()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Drop for Branch {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.positions[self.id] = None;
+        state.trim();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter() -> impl Iterator<Item = f32> + Send {
+        (1..).map(|n| n as f32)
+    }
+
+    #[test]
+    fn test_branches_get_the_same_samples() {
+        let fan_out = FanOut::new(counter());
+        let mut a = fan_out.branch(0.0, Duration::ZERO);
+        let mut b = fan_out.branch(0.0, Duration::ZERO);
+        let from_a: Vec<f32> = a.by_ref().take(6).collect();
+        let from_b: Vec<f32> = b.by_ref().take(4).collect();
+        assert_eq!(from_a, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(from_b, [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(a.next(), Some(7.0));
+        assert_eq!(b.next(), Some(5.0));
+    }
+
+    #[test]
+    fn test_branch_trim_and_delay() {
+        let fan_out = FanOut::new(counter());
+        let delay = Duration::from_secs_f64(2.0 / SAMPLE_RATE as f64);
+        let mut branch = fan_out.branch(-20.0, delay);
+        let samples: Vec<f32> = branch.by_ref().take(6).collect();
+        assert_eq!(samples[..4], [0.0; 4]);
+        assert!((samples[4] - 0.1).abs() < 1e-6);
+        assert!((samples[5] - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_lagging_branch_skips_ahead() {
+        let fan_out = FanOut::new(counter());
+        let mut fast = fan_out.branch(0.0, Duration::ZERO);
+        let mut stalled = fan_out.branch(0.0, Duration::ZERO);
+        let lead = max_lag_samples() + 3;
+        let last = fast.by_ref().take(lead).last().unwrap();
+        assert_eq!(last, lead as f32);
+        assert!(fan_out.0.lock().unwrap().buffer.len() <= max_lag_samples());
+        // The stalled branch continues on a frame boundary, i.e. with the
+        // left channel (odd values).
+        let resumed = stalled.next().unwrap();
+        assert!(resumed > 1.0);
+        assert_eq!(resumed as u64 % CHANNELS as u64, 1);
+    }
+
+    #[test]
+    fn test_dropped_branch_id_is_reused() {
+        let fan_out = FanOut::new(counter());
+        let a = fan_out.branch(0.0, Duration::ZERO);
+        let b = fan_out.branch(0.0, Duration::ZERO);
+        drop(a);
+        let c = fan_out.branch(0.0, Duration::ZERO);
+        assert_eq!(c.id, 0);
+        assert_eq!(b.id, 1);
+        assert_eq!(fan_out.0.lock().unwrap().positions.len(), 2);
+    }
+
+    #[test]
+    fn test_dropped_branch_releases_buffer() {
+        let fan_out = FanOut::new(counter());
+        let mut a = fan_out.branch(0.0, Duration::ZERO);
+        let b = fan_out.branch(0.0, Duration::ZERO);
+        a.by_ref().take(10).for_each(drop);
+        assert_eq!(fan_out.0.lock().unwrap().buffer.len(), BLOCK_SAMPLES);
+        drop(b);
+        assert!(fan_out.0.lock().unwrap().buffer.is_empty());
+    }
+}
@@ -0,0 +1,504 @@
+//! A small FLAC encoder for ripping CDs.
+//!
+//! It writes 16-bit PCM in blocks of [`BLOCK_SIZE`] frames.  Every channel
+//! of a block is coded with the best of the fixed predictors (order 0 to 4)
+//! and partitioned Rice coding, or stored as a constant or verbatim, and
+//! stereo blocks use whichever of left/right, left/side, right/side and
+//! mid/side is smallest.  That is what `flac -5` does apart from the LPC
+//! predictors, and gets within a few percent of it.
+//!
+//! The MD5 sum of the audio in STREAMINFO is left at zero, which the format
+//! allows for "unknown"; rips are checked with their own CRCs instead.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Frames per block, the block size `flac` uses for CD audio.
+pub const BLOCK_SIZE: usize = 4096;
+
+/// Largest Rice partition order tried.
+const MAX_PARTITION_ORDER: u32 = 6;
+
+/// Largest Rice parameter of the 4-bit parameter coding (15 is an escape).
+const MAX_RICE_PARAMETER: u32 = 14;
+
+const BITS_PER_SAMPLE: u32 = 16;
+
+/// Size of the STREAMINFO block, without its header.
+const STREAMINFO_LEN: u32 = 34;
+
+/// Writes 16-bit PCM as a FLAC stream.
+pub struct FlacWriter<W: Write + Seek> {
+    writer: W,
+    /// Position of STREAMINFO, which is completed by [`FlacWriter::finish`].
+    streaminfo_pos: u64,
+    channels: usize,
+    sample_rate: u32,
+    /// Samples of the current block, by channel.
+    block: Vec<Vec<i32>>,
+    frame_number: u32,
+    total_frames: u64,
+    min_frame_size: u32,
+    max_frame_size: u32,
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    /// Start a stream with one or two channels.
+    pub fn new(mut writer: W, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        if !(1..=2).contains(&channels) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only mono and stereo are supported",
+            ));
+        }
+        writer.write_all(b"fLaC")?;
+        let streaminfo_pos = writer.stream_position()?;
+        let mut this = Self {
+            writer,
+            streaminfo_pos,
+            channels: channels as usize,
+            sample_rate,
+            block: vec![Vec::with_capacity(BLOCK_SIZE); channels as usize],
+            frame_number: 0,
+            total_frames: 0,
+            min_frame_size: 0,
+            max_frame_size: 0,
+        };
+        this.write_streaminfo()?;
+        Ok(this)
+    }
+
+    /// Add interleaved samples.
+    pub fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, &sample) in self.block.iter_mut().zip(frame) {
+                channel.push(sample as i32);
+            }
+            if self.block[0].len() == BLOCK_SIZE {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the last block and complete STREAMINFO.  Returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.block[0].is_empty() {
+            self.write_frame()?;
+        }
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.streaminfo_pos))?;
+        self.write_streaminfo()?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_streaminfo(&mut self) -> io::Result<()> {
+        let mut bits = BitWriter::default();
+        // Last metadata block, type 0 (STREAMINFO).
+        bits.write(1, 1);
+        bits.write(7, 0);
+        bits.write(24, STREAMINFO_LEN as u64);
+        bits.write(16, BLOCK_SIZE as u64);
+        bits.write(16, BLOCK_SIZE as u64);
+        bits.write(24, self.min_frame_size as u64);
+        bits.write(24, self.max_frame_size as u64);
+        bits.write(20, self.sample_rate as u64);
+        bits.write(3, self.channels as u64 - 1);
+        bits.write(5, BITS_PER_SAMPLE as u64 - 1);
+        bits.write(36, self.total_frames);
+        bits.bytes.extend([0; 16]);
+        self.writer.write_all(&bits.bytes)
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        let block_len = self.block[0].len();
+        let (assignment, subframes) = self.choose_channels();
+
+        let mut bits = BitWriter::default();
+        // Sync code, fixed block size.
+        bits.write(16, 0xFFF8);
+        let block_size_code = if block_len == BLOCK_SIZE {
+            0b1100
+        } else {
+            0b0111
+        };
+        let sample_rate_code = match self.sample_rate {
+            44_100 => 0b1001,
+            48_000 => 0b1010,
+            _ => 0,
+        };
+        bits.write(4, block_size_code);
+        bits.write(4, sample_rate_code);
+        bits.write(4, assignment);
+        // 16 bits per sample, reserved bit.
+        bits.write(3, 0b100);
+        bits.write(1, 0);
+        bits.bytes.extend(utf8_number(self.frame_number));
+        if block_len != BLOCK_SIZE {
+            bits.write(16, block_len as u64 - 1);
+        }
+        let header_crc = crc8(&bits.bytes);
+        bits.write(8, header_crc as u64);
+
+        for (samples, bps) in &subframes {
+            encode_subframe(&mut bits, samples, *bps);
+        }
+        bits.align();
+        let frame_crc = crc16(&bits.bytes);
+        bits.write(16, frame_crc as u64);
+
+        self.writer.write_all(&bits.bytes)?;
+        let size = bits.bytes.len() as u32;
+        if self.frame_number == 0 || size < self.min_frame_size {
+            self.min_frame_size = size;
+        }
+        self.max_frame_size = self.max_frame_size.max(size);
+        self.frame_number += 1;
+        self.total_frames += block_len as u64;
+        for channel in &mut self.block {
+            channel.clear();
+        }
+        Ok(())
+    }
+
+    /// Pick the channel assignment with the smallest subframes.  Returns the
+    /// assignment code and the samples and bit depth of each subframe.
+    fn choose_channels(&self) -> (u64, Vec<(Vec<i32>, u32)>) {
+        let bps = BITS_PER_SAMPLE;
+        if self.channels == 1 {
+            return (0, vec![(self.block[0].clone(), bps)]);
+        }
+        let (left, right) = (&self.block[0], &self.block[1]);
+        let side: Vec<i32> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+        let mid: Vec<i32> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+        let cost = |samples: &[i32], bps| plan_subframe(samples, bps).bits;
+        let (left_cost, right_cost) = (cost(left, bps), cost(right, bps));
+        let (side_cost, mid_cost) = (cost(&side, bps + 1), cost(&mid, bps));
+        let options = [
+            (left_cost + right_cost, 0b0001),
+            (left_cost + side_cost, 0b1000),
+            (right_cost + side_cost, 0b1001),
+            (mid_cost + side_cost, 0b1010),
+        ];
+        let (_, assignment) = options.into_iter().min_by_key(|(cost, _)| *cost).unwrap();
+        let subframes = match assignment {
+            0b0001 => vec![(left.clone(), bps), (right.clone(), bps)],
+            0b1000 => vec![(left.clone(), bps), (side, bps + 1)],
+            0b1001 => vec![(side, bps + 1), (right.clone(), bps)],
+            _ => vec![(mid, bps), (side, bps + 1)],
+        };
+        (assignment, subframes)
+    }
+}
+
+/// How a subframe is coded.
+#[derive(Debug, PartialEq)]
+enum SubframeKind {
+    Constant,
+    Verbatim,
+    /// Fixed predictor of this order with Rice partitions of this order.
+    Fixed {
+        order: usize,
+        partition_order: u32,
+    },
+}
+
+struct SubframePlan {
+    kind: SubframeKind,
+    /// Size of the subframe in bits.
+    bits: u64,
+}
+
+/// Find the smallest coding of `samples`.
+fn plan_subframe(samples: &[i32], bps: u32) -> SubframePlan {
+    let n = samples.len();
+    if samples.iter().all(|&s| s == samples[0]) {
+        return SubframePlan {
+            kind: SubframeKind::Constant,
+            bits: 8 + bps as u64,
+        };
+    }
+    let mut best = SubframePlan {
+        kind: SubframeKind::Verbatim,
+        bits: 8 + bps as u64 * n as u64,
+    };
+    for order in 0..=4.min(n.saturating_sub(1)) {
+        let residual = fixed_residual(samples, order);
+        let (partition_order, rice_bits) = best_partitioning(&residual, n, order);
+        let bits = 8 + (order as u64 * bps as u64) + 6 + rice_bits;
+        if bits < best.bits {
+            best = SubframePlan {
+                kind: SubframeKind::Fixed {
+                    order,
+                    partition_order,
+                },
+                bits,
+            };
+        }
+    }
+    best
+}
+
+fn encode_subframe(bits: &mut BitWriter, samples: &[i32], bps: u32) {
+    match plan_subframe(samples, bps).kind {
+        SubframeKind::Constant => {
+            bits.write(8, 0);
+            bits.write_signed(bps, samples[0]);
+        }
+        SubframeKind::Verbatim => {
+            bits.write(8, 1 << 1);
+            for &sample in samples {
+                bits.write_signed(bps, sample);
+            }
+        }
+        SubframeKind::Fixed {
+            order,
+            partition_order,
+        } => {
+            bits.write(8, (0b001000 | order as u64) << 1);
+            for &sample in &samples[..order] {
+                bits.write_signed(bps, sample);
+            }
+            let residual = fixed_residual(samples, order);
+            // Rice coding with 4-bit parameters.
+            bits.write(2, 0);
+            bits.write(4, partition_order as u64);
+            for partition in partitions(&residual, samples.len(), order, partition_order) {
+                let k = rice_parameter(partition).0;
+                bits.write(4, k as u64);
+                for &r in partition {
+                    let u = zigzag(r);
+                    bits.write_unary((u >> k) as u32);
+                    bits.write(k, u);
+                }
+            }
+        }
+    }
+}
+
+/// Residual of the fixed predictor of the given order, without the warm-up
+/// samples.
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<i64> {
+    let x = |i: usize| samples[i] as i64;
+    (order..samples.len())
+        .map(|i| match order {
+            0 => x(i),
+            1 => x(i) - x(i - 1),
+            2 => x(i) - 2 * x(i - 1) + x(i - 2),
+            3 => x(i) - 3 * x(i - 1) + 3 * x(i - 2) - x(i - 3),
+            _ => x(i) - 4 * x(i - 1) + 6 * x(i - 2) - 4 * x(i - 3) + x(i - 4),
+        })
+        .collect()
+}
+
+/// Split the residual into `2^partition_order` partitions.  The first one is
+/// short by the warm-up samples.
+fn partitions(
+    residual: &[i64],
+    block_len: usize,
+    order: usize,
+    partition_order: u32,
+) -> Vec<&[i64]> {
+    let len = block_len >> partition_order;
+    let mut parts = Vec::with_capacity(1 << partition_order);
+    let mut rest = residual;
+    for i in 0..1usize << partition_order {
+        let take = if i == 0 { len - order } else { len };
+        let (part, tail) = rest.split_at(take);
+        parts.push(part);
+        rest = tail;
+    }
+    parts
+}
+
+/// The partition order with the fewest bits, and the number of bits of the
+/// partitioned residual.
+fn best_partitioning(residual: &[i64], block_len: usize, order: usize) -> (u32, u64) {
+    (0..=MAX_PARTITION_ORDER)
+        .take_while(|&p| block_len.is_multiple_of(1 << p) && (block_len >> p) > order)
+        .map(|p| {
+            let bits = partitions(residual, block_len, order, p)
+                .iter()
+                .map(|part| 4 + rice_parameter(part).1)
+                .sum();
+            (p, bits)
+        })
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap_or((0, u64::MAX / 2))
+}
+
+/// The best Rice parameter for a partition and the number of bits it codes
+/// the partition in.  Only the parameters next to the one estimated from the
+/// mean are tried.
+fn rice_parameter(partition: &[i64]) -> (u32, u64) {
+    let n = partition.len() as u64;
+    if n == 0 {
+        return (0, 0);
+    }
+    let sum: u64 = partition.iter().map(|&r| zigzag(r)).sum();
+    let estimate = (sum / n).max(1).ilog2().min(MAX_RICE_PARAMETER);
+    let cost = |k: u32| n * (k as u64 + 1) + partition.iter().map(|&r| zigzag(r) >> k).sum::<u64>();
+    (estimate.saturating_sub(1)..=(estimate + 1).min(MAX_RICE_PARAMETER))
+        .map(|k| (k, cost(k)))
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap()
+}
+
+fn zigzag(r: i64) -> u64 {
+    ((r << 1) ^ (r >> 63)) as u64
+}
+
+/// Frame number in the UTF-8 like coding of FLAC.
+fn utf8_number(n: u32) -> Vec<u8> {
+    if n < 0x80 {
+        return vec![n as u8];
+    }
+    let len = match n {
+        0..0x800 => 2,
+        0x800..0x1_0000 => 3,
+        0x1_0000..0x20_0000 => 4,
+        0x20_0000..0x400_0000 => 5,
+        _ => 6,
+    };
+    let mut bytes = vec![(0xFF00u32 >> len) as u8 | (n >> (6 * (len - 1))) as u8];
+    for i in (0..len - 1).rev() {
+        bytes.push(0x80 | ((n >> (6 * i)) & 0x3F) as u8);
+    }
+    bytes
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Collects bits, most significant first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    /// Write the lowest `bits` bits of `value`, at most 32 at a time.
+    fn write(&mut self, bits: u32, value: u64) {
+        if bits > 32 {
+            self.write(bits - 32, value >> 32);
+            self.write(32, value & 0xFFFF_FFFF);
+            return;
+        }
+        self.acc = (self.acc << bits) | (value & ((1u64 << bits) - 1));
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.acc >> self.bits) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, bits: u32, value: i32) {
+        self.write(bits, value as i64 as u64);
+    }
+
+    /// Write `q` zeros and a one.
+    fn write_unary(&mut self, mut q: u32) {
+        while q >= 32 {
+            self.write(32, 0);
+            q -= 32;
+        }
+        self.write(q + 1, 1);
+    }
+
+    /// Pad with zeros to the next byte.
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(8 - self.bits, 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Encode interleaved stereo samples and decode them again with rodio.
+    fn round_trip(samples: &[i16]) -> Vec<i16> {
+        let mut writer = FlacWriter::new(Cursor::new(Vec::new()), 2, 44_100).unwrap();
+        writer.write(samples).unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        rodio::Decoder::new(Cursor::new(data))
+            .unwrap()
+            .map(|sample| (sample * 32_768.0).round() as i16)
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_is_lossless() {
+        let mut seed = 1u32;
+        let mut noise = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as i16
+        };
+        let mut samples = Vec::new();
+        // Silence, a loud sine on both channels, different channels, noise
+        // and a partial last block.
+        samples.extend(vec![0; 2 * 1000]);
+        for n in 0..BLOCK_SIZE * 2 {
+            let value = (30_000.0 * (n as f32 * 0.05).sin()) as i16;
+            samples.extend([value, value / 2]);
+        }
+        for n in 0..BLOCK_SIZE {
+            samples.extend([i16::MAX - (n % 3) as i16, i16::MIN + (n % 5) as i16]);
+        }
+        for _ in 0..BLOCK_SIZE + 123 {
+            samples.extend([noise(), noise()]);
+        }
+        assert_eq!(round_trip(&samples), samples);
+    }
+
+    #[test]
+    fn test_streaminfo_is_completed() {
+        let mut writer = FlacWriter::new(Cursor::new(Vec::new()), 2, 44_100).unwrap();
+        writer.write(&vec![100; 2 * (BLOCK_SIZE + 10)]).unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        assert_eq!(&data[..4], b"fLaC");
+        // Total samples: the low 32 of 36 bits at the end of STREAMINFO
+        // before the MD5 sum.
+        let total = u32::from_be_bytes(data[22..26].try_into().unwrap());
+        assert_eq!(total as usize, BLOCK_SIZE + 10);
+    }
+
+    #[test]
+    fn test_utf8_frame_numbers() {
+        assert_eq!(utf8_number(0x7F), [0x7F]);
+        assert_eq!(utf8_number(0x80), [0xC2, 0x80]);
+        assert_eq!(utf8_number(0x800), [0xE0, 0xA0, 0x80]);
+    }
+}
@@ -0,0 +1,165 @@
+//! Hardware volume through an ALSA mixer control.
+//!
+//! Scaling samples in software loses resolution at low volume.  With a
+//! hardware mixer the samples are passed on untouched and the DAC does the
+//! attenuation.  The player volume (0.0 – 1.0) is mapped onto the control
+//! like `alsamixer` does it: on a perceptual scale if the control reports
+//! its range in dB, linearly on the raw range otherwise.
+
+use alsa::Round;
+use alsa::mixer::{MilliBel, Mixer, SelemChannelId, SelemId};
+use anyhow::{Error, anyhow};
+
+/// Controls with a smaller dB range than this are mapped linearly in dB.
+const MAX_LINEAR_DB_SCALE: f64 = 24.0;
+
+/// Volume changes smaller than this are not reported as external changes.
+const CHANGE_THRESHOLD: f32 = 0.005;
+
+/// ALSA mixer control used for the player volume.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HardwareMixer {
+    /// Mixer device, e.g. `default` or `hw:1`.
+    pub card: String,
+    /// Simple mixer control, e.g. `PCM` or `Master`.
+    pub control: String,
+}
+
+/// An open ALSA mixer control.
+pub(crate) struct HardwareVolume {
+    config: HardwareMixer,
+    mixer: Mixer,
+    id: SelemId,
+    /// Last volume set or read, to recognise changes made by other programs.
+    last: f32,
+}
+
+impl HardwareVolume {
+    pub(crate) fn open(config: &HardwareMixer) -> Result<Self, Error> {
+        let mixer = Mixer::new(&config.card, false)?;
+        let id = SelemId::new(&config.control, 0);
+        let selem = mixer
+            .find_selem(&id)
+            .ok_or_else(|| anyhow!("no mixer control '{}' on {}", config.control, config.card))?;
+        if !selem.has_playback_volume() {
+            return Err(anyhow!("mixer control '{}' has no volume", config.control));
+        }
+        let mut volume = Self {
+            config: config.clone(),
+            mixer,
+            id,
+            last: 0.0,
+        };
+        volume.last = volume.get()?;
+        Ok(volume)
+    }
+
+    pub(crate) fn config(&self) -> &HardwareMixer {
+        &self.config
+    }
+
+    /// Current volume of the control, 0.0 – 1.0.
+    pub(crate) fn get(&self) -> Result<f32, Error> {
+        let selem = self
+            .mixer
+            .find_selem(&self.id)
+            .ok_or_else(|| anyhow!("mixer control '{}' vanished", self.config.control))?;
+        let channel = SelemChannelId::mono();
+        let (min_db, max_db) = selem.get_playback_db_range();
+        if min_db < max_db {
+            let db = selem.get_playback_vol_db(channel)?;
+            return Ok(db_to_volume(
+                db.to_db() as f64,
+                min_db.to_db() as f64,
+                max_db.to_db() as f64,
+            ));
+        }
+        let (min, max) = selem.get_playback_volume_range();
+        let raw = selem.get_playback_volume(channel)?;
+        Ok(if max > min {
+            (raw - min) as f32 / (max - min) as f32
+        } else {
+            0.0
+        })
+    }
+
+    /// Set the volume of the control, 0.0 – 1.0.
+    pub(crate) fn set(&mut self, volume: f32) -> Result<(), Error> {
+        let volume = volume.clamp(0.0, 1.0);
+        {
+            let selem = self
+                .mixer
+                .find_selem(&self.id)
+                .ok_or_else(|| anyhow!("mixer control '{}' vanished", self.config.control))?;
+            let (min_db, max_db) = selem.get_playback_db_range();
+            if min_db < max_db {
+                let db = volume_to_db(volume, min_db.to_db() as f64, max_db.to_db() as f64);
+                selem.set_playback_db_all(MilliBel::from_db(db as f32), Round::Floor)?;
+            } else {
+                let (min, max) = selem.get_playback_volume_range();
+                let raw = min + ((max - min) as f32 * volume).round() as i64;
+                selem.set_playback_volume_all(raw)?;
+            }
+        }
+        // Remember what the control really ended up with, it is quantized.
+        self.last = self.get().unwrap_or(volume);
+        Ok(())
+    }
+
+    /// Returns the new volume if another program changed the control since
+    /// the last call.
+    pub(crate) fn poll_external_change(&mut self) -> Result<Option<f32>, Error> {
+        self.mixer.handle_events()?;
+        let volume = self.get()?;
+        if (volume - self.last).abs() < CHANGE_THRESHOLD {
+            return Ok(None);
+        }
+        self.last = volume;
+        Ok(Some(volume))
+    }
+}
+
+/// Map a control value in dB to the player volume.
+fn db_to_volume(db: f64, min_db: f64, max_db: f64) -> f32 {
+    if max_db - min_db <= MAX_LINEAR_DB_SCALE {
+        return ((db - min_db) / (max_db - min_db)).clamp(0.0, 1.0) as f32;
+    }
+    let normalized = 10f64.powf((db - max_db) / 60.0);
+    let min_normalized = 10f64.powf((min_db - max_db) / 60.0);
+    ((normalized - min_normalized) / (1.0 - min_normalized)).clamp(0.0, 1.0) as f32
+}
+
+/// Map the player volume to a control value in dB.
+fn volume_to_db(volume: f32, min_db: f64, max_db: f64) -> f64 {
+    let volume = volume.clamp(0.0, 1.0) as f64;
+    if max_db - min_db <= MAX_LINEAR_DB_SCALE {
+        return min_db + volume * (max_db - min_db);
+    }
+    let min_normalized = 10f64.powf((min_db - max_db) / 60.0);
+    let normalized = volume * (1.0 - min_normalized) + min_normalized;
+    (60.0 * normalized.log10() + max_db).max(min_db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_db_mapping_round_trip() {
+        for (min_db, max_db) in [(-103.5, 0.0), (-20.0, 4.0)] {
+            for volume in [0.0, 0.1, 0.25, 0.5, 0.9, 1.0] {
+                let db = volume_to_db(volume, min_db, max_db);
+                assert!(db >= min_db && db <= max_db);
+                assert!((db_to_volume(db, min_db, max_db) - volume).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_db_mapping_is_perceptual() {
+        // Half volume is well below the middle of a large dB range, but far
+        // from silent.
+        let db = volume_to_db(0.5, -103.5, 0.0);
+        assert!(db < -10.0 && db > -30.0);
+    }
+}
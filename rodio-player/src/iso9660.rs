@@ -0,0 +1,415 @@
+//! Reading the files of data CDs and DVDs with an ISO 9660 file system.
+//!
+//! "MP3 CDs" are data discs whose audio files are in an ISO 9660 file
+//! system, usually with Joliet extensions for long names.  The file system
+//! is read from the device directly, so the disc doesn't need to be mounted.
+//! Enhanced CDs keep their files in a data session after the audio tracks,
+//! whose volume descriptors follow the start of the data track.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use anyhow::{Error, anyhow};
+use tracing::debug;
+
+/// Size of a logical sector of the file system.
+const SECTOR_SIZE: u64 = 2048;
+
+/// Sector of the first volume descriptor, after the system area.
+const VOLUME_DESCRIPTORS_START: u32 = 16;
+/// Volume descriptors are searched up to this many sectors.
+const MAX_VOLUME_DESCRIPTORS: u32 = 32;
+
+/// Volume descriptor types.
+const VD_PRIMARY: u8 = 1;
+const VD_SUPPLEMENTARY: u8 = 2;
+const VD_TERMINATOR: u8 = 255;
+/// Identifier following the type of every volume descriptor.
+const STANDARD_ID: &[u8] = b"CD001";
+/// Offset of the directory record of the root in a volume descriptor.
+const ROOT_RECORD_OFFSET: usize = 156;
+/// Offset of the escape sequences in a supplementary volume descriptor.
+const ESCAPES_OFFSET: usize = 88;
+/// Escape sequences of the Joliet levels 1 to 3 (UCS-2 names).
+const JOLIET_ESCAPES: [&[u8]; 3] = [b"%/@", b"%/C", b"%/E"];
+
+/// Size of a directory record without the name.
+const RECORD_HEADER_SIZE: usize = 33;
+/// Directory record flags.
+const FLAG_HIDDEN: u8 = 0x01;
+const FLAG_DIRECTORY: u8 = 0x02;
+
+/// Larger directories are not read; protects against broken discs.
+const MAX_DIR_SIZE: u64 = 16 << 20;
+
+/// A file or folder on a data disc.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscEntry {
+    pub name: String,
+    pub is_dir: bool,
+    /// First sector of the data (LBA).
+    pub lba: u32,
+    /// Size of the data in bytes.
+    pub size: u64,
+}
+
+impl DiscEntry {
+    /// The file extension in lower case, if there is one.
+    pub fn extension(&self) -> Option<String> {
+        let (stem, extension) = self.name.rsplit_once('.')?;
+        (!stem.is_empty() && !extension.is_empty()).then(|| extension.to_ascii_lowercase())
+    }
+}
+
+/// The file system of a data disc.
+pub struct DiscFs<R> {
+    reader: R,
+    root: DiscEntry,
+    /// Names are UCS-2 (Joliet) instead of ASCII.
+    joliet: bool,
+}
+
+impl DiscFs<BufReader<File>> {
+    /// Open the file system of the disc in `device`, in the session starting
+    /// at `session_lba` (the start of the last data track).
+    pub fn open(device: &str, session_lba: u32) -> Result<Self, Error> {
+        let file = File::open(device).map_err(|e| anyhow!("Could not open {device}: {e}"))?;
+        Self::new(BufReader::new(file), session_lba)
+    }
+}
+
+impl<R: Read + Seek> DiscFs<R> {
+    /// Read the volume descriptors of the session starting at `session_lba`.
+    /// The Joliet tree is preferred for its long names.
+    pub fn new(mut reader: R, session_lba: u32) -> Result<Self, Error> {
+        let mut primary = None;
+        let mut joliet = None;
+        for i in 0..MAX_VOLUME_DESCRIPTORS {
+            let lba = session_lba + VOLUME_DESCRIPTORS_START + i;
+            let mut sector = [0u8; SECTOR_SIZE as usize];
+            reader.seek(SeekFrom::Start(lba as u64 * SECTOR_SIZE))?;
+            reader.read_exact(&mut sector)?;
+            if &sector[1..6] != STANDARD_ID {
+                break;
+            }
+            let root = || parse_record(&sector[ROOT_RECORD_OFFSET..], false);
+            match sector[0] {
+                VD_PRIMARY => primary = root(),
+                VD_SUPPLEMENTARY
+                    if JOLIET_ESCAPES.contains(&&sector[ESCAPES_OFFSET..ESCAPES_OFFSET + 3]) =>
+                {
+                    joliet = root();
+                }
+                VD_TERMINATOR => break,
+                _ => {}
+            }
+        }
+        let (root, joliet) = match (joliet, primary) {
+            (Some(root), _) => (root, true),
+            (None, Some(root)) => (root, false),
+            (None, None) => return Err(anyhow!("No ISO 9660 file system found")),
+        };
+        debug!("ISO 9660 root at LBA {} (Joliet: {joliet})", root.lba);
+        Ok(Self {
+            reader,
+            root: DiscEntry {
+                name: String::new(),
+                ..root
+            },
+            joliet,
+        })
+    }
+
+    /// The root folder.
+    pub fn root(&self) -> DiscEntry {
+        self.root.clone()
+    }
+
+    /// The files and folders in `dir`: folders first, then files, each
+    /// sorted by name.  Hidden entries are left out.
+    pub fn read_dir(&mut self, dir: &DiscEntry) -> Result<Vec<DiscEntry>, Error> {
+        if !dir.is_dir {
+            return Err(anyhow!("{} is not a folder", dir.name));
+        }
+        if dir.size > MAX_DIR_SIZE {
+            return Err(anyhow!("Folder {} is too large", dir.name));
+        }
+        let mut data = vec![0u8; dir.size as usize];
+        self.reader
+            .seek(SeekFrom::Start(dir.lba as u64 * SECTOR_SIZE))?;
+        self.reader.read_exact(&mut data)?;
+
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let len = data[pos] as usize;
+            if len == 0 {
+                // Records don't cross sectors; the rest of this one is empty.
+                pos = (pos / SECTOR_SIZE as usize + 1) * SECTOR_SIZE as usize;
+                continue;
+            }
+            let Some(record) = data.get(pos..pos + len) else {
+                break;
+            };
+            pos += len;
+            if let Some(entry) = parse_record(record, self.joliet)
+                && !entry.name.is_empty()
+            {
+                entries.push(entry);
+            }
+        }
+        entries.sort_by(|a, b| {
+            b.is_dir
+                .cmp(&a.is_dir)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+        Ok(entries)
+    }
+}
+
+/// Parse a directory record.  The records of the folder itself and its
+/// parent get an empty name, hidden ones none at all.
+fn parse_record(record: &[u8], joliet: bool) -> Option<DiscEntry> {
+    if record.len() < RECORD_HEADER_SIZE || (record[0] as usize) < RECORD_HEADER_SIZE {
+        return None;
+    }
+    let flags = record[25];
+    if flags & FLAG_HIDDEN != 0 {
+        return None;
+    }
+    let name_len = record[32] as usize;
+    let raw_name = record.get(RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + name_len)?;
+    let name = if raw_name == [0] || raw_name == [1] {
+        String::new()
+    } else if joliet {
+        let units: Vec<u16> = raw_name
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        file_name(&String::from_utf16_lossy(&units))
+    } else {
+        file_name(&String::from_utf8_lossy(raw_name))
+    };
+    let is_dir = flags & FLAG_DIRECTORY != 0;
+    Some(DiscEntry {
+        name,
+        is_dir,
+        lba: u32::from_le_bytes(record[2..6].try_into().ok()?),
+        size: u32::from_le_bytes(record[10..14].try_into().ok()?) as u64,
+    })
+}
+
+/// Strip the version (`;1`) and the dot of names without an extension.
+fn file_name(name: &str) -> String {
+    let name = match name.rsplit_once(';') {
+        Some((name, version)) if version.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => name,
+    };
+    name.strip_suffix('.').unwrap_or(name).to_string()
+}
+
+/// Open a file of the disc in `device` for reading.
+pub fn open_file(device: &str, entry: &DiscEntry) -> Result<FileReader<BufReader<File>>, Error> {
+    let file = File::open(device).map_err(|e| anyhow!("Could not open {device}: {e}"))?;
+    Ok(FileReader::new(BufReader::new(file), entry)?)
+}
+
+/// Reads one file of the file system, like a file of its own.
+pub struct FileReader<R> {
+    reader: R,
+    /// Position of the file on the disc in bytes.
+    start: u64,
+    len: u64,
+    /// Position within the file.
+    pos: u64,
+}
+
+impl<R: Read + Seek> FileReader<R> {
+    pub fn new(mut reader: R, entry: &DiscEntry) -> io::Result<Self> {
+        let start = entry.lba as u64 * SECTOR_SIZE;
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            reader,
+            start,
+            len: entry.size,
+            pos: 0,
+        })
+    }
+}
+
+impl<R: Read + Seek> Read for FileReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos);
+        let max = (buf.len() as u64).min(remaining) as usize;
+        if max == 0 {
+            return Ok(0);
+        }
+        let n = self.reader.read(&mut buf[..max])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for FileReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start"))?;
+        self.reader.seek(SeekFrom::Start(self.start + target))?;
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const SECTOR: usize = SECTOR_SIZE as usize;
+
+    fn record(name: &[u8], flags: u8, lba: u32, size: u32) -> Vec<u8> {
+        let mut record = vec![0u8; RECORD_HEADER_SIZE];
+        record.extend_from_slice(name);
+        if record.len() % 2 == 1 {
+            record.push(0);
+        }
+        record[0] = record.len() as u8;
+        record[2..6].copy_from_slice(&lba.to_le_bytes());
+        record[10..14].copy_from_slice(&size.to_le_bytes());
+        record[25] = flags;
+        record[32] = name.len() as u8;
+        record
+    }
+
+    fn ucs2(name: &str) -> Vec<u8> {
+        name.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    fn volume_descriptor(kind: u8, root: &[u8], joliet: bool) -> Vec<u8> {
+        let mut sector = vec![0u8; SECTOR];
+        sector[0] = kind;
+        sector[1..6].copy_from_slice(STANDARD_ID);
+        if joliet {
+            sector[ESCAPES_OFFSET..ESCAPES_OFFSET + 3].copy_from_slice(b"%/E");
+        }
+        sector[ROOT_RECORD_OFFSET..ROOT_RECORD_OFFSET + root.len()].copy_from_slice(root);
+        sector
+    }
+
+    fn directory(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut sector: Vec<u8> = records.concat();
+        sector.resize(SECTOR, 0);
+        sector
+    }
+
+    /// An image with a primary and, if `joliet`, a Joliet tree, starting at
+    /// sector `session`:
+    /// `/Album/01 Song.mp3` with the content "song", and a hidden file.
+    fn image(session: usize, joliet: bool) -> Vec<u8> {
+        let lba = |sector: usize| (session + sector) as u32;
+        let sector = SECTOR as u32;
+        let mut image = vec![0u8; (session + 16) * SECTOR];
+        image.extend(volume_descriptor(
+            VD_PRIMARY,
+            &record(&[0], FLAG_DIRECTORY, lba(19), sector),
+            false,
+        ));
+        if joliet {
+            image.extend(volume_descriptor(
+                VD_SUPPLEMENTARY,
+                &record(&[0], FLAG_DIRECTORY, lba(21), sector),
+                true,
+            ));
+        } else {
+            image.extend(vec![0u8; SECTOR]);
+            image[(session + 17) * SECTOR + 1..(session + 17) * SECTOR + 6]
+                .copy_from_slice(STANDARD_ID);
+        }
+        image.extend(volume_descriptor(VD_TERMINATOR, &[], false));
+        // 19, 20: primary tree.
+        image.extend(directory(&[
+            record(&[0], FLAG_DIRECTORY, lba(19), sector),
+            record(&[1], FLAG_DIRECTORY, lba(19), sector),
+            record(b"HIDDEN.TXT;1", FLAG_HIDDEN, lba(23), 4),
+            record(b"ALBUM", FLAG_DIRECTORY, lba(20), sector),
+            record(b"README.;1", 0, lba(23), 4),
+        ]));
+        image.extend(directory(&[
+            record(&[0], FLAG_DIRECTORY, lba(20), sector),
+            record(&[1], FLAG_DIRECTORY, lba(19), sector),
+            record(b"01_SONG.MP3;1", 0, lba(23), 4),
+        ]));
+        // 21, 22: Joliet tree.
+        image.extend(directory(&[
+            record(&[0], FLAG_DIRECTORY, lba(21), sector),
+            record(&[1], FLAG_DIRECTORY, lba(21), sector),
+            record(&ucs2("readme;1"), 0, lba(23), 4),
+            record(&ucs2("Album"), FLAG_DIRECTORY, lba(22), sector),
+        ]));
+        image.extend(directory(&[
+            record(&[0], FLAG_DIRECTORY, lba(22), sector),
+            record(&[1], FLAG_DIRECTORY, lba(21), sector),
+            record(&ucs2("01 Song.mp3;1"), 0, lba(23), 4),
+        ]));
+        // 23: file content.
+        image.extend(directory(&[b"song".to_vec()]));
+        image
+    }
+
+    #[test]
+    fn test_joliet_tree() {
+        let mut fs = DiscFs::new(Cursor::new(image(0, true)), 0).unwrap();
+        let root = fs.read_dir(&fs.root()).unwrap();
+        let names: Vec<&str> = root.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["Album", "readme"]);
+        assert!(root[0].is_dir);
+
+        let album = fs.read_dir(&root[0]).unwrap();
+        assert_eq!(album.len(), 1);
+        assert_eq!(album[0].name, "01 Song.mp3");
+        assert_eq!(album[0].extension().as_deref(), Some("mp3"));
+        assert_eq!(album[0].size, 4);
+    }
+
+    #[test]
+    fn test_primary_tree_in_later_session() {
+        let mut fs = DiscFs::new(Cursor::new(image(100, false)), 100).unwrap();
+        let root = fs.read_dir(&fs.root()).unwrap();
+        let names: Vec<&str> = root.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["ALBUM", "README"]);
+        assert_eq!(root[1].extension(), None);
+        let album = fs.read_dir(&root[0]).unwrap();
+        assert_eq!(album[0].name, "01_SONG.MP3");
+        assert!(fs.read_dir(&album[0]).is_err());
+    }
+
+    #[test]
+    fn test_no_file_system() {
+        let empty = vec![0u8; 40 * SECTOR];
+        assert!(DiscFs::new(Cursor::new(empty), 0).is_err());
+    }
+
+    #[test]
+    fn test_file_reader() {
+        let image = image(0, true);
+        let mut fs = DiscFs::new(Cursor::new(image.clone()), 0).unwrap();
+        let root = fs.read_dir(&fs.root()).unwrap();
+        let song = fs.read_dir(&root[0]).unwrap().remove(0);
+
+        let mut reader = FileReader::new(Cursor::new(image), &song).unwrap();
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "song");
+
+        assert_eq!(reader.seek(SeekFrom::End(-2)).unwrap(), 2);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"ng");
+        assert_eq!(reader.seek(SeekFrom::Current(-3)).unwrap(), 1);
+        assert!(reader.seek(SeekFrom::Current(-2)).is_err());
+    }
+}
@@ -4,42 +4,93 @@
 //! So it provides
 //! a play queue handling with common functionality like 'play', 'skip' etc.
 //! but also informs about events like title changes and the current player
-//! state. Currently there is support to play a (list) of files, Internet
-//! streams like radio stations, and audio CDs.
+//! state. The play queue may contain files (local or on an HTTP server),
+//! Internet streams like radio stations, and audio CD tracks, mixed in any
+//! order.  Short announcements like a doorbell chime can be played over
+//! the music, which is turned down meanwhile.
 //!
 //! [rodio]: https://crates.io/crates/rodio
 //! [homeplayer]: https://github.com/kayhannay/homeplayer
 
+mod ab_loop;
+pub mod announcement;
 pub mod cd_audio;
-
+pub mod cd_metadata;
+mod cd_read;
+pub mod cd_rip;
+pub mod cd_watcher;
+mod de_emphasis;
+mod dynamics;
+mod fan_out;
+mod flac;
+mod hw_volume;
+pub mod iso9660;
+mod loudness;
+mod output;
+mod pcm_output;
+pub mod queue;
+mod silence;
+pub mod stream_metadata;
+mod time_stretch;
+
+use ab_loop::{AbLoopControl, AbLoopSource};
+use announcement::{DuckControl, Ducking};
 use anyhow::Error;
-use icy_metadata::{IcyHeaders, IcyMetadataReader, RequestIcyMetadata};
+use dynamics::{Compressor, DynamicsControl};
+use hw_volume::HardwareVolume;
+use output::{DEFAULT_DEVICE, Output, OutputChange};
 use rodio::cpal;
 use rodio::cpal::traits::{DeviceTrait, HostTrait};
-use rodio::{DeviceSinkBuilder, MixerDeviceSink, Player, Source};
-use std::num::NonZeroUsize;
+use rodio::{Player, Source};
+use silence::{DeadAirDetector, TrimSilence};
+use std::num::NonZero;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
-use std::thread::spawn;
+use std::thread::{sleep, spawn};
 use std::time::Duration;
-use stream_download::http::{HttpStream, reqwest::Client};
-use stream_download::storage::bounded::BoundedStorageProvider;
-use stream_download::storage::memory::MemoryStorageProvider;
-use stream_download::{Settings, StreamDownload};
+use time_stretch::{PlaybackPosition, SpeedControl, TimeStretch};
+use tokio::runtime::Handle;
 use tracing::{debug, error, info, warn};
 
+pub use ab_loop::AbLoop;
+pub use hw_volume::HardwareMixer;
+pub use output::{DeviceOutput, OutputBackend, OutputStatus};
+pub use queue::{CdTrackItem, DiscFileItem, QueueEntry, QueueItem, SoundItem, StationItem};
+pub use silence::SilenceSettings;
+pub use stream_metadata::StationMetadata;
+pub use time_stretch::{MAX_SPEED, MIN_SPEED};
+
+/// How often the audio output is checked for lost or new devices.
+const OUTPUT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a silent station is reconnected before giving up on it.
+const DEAD_AIR_RECONNECTS: usize = 1;
+
+/// How often the hardware mixer is checked for volume changes made by other
+/// programs.
+const MIXER_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often it is checked which of the CD tracks played without a gap is
+/// playing, to announce the next one.
+const TRACK_CHANGE_CHECK_INTERVAL: Duration = Duration::from_millis(20);
+
 /// Placeholder string used when no meaningful value is available (e.g. unknown
 /// album or artist in stream metadata).
 const UNKNOWN: &str = "-";
 
 /// Data structure that is sent over the provided channel to inform
 /// about the audio title that is currently played.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct TitleChanged {
     pub artist: String,
     pub album: String,
     pub title: String,
     pub cover: String,
+    /// `true` if the title was announced by an internet stream, i.e. it does
+    /// not refer to a local file.
+    pub is_stream: bool,
 }
 
 /// This enum represents the different player states.
@@ -52,33 +103,74 @@ pub enum PlayerState {
     Seekable,
     Unseekable,
     StartPlaying,
+    /// A queue item could not be played and was skipped.
+    Failed(PlaybackError),
+    /// The audio output device was connected, lost or switched.
+    OutputChanged(OutputStatus),
+    /// The hardware mixer volume was changed by another program.
+    VolumeChanged(f32),
+    /// A station stayed silent even after reconnecting, and nothing else is
+    /// queued after it.
+    DeadAir(StationItem),
 }
 
-/// Data structure for a concrete file title which can be added
-/// to the internal play list of the player.
+/// Describes a queue item that could not be played.
 #[derive(Clone, Debug)]
-pub struct SoundItem {
-    pub artist: String,
-    pub album: String,
-    pub title: String,
-    pub path: String,
-    pub cover: String,
+pub struct PlaybackError {
+    /// Position of the item in the queue.
+    pub index: usize,
+    /// File name or title of the item.
+    pub name: String,
+    pub reason: String,
 }
 
 /// The main struct, the player with all the functionality in it.
 ///
-/// The sink and output stream are stored behind a double-`Arc` with a `Mutex`
-/// in between (`Arc<Mutex<Arc<T>>>`).  This allows the active audio device to
-/// be swapped at runtime via [`switch_device`](RodioPlayer::switch_device)
-/// while spawned playback threads keep a clone of the *inner* `Arc<Sink>` and
-/// continue to reference the (stopped) old sink until they exit naturally.
+/// The sink is stored behind a double-`Arc` with a `Mutex` in between
+/// (`Arc<Mutex<Arc<T>>>`), so that spawned playback threads can keep a clone
+/// of the *inner* `Arc<Sink>` while it could be swapped.
+///
+/// The sink plays into an internal mixer, not into the audio device.  The
+/// device is managed separately (see [`OutputStatus`]): it may be missing at
+/// start, get lost during playback or be switched via
+/// [`switch_output`](RodioPlayer::switch_output) without affecting the queue.
 #[derive(Clone)]
 pub struct RodioPlayer {
     sink: Arc<Mutex<Arc<Player>>>,
-    _stream: Arc<Mutex<Arc<MixerDeviceSink>>>,
-    sound_queue: Arc<Mutex<Vec<SoundItem>>>,
+    /// Plays announcements over the music.
+    announcements: Arc<Player>,
+    /// Turns the music down while an announcement plays.
+    duck: DuckControl,
+    /// Switches the night mode compressor on and off.
+    night_mode: DynamicsControl,
+    /// Loudness internet streams are normalised to, in LUFS, if any.
+    stream_loudness_target: Arc<Mutex<Option<f32>>>,
+    /// How silence in files and streams is handled.
+    silence: Arc<Mutex<SilenceSettings>>,
+    /// Playback speed of files and CD tracks.
+    speed: SpeedControl,
+    /// Position in the file or CD track played, which the sink doesn't
+    /// know while the speed is not 1.0.
+    position: PlaybackPosition,
+    /// A-B repeat of the current file or CD track.
+    ab_loop: AbLoopControl,
+    output: Arc<Mutex<Output>>,
+    /// Set when playback was paused because the output device was lost, so it
+    /// can be resumed when the device is back.
+    paused_by_output_loss: Arc<AtomicBool>,
+    /// Set by [`Self::close`]; the monitor threads exit.
+    closed: Arc<AtomicBool>,
+    sound_queue: Arc<Mutex<Vec<QueueEntry>>>,
     sound_queue_index: Arc<Mutex<usize>>,
     mute_volume: Arc<Mutex<f32>>,
+    /// Hardware mixer control used instead of software volume, if any.
+    hardware_volume: Arc<Mutex<Option<HardwareVolume>>>,
+    /// Incremented whenever the current playback is stopped or the queue is
+    /// cleared, so that a still running queue thread notices that it is
+    /// obsolete and exits instead of continuing with the new queue.
+    playback_generation: Arc<AtomicUsize>,
+    /// Runtime used to connect to internet streams.
+    runtime: Handle,
     title_changed_sender: Sender<TitleChanged>,
     button_state_sender: Sender<PlayerState>,
 }
@@ -89,7 +181,7 @@ pub struct RodioPlayer {
 /// system's default output device.  The remaining entries are the names
 /// reported by the OS audio back-end.
 pub fn list_output_devices() -> Vec<String> {
-    let mut names = vec!["Default".to_string()];
+    let mut names = vec![DEFAULT_DEVICE.to_string()];
     if let Ok(devices) = cpal::default_host().output_devices() {
         for device in devices {
             if let Ok(name) = device.name() {
@@ -103,222 +195,286 @@ pub fn list_output_devices() -> Vec<String> {
 impl RodioPlayer {
     /// Creates a new `RodioPlayer`.
     ///
-    /// When `device_name` is `None` (or `Some("Default")`), the system's
-    /// default output device is used.  Otherwise the device whose name
-    /// matches the given string is selected.  If the requested device
-    /// cannot be found the default device is used as a fallback.
+    /// Audio goes to the given output `backend`.  For a sound card device
+    /// `None` (or `Some("Default")`) selects the system's default output
+    /// device, otherwise the device whose name matches the given string.  If
+    /// the requested device cannot be found the default device is used as a
+    /// fallback until it appears.  If no output can be opened at all, the
+    /// player starts without output and connects as soon as it is available.
+    ///
+    /// Internet streams are connected on the tokio runtime behind `runtime`.
     pub fn new(
         title_changed_sender: Sender<TitleChanged>,
         button_state_sender: Sender<PlayerState>,
-        device_name: Option<&str>,
+        backend: OutputBackend,
+        runtime: Handle,
     ) -> Self {
-        let stream = open_output_stream(device_name);
-        let sink = Player::connect_new(stream.mixer());
+        let (output, mixer) = Output::new(backend);
+        let (music_mixer, music) = rodio::mixer::mixer(
+            NonZero::new(output::CHANNELS).unwrap(),
+            NonZero::new(output::SAMPLE_RATE).unwrap(),
+        );
+        let night_mode = DynamicsControl::default();
+        let (ducking, duck) = Ducking::new(music);
+        mixer.add(ducking);
+        let sink = Player::connect_new(&music_mixer);
+        let announcements = Player::connect_new(&mixer);
 
-        Self {
+        let player = Self {
             sink: Arc::new(Mutex::new(Arc::new(sink))),
-            _stream: Arc::new(Mutex::new(Arc::new(stream))),
+            announcements: Arc::new(announcements),
+            duck,
+            night_mode,
+            stream_loudness_target: Arc::new(Mutex::new(None)),
+            silence: Arc::new(Mutex::new(SilenceSettings::default())),
+            speed: SpeedControl::default(),
+            position: PlaybackPosition::default(),
+            ab_loop: AbLoopControl::default(),
+            output: Arc::new(Mutex::new(output)),
+            paused_by_output_loss: Arc::new(AtomicBool::new(false)),
+            closed: Arc::new(AtomicBool::new(false)),
             sound_queue: Arc::new(Mutex::new(Vec::new())),
             sound_queue_index: Arc::new(Mutex::new(0)),
             mute_volume: Arc::new(Mutex::new(0.0)),
+            hardware_volume: Arc::new(Mutex::new(None)),
+            playback_generation: Arc::new(AtomicUsize::new(0)),
+            runtime,
             title_changed_sender,
             button_state_sender,
-        }
+        };
+        player.spawn_output_monitor();
+        player.spawn_mixer_monitor();
+        player
     }
 
-    /// Switch the audio output device at runtime.
+    /// Switch the audio output at runtime.
     ///
-    /// This stops any current playback, creates a new output stream for the
-    /// requested device, and swaps the internal sink so that subsequent
-    /// playback uses the new device.  The volume level is preserved across
-    /// the switch.
-    ///
-    /// Spawned playback threads that still hold a reference to the old sink
-    /// will see it in a stopped state and exit naturally.
-    pub fn switch_device(&self, device_name: Option<&str>) {
-        // Grab the current volume before stopping so we can restore it.
-        let volume = {
-            let sink = self.sink.lock().unwrap();
-            sink.volume()
+    /// Playback continues on the new output.  If a device cannot be opened,
+    /// the default device is used until the requested one appears.
+    pub fn switch_output(&self, backend: OutputBackend) {
+        info!("Switch audio output to {backend}");
+        let status = {
+            let mut output = self.output.lock().unwrap();
+            output.switch(backend);
+            output.status()
         };
+        if !matches!(status, OutputStatus::NoOutput) {
+            self.resume_after_output_loss();
+        }
+        let _ = self
+            .button_state_sender
+            .send(PlayerState::OutputChanged(status));
+    }
 
-        // Stop current playback (sets queue index to MAX so spawned threads
-        // break out of their loop, and stops the sink so sleep_until_end
-        // returns).
-        self.stop();
-        self.clear();
-
-        // Create a new output stream and sink for the requested device.
-        let stream = open_output_stream(device_name);
-        let new_sink = Arc::new(Player::connect_new(stream.mixer()));
-        new_sink.set_volume(volume);
-
-        // Swap the sink and stream.  Old values are dropped when the last
-        // reference (held by any still-running spawned thread) goes away.
-        *self.sink.lock().unwrap() = new_sink;
-        *self._stream.lock().unwrap() = Arc::new(stream);
+    /// Set how much the music is turned down while an announcement plays, in
+    /// dB.  See [`announcement::DEFAULT_DUCK_DB`] for the default.
+    pub fn set_duck_level(&self, db: f32) {
+        self.duck.set_duck_db(db);
+    }
 
+    /// Compress the dynamic range of the music, so quiet passages get louder
+    /// and loud ones softer.  Meant for late hours.
+    pub fn set_night_mode(&self, enabled: bool) {
         info!(
-            "Switched audio output device to {:?}",
-            device_name.unwrap_or("Default")
+            "Night mode compression {}",
+            if enabled { "on" } else { "off" }
         );
+        self.night_mode.set_enabled(enabled);
     }
 
-    /// Clone the current inner `Arc<Sink>`.  Spawned threads should use this
-    /// to obtain a handle that remains valid even if the device is switched.
-    fn current_sink(&self) -> Arc<Player> {
-        self.sink.lock().unwrap().clone()
+    /// Normalise internet streams to the given loudness in LUFS (e.g.
+    /// `-18.0`), or play them as they are with `None`.  Applies from the next
+    /// station on.
+    pub fn set_stream_loudness_target(&self, target_lufs: Option<f32>) {
+        *self.stream_loudness_target.lock().unwrap() = target_lufs;
     }
 
-    pub fn append(&self, mut sound_items: Vec<SoundItem>) {
-        self.sound_queue.lock().unwrap().append(&mut sound_items);
+    /// Set how silence in files and streams is handled.  Applies from the
+    /// next queue item on.
+    pub fn set_silence_settings(&self, settings: SilenceSettings) {
+        *self.silence.lock().unwrap() = settings;
     }
 
-    pub fn play_cd(
-        &self,
-        device: &str,
-        tracks: Vec<cd_audio::CdTrackInfo>,
-        start_index: usize,
-    ) -> Result<(), Error> {
-        self.stop();
-        self.clear();
+    /// Play files and CD tracks faster or slower without changing the pitch,
+    /// between [`MIN_SPEED`] and [`MAX_SPEED`].  Takes effect immediately;
+    /// streams always play at normal speed.
+    pub fn set_speed(&self, speed: f32) {
+        info!("Playback speed {speed:.2}");
+        self.speed.set(speed);
+    }
 
-        let player_sink = self.current_sink();
-        let title_changed_sender = self.title_changed_sender.clone();
-        let button_state_sender = self.button_state_sender.clone();
-        let device = device.to_string();
-        let queue_index = Arc::clone(&self.sound_queue_index);
+    /// Current playback speed of files and CD tracks.
+    pub fn speed(&self) -> f32 {
+        self.speed.get()
+    }
 
-        let _ = spawn(move || {
-            if let Err(error) = start_cd_playback(
-                player_sink,
-                queue_index,
-                &device,
-                tracks,
-                start_index,
-                title_changed_sender,
-                button_state_sender,
-            ) {
-                error!("Could not start CD playback: {error}");
-            }
-        });
-        Ok(())
+    /// Tap for the A-B repeat of the current file or CD track: the first
+    /// call sets A, the second sets B and the segment between them repeats
+    /// seamlessly, the third ends the repeat.  Returns the new state.
+    pub fn ab_loop_mark(&self) -> AbLoop {
+        let state = self.ab_loop.mark();
+        info!("A-B repeat: {state:?}");
+        state
     }
 
-    pub fn play(&self) -> Result<(), Error> {
-        let player_sink = self.current_sink();
-        let player_queue = Arc::clone(&self.sound_queue);
-        let queue_index = Arc::clone(&self.sound_queue_index);
-        let title_changed_sender = self.title_changed_sender.clone();
-        let button_state_sender = self.button_state_sender.clone();
+    /// End the A-B repeat; playback continues from where it is.
+    pub fn ab_loop_clear(&self) {
+        self.ab_loop.clear();
+    }
+
+    /// State of the A-B repeat.  Ends with the item it was set in.
+    pub fn ab_loop(&self) -> AbLoop {
+        self.ab_loop.get()
+    }
+
+    /// Play an announcement over the music, e.g. [`announcement::chime`].
+    /// The music is turned down until all announcements are played; the
+    /// queue keeps its position.  Announcements are played at the player
+    /// volume.
+    pub fn announce<S>(&self, source: S)
+    where
+        S: Source + Send + 'static,
+    {
+        self.announcements.set_volume(self.current_sink().volume());
+        self.duck.duck(true);
+        self.announcements.append(source);
+        self.announcements.play();
+        let announcements = Arc::clone(&self.announcements);
+        let duck = self.duck.clone();
         let _ = spawn(move || {
-            if let Err(error) = start_playback_queue(
-                player_sink,
-                player_queue,
-                queue_index,
-                title_changed_sender,
-                button_state_sender,
-            ) {
-                error!("Could not start playback: {error}");
+            announcements.sleep_until_end();
+            // Another announcement may have been queued meanwhile.
+            if announcements.empty() {
+                duck.duck(false);
             }
         });
-        Ok(())
     }
 
-    pub async fn play_stream(&self, url: &str, icon: &str) -> Result<(), Error> {
-        let client = Client::builder().request_icy_metadata().build()?;
-        let stream = HttpStream::new(client, url.parse()?).await?;
+    /// Play an audio file, e.g. a recorded message, as an announcement.
+    pub fn announce_file(&self, path: &Path) -> Result<(), Error> {
+        let source = queue::open_file(&path.to_string_lossy())?;
+        info!("Announce {}", path.display());
+        self.announce(source);
+        Ok(())
+    }
 
-        debug!("content type={:?}", stream.content_type());
-        let bitrate: u64 = stream.header("Icy-Br").unwrap_or("256").parse()?;
-        debug!("bitrate={bitrate}");
+    /// Stop playback, close the audio output and end the threads watching
+    /// it.  The player cannot be used afterwards; used when a zone is
+    /// removed.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.stop();
+        self.output.lock().unwrap().close();
+        if let Some(hw) = self.hardware_volume.lock().unwrap().take() {
+            debug!("Release hardware mixer {}", hw.config().control);
+        }
+    }
 
-        let icy_headers = IcyHeaders::parse_from_headers(stream.headers());
+    /// Current state of the audio output device.
+    pub fn output_status(&self) -> OutputStatus {
+        self.output.lock().unwrap().status()
+    }
 
-        // buffer 5 seconds of audio
-        // bitrate (in kilobits) / bits per byte * bytes per kilobyte * 5 seconds
-        let prefetch_bytes = bitrate / 8 * 1024 * 5;
-        debug!("prefetch bytes={prefetch_bytes}");
+    /// Spawn a thread that watches the audio output: playback is paused when
+    /// the device is lost and resumed when it (or the configured device, if
+    /// only the default device was available) appears.
+    fn spawn_output_monitor(&self) {
+        let player = self.clone();
+        let _ = spawn(move || {
+            loop {
+                sleep(OUTPUT_CHECK_INTERVAL);
+                if player.closed.load(Ordering::SeqCst) {
+                    break;
+                }
+                let (change, status) = {
+                    let mut output = player.output.lock().unwrap();
+                    (output.check(), output.status())
+                };
+                match change {
+                    OutputChange::None => continue,
+                    OutputChange::Lost => {
+                        let sink = player.current_sink();
+                        if !sink.is_paused() && !sink.empty() {
+                            sink.pause();
+                            player.paused_by_output_loss.store(true, Ordering::SeqCst);
+                            let _ = player.button_state_sender.send(PlayerState::Paused);
+                        }
+                    }
+                    OutputChange::Connected => player.resume_after_output_loss(),
+                    OutputChange::Changed => {}
+                }
+                let _ = player
+                    .button_state_sender
+                    .send(PlayerState::OutputChanged(status));
+            }
+        });
+    }
 
-        let reader = StreamDownload::from_stream(
-            stream,
-            // use bounded storage to keep the underlying size from growing indefinitely
-            BoundedStorageProvider::new(
-                MemoryStorageProvider,
-                // be liberal with the buffer size, you need to make sure it holds enough space to
-                // prevent any out-of-bounds reads
-                NonZeroUsize::new(512 * 1024).unwrap(),
-            ),
-            Settings::default().prefetch_bytes(prefetch_bytes),
-        )
-        .await?;
+    fn resume_after_output_loss(&self) {
+        if self.paused_by_output_loss.swap(false, Ordering::SeqCst) {
+            self.current_sink().play();
+            let _ = self.button_state_sender.send(PlayerState::Playing);
+        }
+    }
 
-        let _ = self.start_stream_playback(reader, icy_headers, icon);
+    /// Clone the current inner `Arc<Sink>`.  Spawned threads should use this
+    /// to obtain a handle that remains valid even if the device is switched.
+    fn current_sink(&self) -> Arc<Player> {
+        self.sink.lock().unwrap().clone()
+    }
 
-        Ok(())
+    pub fn append(&self, items: Vec<QueueItem>) {
+        self.sound_queue
+            .lock()
+            .unwrap()
+            .extend(items.into_iter().map(QueueEntry::from));
     }
 
-    /// Set up the ICY metadata reader and spawn a thread that drives playback
-    /// of the given internet radio / audio stream.
-    fn start_stream_playback(
+    /// Replace the queue with the audio tracks of a CD and start playback at
+    /// the `start_index`-th audio track.  The tracks are read as `read` says.
+    pub fn play_cd(
         &self,
-        reader: StreamDownload<BoundedStorageProvider<MemoryStorageProvider>>,
-        icy_headers: IcyHeaders,
-        icon: &str,
+        device: &str,
+        disc: &cd_audio::CdInfo,
+        start_index: usize,
+        read: &cd_audio::CdReadSettings,
     ) -> Result<(), Error> {
+        let items = disc
+            .tracks
+            .iter()
+            .filter(|track| track.is_audio)
+            .map(|track| QueueItem::CdTrack(CdTrackItem::new(device, disc, track.clone(), read)))
+            .collect();
         self.stop();
-        let title_changed_sender = self.title_changed_sender.clone();
-        let icon = icon.to_string();
-        let stream_reader = IcyMetadataReader::new(
-            reader,
-            // Since we requested icy metadata, the metadata interval header should be present in the
-            // response. This will allow us to parse the metadata within the stream
-            icy_headers.metadata_interval(),
-            // Parse stream metadata whenever we receive new values.
-            move |metadata| {
-                // ICY stream titles typically use the format "Artist - Title"
-                // and some stations append extra info after a single-quote
-                // (e.g. "Artist - Title'extra"). We split on "-" for
-                // artist/title and strip anything after "'" from the title.
-                let stream_title = match metadata {
-                    Ok(meta) => meta.stream_title().unwrap_or(UNKNOWN).to_string(),
-                    Err(e) => {
-                        error!("Could not get music title from stream: {}", e);
-                        UNKNOWN.to_string()
-                    }
-                };
-                debug!("Update title: {}", &stream_title);
-                let (artist, title) = stream_title
-                    .split_once("-")
-                    .unwrap_or((&stream_title, UNKNOWN));
-                let (normalized_title, _) = title.split_once("'").unwrap_or((title, ""));
-                let _ = title_changed_sender.send(TitleChanged {
-                    title: normalized_title.trim().to_string(),
-                    artist: artist.trim().to_string(),
-                    album: UNKNOWN.to_string(),
-                    cover: icon.clone(),
-                });
-            },
-        );
+        self.clear();
+        self.append(items);
+        self.play_from(start_index)?;
+        self.play()
+    }
 
+    pub fn play(&self) -> Result<(), Error> {
+        let player = self.clone();
         let player_sink = self.current_sink();
-        let button_state_sender = self.button_state_sender.clone();
+        let generation = self.playback_generation.load(Ordering::SeqCst);
         let _ = spawn(move || {
-            let _ = button_state_sender.send(PlayerState::Playing);
-            let _ = button_state_sender.send(PlayerState::StartPlaying);
-            let source = rodio::Decoder::new(stream_reader).unwrap();
-            player_sink.append(source);
-            debug!("Start Play now ...");
-            player_sink.play();
-            player_sink.sleep_until_end();
-            debug!("Play finished ...");
-
-            let _ = button_state_sender.send(PlayerState::Stopped);
+            if let Err(error) = start_playback_queue(player, player_sink, generation) {
+                error!("Could not start playback: {error}");
+            }
         });
         Ok(())
     }
 
+    /// Replace the queue with an internet radio / audio stream and play it.
+    pub fn play_stream(&self, station: StationItem) -> Result<(), Error> {
+        self.stop();
+        self.clear();
+        self.append(vec![QueueItem::Station(station)]);
+        self.play()
+    }
+
     pub fn stop(&self) {
+        self.playback_generation.fetch_add(1, Ordering::SeqCst);
+        self.paused_by_output_loss.store(false, Ordering::SeqCst);
         let mut idx = self.sound_queue_index.lock().unwrap();
         *idx = usize::MAX;
         drop(idx);
@@ -328,6 +484,8 @@ impl RodioPlayer {
     }
 
     pub fn pause(&self) {
+        // The user takes over from an automatic pause on output loss.
+        self.paused_by_output_loss.store(false, Ordering::SeqCst);
         let sink = self.current_sink();
         debug!("Pause: {}", sink.is_paused());
         if sink.is_paused() {
@@ -339,32 +497,110 @@ impl RodioPlayer {
         }
     }
 
+    /// Use the given ALSA mixer control for the volume instead of scaling
+    /// the samples in software.  `None` switches back to software volume, as
+    /// does a control that cannot be opened.  The current volume is kept.
+    pub fn use_hardware_mixer(&self, mixer: Option<HardwareMixer>) -> Result<(), Error> {
+        let mut hardware_volume = self.hardware_volume.lock().unwrap();
+        if hardware_volume.as_ref().map(HardwareVolume::config) == mixer.as_ref() {
+            return Ok(());
+        }
+        let volume = match hardware_volume.take() {
+            Some(hw) => hw.get().unwrap_or(1.0),
+            None => self.current_sink().volume(),
+        };
+        let Some(mixer) = mixer else {
+            info!("Use software volume");
+            self.current_sink().set_volume(volume);
+            return Ok(());
+        };
+        match HardwareVolume::open(&mixer) {
+            Ok(mut hw) => {
+                info!("Use hardware volume: {} on {}", mixer.control, mixer.card);
+                hw.set(volume)?;
+                self.current_sink().set_volume(1.0);
+                *hardware_volume = Some(hw);
+                Ok(())
+            }
+            Err(e) => {
+                self.current_sink().set_volume(volume);
+                Err(e)
+            }
+        }
+    }
+
     pub fn set_volume(&self, volume: f32) {
+        if let Some(hw) = self.hardware_volume.lock().unwrap().as_mut() {
+            match hw.set(volume) {
+                Ok(()) => return,
+                Err(e) => error!("Could not set hardware volume, using software volume: {e}"),
+            }
+        }
         self.current_sink().set_volume(volume);
     }
 
     pub fn get_volume(&self) -> f32 {
+        if let Some(hw) = self.hardware_volume.lock().unwrap().as_ref()
+            && let Ok(volume) = hw.get()
+        {
+            return volume;
+        }
         self.current_sink().volume()
     }
 
     pub fn mute(&self) {
-        let sink = self.current_sink();
         let mut mute_vol = self.mute_volume.lock().unwrap();
-        if sink.volume() != 0.0 {
-            *mute_vol = sink.volume();
-            sink.set_volume(0.0);
+        let volume = self.get_volume();
+        if volume != 0.0 {
+            *mute_vol = volume;
+            self.set_volume(0.0);
             let _ = self.button_state_sender.send(PlayerState::Muted);
         } else {
-            sink.set_volume(*mute_vol);
+            self.set_volume(*mute_vol);
             *mute_vol = 0.0;
             let _ = self.button_state_sender.send(PlayerState::Unmuted);
         }
     }
 
+    /// Spawn a thread that reports volume changes of the hardware mixer made
+    /// by other programs, e.g. `alsamixer` or a Snapcast client.
+    fn spawn_mixer_monitor(&self) {
+        let hardware_volume = Arc::clone(&self.hardware_volume);
+        let closed = Arc::clone(&self.closed);
+        let button_state_sender = self.button_state_sender.clone();
+        let _ = spawn(move || {
+            loop {
+                sleep(MIXER_CHECK_INTERVAL);
+                if closed.load(Ordering::SeqCst) {
+                    break;
+                }
+                let change = match hardware_volume.lock().unwrap().as_mut() {
+                    Some(hw) => hw.poll_external_change(),
+                    None => continue,
+                };
+                match change {
+                    Ok(Some(volume)) => {
+                        debug!("Hardware volume changed to {volume}");
+                        if button_state_sender
+                            .send(PlayerState::VolumeChanged(volume))
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => debug!("Could not read hardware volume: {e}"),
+                }
+            }
+        });
+    }
+
     pub fn clear(&self) {
+        self.playback_generation.fetch_add(1, Ordering::SeqCst);
         self.sound_queue.lock().unwrap().clear();
         *self.sound_queue_index.lock().unwrap() = 0;
         self.current_sink().clear();
+        let _ = self.button_state_sender.send(PlayerState::Stopped);
     }
 
     pub fn skip_next(&self) {
@@ -398,7 +634,7 @@ impl RodioPlayer {
     ///
     /// The returned index is the *next* item the playback thread will pick up,
     /// so the **currently playing** item is at `index.saturating_sub(1)`.
-    pub fn get_queue(&self) -> (Vec<SoundItem>, usize) {
+    pub fn get_queue(&self) -> (Vec<QueueEntry>, usize) {
         let queue = self.sound_queue.lock().unwrap().clone();
         let index = *self.sound_queue_index.lock().unwrap();
         (queue, index)
@@ -439,6 +675,17 @@ impl RodioPlayer {
         }
     }
 
+    /// Record the playback result of the queue entry at `index`, unless the
+    /// queue was changed in the meantime and the entry is a different one.
+    fn set_entry_error(&self, index: usize, item: &QueueItem, error: Option<String>) {
+        let mut queue = self.sound_queue.lock().unwrap();
+        if let Some(entry) = queue.get_mut(index)
+            && entry.item.location() == item.location()
+        {
+            entry.error = error;
+        }
+    }
+
     pub fn skip_previous(&self) {
         let mut idx = self.sound_queue_index.lock().unwrap();
         *idx = idx.saturating_sub(2);
@@ -447,14 +694,18 @@ impl RodioPlayer {
     }
 
     pub fn forward(&self) {
-        let s = self.current_sink();
-        let _ = s.try_seek(s.get_pos() + Duration::from_secs(5));
+        let position = self.position.get();
+        let _ = self
+            .current_sink()
+            .try_seek(position + Duration::from_secs(5));
     }
 
     pub fn rewind(&self) {
-        let s = self.current_sink();
-        if s.get_pos() > Duration::from_secs(5) {
-            let result = s.try_seek(s.get_pos() - Duration::from_secs(5));
+        let position = self.position.get();
+        if position > Duration::from_secs(5) {
+            let result = self
+                .current_sink()
+                .try_seek(position - Duration::from_secs(5));
             if let Err(error) = result {
                 debug!("Error when rewind: {error}");
             }
@@ -462,60 +713,128 @@ impl RodioPlayer {
     }
 }
 
-fn start_cd_playback(
+/// Play the queue of `player` until its end, or until playback is stopped or
+/// the queue is cleared (i.e. the playback generation differs from
+/// `generation`).
+fn start_playback_queue(
+    player: RodioPlayer,
     player_sink: Arc<Player>,
-    queue_index: Arc<Mutex<usize>>,
-    device: &str,
-    tracks: Vec<cd_audio::CdTrackInfo>,
-    start_index: usize,
-    title_changed_sender: Sender<TitleChanged>,
-    button_state_sender: Sender<PlayerState>,
+    generation: usize,
 ) -> Result<(), Error> {
+    let button_state_sender = player.button_state_sender.clone();
+    let is_current = || player.playback_generation.load(Ordering::SeqCst) == generation;
     button_state_sender.send(PlayerState::Playing)?;
-    button_state_sender.send(PlayerState::Seekable)?;
     button_state_sender.send(PlayerState::StartPlaying)?;
 
-    // Reset the queue index so stop/skip controls work
-    *queue_index.lock().unwrap() = start_index;
-
-    let audio_tracks: Vec<&cd_audio::CdTrackInfo> = tracks.iter().filter(|t| t.is_audio).collect();
-
     loop {
-        let idx = {
-            let mut idx = queue_index.lock().unwrap();
-            let current = *idx;
-            if current >= audio_tracks.len() {
+        // Lock both the queue and the index in one critical section to
+        // extract the next item (or break if we've reached the end).
+        let (index, item, cd_tracks) = {
+            let queue = player.sound_queue.lock().unwrap();
+            let mut idx = player.sound_queue_index.lock().unwrap();
+            if !is_current() {
+                // Stopped or cleared; whoever did that already reported it.
+                return Ok(());
+            }
+            if *idx >= queue.len() {
                 break;
             }
-            *idx = current + 1;
-            current
+            let index = *idx;
+            *idx += 1;
+            // CD tracks that follow each other on the disc are read as one
+            // source, so they play without a gap like the disc itself.
+            let cd_tracks = queue::gapless_cd_tracks(&queue, index);
+            (index, queue[index].item.clone(), cd_tracks)
         };
 
-        let track = audio_tracks[idx];
-
-        info!(
-            "Playing CD track {} (LBA {}–{})",
-            track.number, track.start_lba, track.end_lba
-        );
-
-        let _ = title_changed_sender.send(TitleChanged {
-            artist: String::new(),
-            album: "Audio CD".to_string(),
-            title: format!("Track {}", track.number),
-            cover: String::new(),
-        });
+        debug!("Change title: {}", item.title());
+        let _ = player.title_changed_sender.send(item.title_changed());
+        button_state_sender.send(if item.is_seekable() {
+            PlayerState::Seekable
+        } else {
+            PlayerState::Unseekable
+        })?;
+
+        player.ab_loop.clear();
+        let mut reconnects = 0;
+        loop {
+            // Reset when the item is finished, so that helper threads of the
+            // item (e.g. a station metadata poller) stop as well.
+            let active = Arc::new(AtomicBool::new(true));
+            let dead_air = Arc::new(AtomicBool::new(false));
+            let opened = if cd_tracks.is_empty() {
+                open_source(&player, &item, &active, &dead_air).map(|source| (source, None))
+            } else {
+                open_cd_source(&player, &cd_tracks).map(|(source, track)| (source, Some(track)))
+            };
+            if !is_current() {
+                // Stopped or replaced while opening, which can take a while.
+                debug!("Dropping {}, playback moved on", item.location());
+                active.store(false, Ordering::SeqCst);
+                return Ok(());
+            }
+            let (source, current_track) = match opened {
+                Ok(opened) => {
+                    player.set_entry_error(index, &item, None);
+                    opened
+                }
+                Err(e) => {
+                    // Skip the item instead of giving up on the whole queue.
+                    active.store(false, Ordering::SeqCst);
+                    let reason = format!("{e:#}");
+                    error!("Could not play {}: {reason}", item.location());
+                    player.set_entry_error(index, &item, Some(reason.clone()));
+                    button_state_sender.send(PlayerState::Failed(PlaybackError {
+                        index,
+                        name: item.display_name(),
+                        reason,
+                    }))?;
+                    break;
+                }
+            };
+            {
+                // `stop()` and `clear()` move on the generation before they
+                // take the index lock and empty the sink, so a source
+                // appended while holding the lock is removed by them.
+                let _idx = player.sound_queue_index.lock().unwrap();
+                if !is_current() {
+                    active.store(false, Ordering::SeqCst);
+                    return Ok(());
+                }
+                // Compressed ahead of the volume of the sink, so the makeup
+                // gain never makes the music louder than the volume allows.
+                player_sink.append(Compressor::new(source, player.night_mode.clone()));
+            }
+            debug!("Start Play now ...");
+            player_sink.play();
+            match current_track {
+                Some(current_track) if cd_tracks.len() > 1 => follow_cd_tracks(
+                    &player,
+                    &player_sink,
+                    index,
+                    &cd_tracks,
+                    &current_track,
+                    &is_current,
+                ),
+                _ => player_sink.sleep_until_end(),
+            }
+            active.store(false, Ordering::SeqCst);
+            debug!("Play finished ...");
 
-        match cd_audio::open_track(device, track) {
-            Ok(source) => {
-                player_sink.append(source);
-                debug!("Start CD track {} playback...", track.number);
-                player_sink.play();
-                player_sink.sleep_until_end();
-                debug!("CD track {} finished", track.number);
+            if !dead_air.load(Ordering::SeqCst) || !is_current() {
+                break;
             }
-            Err(e) => {
-                error!("Failed to open CD track {}: {e}", track.number);
+            if reconnects < DEAD_AIR_RECONNECTS {
+                reconnects += 1;
+                warn!("No sound from {}, reconnecting", item.location());
+                continue;
             }
+            warn!("Still no sound from {}, giving up", item.location());
+            let is_last = index + 1 >= player.sound_queue.lock().unwrap().len();
+            if let (QueueItem::Station(station), true) = (&item, is_last) {
+                button_state_sender.send(PlayerState::DeadAir(station.clone()))?;
+            }
+            break;
         }
     }
 
@@ -524,96 +843,347 @@ fn start_cd_playback(
     Ok(())
 }
 
-/// Open a [`MixerDeviceSink`] for the device identified by `device_name`.
-///
-/// When the name is `None`, empty, or `"Default"` the system default device
-/// is used.  If a specific device cannot be found, falls back to the default.
-fn open_output_stream(device_name: Option<&str>) -> MixerDeviceSink {
-    let use_default = match device_name {
-        None => true,
-        Some(name) => name.is_empty() || name == "Default",
+/// Open the audio source of a queue item.  A station that stays silent for
+/// too long ends and sets `dead_air`.
+fn open_source(
+    player: &RodioPlayer,
+    item: &QueueItem,
+    active: &Arc<AtomicBool>,
+    dead_air: &Arc<AtomicBool>,
+) -> Result<queue::BoxedSource, Error> {
+    let silence = player.silence.lock().unwrap().clone();
+    // Practising controls, for everything but streams.
+    let stretch = |source: queue::BoxedSource| -> queue::BoxedSource {
+        let source = TimeStretch::new(source, player.speed.clone(), player.position.clone());
+        Box::new(AbLoopSource::new(
+            source,
+            player.ab_loop.clone(),
+            player.position.clone(),
+        ))
+    };
+    let file = |source: queue::BoxedSource| -> queue::BoxedSource {
+        if silence.trim_files {
+            stretch(Box::new(TrimSilence::new(source, &silence)))
+        } else {
+            stretch(source)
+        }
     };
+    match item {
+        QueueItem::File(sound_item) => queue::open_file(&sound_item.path).map(file),
+        QueueItem::HttpFile(sound_item) => queue::open_http_file(&sound_item.path).map(file),
+        QueueItem::DiscFile(item) => queue::open_disc_file(item).map(file),
+        QueueItem::Station(station) => {
+            let source = queue::open_station(
+                &player.runtime,
+                station,
+                *player.stream_loudness_target.lock().unwrap(),
+                Arc::clone(active),
+                player.title_changed_sender.clone(),
+            )?;
+            Ok(match silence.dead_air {
+                Some(limit) => Box::new(DeadAirDetector::new(
+                    source,
+                    silence.threshold_db,
+                    limit,
+                    Arc::clone(dead_air),
+                )),
+                None => source,
+            })
+        }
+        QueueItem::CdTrack(track) => {
+            open_cd_source(player, std::slice::from_ref(track)).map(|(source, _)| source)
+        }
+    }
+}
 
-    if !use_default {
-        let requested = device_name.unwrap();
-        if let Ok(devices) = cpal::default_host().output_devices() {
-            for device in devices {
-                if let Ok(name) = device.name() {
-                    if name == requested {
-                        match DeviceSinkBuilder::from_device(device).and_then(|b| b.open_stream())
-                        {
-                            Ok(stream) => {
-                                info!("Opened audio output device: {requested}");
-                                return stream;
-                            }
-                            Err(e) => {
-                                warn!(
-                                    "Failed to open audio device '{requested}': {e}, \
-                                     falling back to default"
-                                );
-                            }
-                        }
-                        break;
-                    }
-                }
+/// Open consecutive CD tracks as one source.  The returned index tells which
+/// of them is playing.
+fn open_cd_source(
+    player: &RodioPlayer,
+    tracks: &[CdTrackItem],
+) -> Result<(queue::BoxedSource, Arc<AtomicUsize>), Error> {
+    if let (Some(first), Some(last)) = (tracks.first(), tracks.last()) {
+        info!(
+            "Playing CD tracks {}–{} (LBA {}–{})",
+            first.track.number, last.track.number, first.track.start_lba, last.track.end_lba
+        );
+    }
+    let (source, current_track) = queue::open_cd_tracks(tracks)?;
+    let source = TimeStretch::new(source, player.speed.clone(), player.position.clone());
+    Ok((
+        Box::new(AbLoopSource::new(
+            source,
+            player.ab_loop.clone(),
+            player.position.clone(),
+        )),
+        current_track,
+    ))
+}
+
+/// Wait until the CD tracks played as one source from queue position
+/// `index` on have ended, and announce every track when its first sample is
+/// played, as if it were played on its own.
+fn follow_cd_tracks(
+    player: &RodioPlayer,
+    sink: &Player,
+    index: usize,
+    tracks: &[CdTrackItem],
+    current_track: &AtomicUsize,
+    is_current: &impl Fn() -> bool,
+) {
+    let mut playing = 0;
+    while !sink.empty() && is_current() {
+        sleep(TRACK_CHANGE_CHECK_INTERVAL);
+        let track = current_track.load(Ordering::SeqCst);
+        if track == playing {
+            continue;
+        }
+        {
+            let mut idx = player.sound_queue_index.lock().unwrap();
+            if *idx != index + playing + 1 {
+                // The queue was changed meanwhile; the index no longer
+                // belongs to the tracks of the source.
+                debug!("Queue changed, no longer following CD tracks");
+                drop(idx);
+                sink.sleep_until_end();
+                return;
             }
+            *idx = index + track + 1;
         }
-        warn!("Audio device '{requested}' not found, falling back to default");
+        playing = track;
+        let item = QueueItem::CdTrack(tracks[track].clone());
+        debug!("Change title: {}", item.title());
+        player.set_entry_error(index + track, &item, None);
+        let _ = player.title_changed_sender.send(item.title_changed());
     }
-
-    DeviceSinkBuilder::open_default_sink().expect("Failed to open default audio output stream")
 }
 
-fn start_playback_queue(
-    player_sink: Arc<Player>,
-    player_queue: Arc<Mutex<Vec<SoundItem>>>,
-    queue_index: Arc<Mutex<usize>>,
-    title_changed_sender: Sender<TitleChanged>,
-    button_state_sender: Sender<PlayerState>,
-) -> Result<(), Error> {
-    button_state_sender.send(PlayerState::Playing)?;
-    button_state_sender.send(PlayerState::Seekable)?;
-    button_state_sender.send(PlayerState::StartPlaying)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcm_output::{PcmWriter, WavWriter};
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc::{self, Receiver};
+    use std::time::Instant;
 
-    loop {
-        // Lock both the queue and the index in one critical section to
-        // extract the next item (or break if we've reached the end).
-        let sound_item = {
-            let queue = player_queue.lock().unwrap();
-            let mut idx = queue_index.lock().unwrap();
-            if *idx >= queue.len() {
-                break;
+    const EVENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rodio-player-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write a short stereo sine tone as WAV file.
+    fn write_tone(path: &Path) {
+        write_tone_frames(path, 44_100 / 5);
+    }
+
+    fn write_tone_frames(path: &Path, frames: usize) {
+        let mut writer = WavWriter::create(path, 2, 44_100).unwrap();
+        let mut pcm = Vec::new();
+        for i in 0..frames {
+            let value = ((i as f32 * 0.05).sin() * 8000.0) as i16;
+            pcm.extend_from_slice(&value.to_le_bytes());
+            pcm.extend_from_slice(&value.to_le_bytes());
+        }
+        writer.write(&pcm).unwrap();
+    }
+
+    fn file_item(path: &Path) -> QueueItem {
+        QueueItem::from(SoundItem {
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            title: path.file_name().unwrap().to_string_lossy().to_string(),
+            path: path.to_string_lossy().to_string(),
+            cover: String::new(),
+        })
+    }
+
+    fn new_player(
+        backend: OutputBackend,
+    ) -> (
+        RodioPlayer,
+        Receiver<TitleChanged>,
+        Receiver<PlayerState>,
+        tokio::runtime::Runtime,
+    ) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let (title_tx, title_rx) = mpsc::channel();
+        let (state_tx, state_rx) = mpsc::channel();
+        let player = RodioPlayer::new(title_tx, state_tx, backend, runtime.handle().clone());
+        (player, title_rx, state_rx, runtime)
+    }
+
+    /// Wait for the end of the queue and return all failures on the way.
+    fn wait_until_stopped(states: &Receiver<PlayerState>) -> Vec<PlaybackError> {
+        let deadline = Instant::now() + EVENT_TIMEOUT;
+        let mut failures = Vec::new();
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match states.recv_timeout(timeout).expect("playback did not stop") {
+                PlayerState::Stopped => return failures,
+                PlayerState::Failed(failure) => failures.push(failure),
+                _ => {}
             }
-            let item = queue[*idx].clone();
-            *idx += 1;
-            item
-        };
+        }
+    }
 
-        debug!("Change title: {}", &sound_item.title);
-        let _ = title_changed_sender.send(TitleChanged {
-            artist: sound_item.artist.clone(),
-            album: sound_item.album.clone(),
-            title: sound_item.title.clone(),
-            cover: sound_item.cover.clone(),
+    #[test]
+    fn test_queue_skips_broken_items() {
+        let dir = test_dir("skip");
+        let tone = dir.join("tone.wav");
+        write_tone(&tone);
+
+        let (player, titles, states, _runtime) = new_player(OutputBackend::Null);
+        assert_eq!(
+            player.output_status(),
+            OutputStatus::Connected("null output".to_string())
+        );
+        player.append(vec![file_item(&dir.join("missing.wav")), file_item(&tone)]);
+        player.play().unwrap();
+
+        let failures = wait_until_stopped(&states);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].index, 0);
+        assert_eq!(failures[0].name, "missing.wav");
+
+        let played: Vec<String> = titles.try_iter().map(|t| t.title).collect();
+        assert_eq!(played, vec!["missing.wav", "tone.wav"]);
+        let (queue, _) = player.get_queue();
+        assert!(queue[0].error.is_some());
+        assert!(queue[1].error.is_none());
+    }
+
+    #[test]
+    fn test_source_opened_after_stop_is_dropped() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let dir = test_dir("stale");
+        let tone = dir.join("tone.wav");
+        write_tone_frames(&tone, 44_100 * 2);
+        let wav = std::fs::read(&tone).unwrap();
+
+        // A server that only answers once playback was stopped.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/tone.wav", listener.local_addr().unwrap());
+        let (connected_tx, connected_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            connected_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                wav.len()
+            );
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(&wav).unwrap();
         });
-        debug!("Open file: {}", &sound_item.path);
-        let file = std::fs::File::open(&sound_item.path)?;
-        let metadata = file.metadata()?;
-        let source = rodio::Decoder::builder()
-            .with_seekable(true)
-            .with_byte_len(metadata.len())
-            .with_data(file)
-            .build()?;
-        let duration = source.total_duration().unwrap_or(Duration::from_secs(0));
-        debug!("Duration: {:?}", duration);
-        player_sink.append(source);
-        debug!("Start Play now ...");
-        player_sink.play();
-        player_sink.sleep_until_end();
-        debug!("Play finished ...");
+
+        let (player, _titles, _states, _runtime) = new_player(OutputBackend::Null);
+        player.append(vec![QueueItem::from(SoundItem {
+            artist: String::new(),
+            album: String::new(),
+            title: "tone".to_string(),
+            path: url,
+            cover: String::new(),
+        })]);
+        player.play().unwrap();
+        connected_rx.recv_timeout(EVENT_TIMEOUT).unwrap();
+        player.stop();
+        release_tx.send(()).unwrap();
+        server.join().unwrap();
+
+        // The download finished after the stop; it must not be played.
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(player.current_sink().empty());
     }
 
-    button_state_sender.send(PlayerState::Stopped)?;
-    button_state_sender.send(PlayerState::Unseekable)?;
-    Ok(())
+    #[test]
+    fn test_night_mode_stays_below_volume() {
+        let dir = test_dir("night");
+        let loud = dir.join("loud.wav");
+        let recording = dir.join("recording.wav");
+        // A full-scale square wave
+        let mut writer = WavWriter::create(&loud, 2, 44_100).unwrap();
+        let pcm: Vec<u8> = (0..44_100 / 5)
+            .flat_map(|i| {
+                let value = if i / 50 % 2 == 0 { i16::MAX } else { -i16::MAX };
+                [value.to_le_bytes(), value.to_le_bytes()].concat()
+            })
+            .collect();
+        writer.write(&pcm).unwrap();
+        drop(writer);
+
+        let (player, _titles, states, _runtime) =
+            new_player(OutputBackend::WavFile(recording.clone()));
+        player.set_volume(0.1);
+        player.set_night_mode(true);
+        player.append(vec![file_item(&loud)]);
+        player.play().unwrap();
+        assert!(wait_until_stopped(&states).is_empty());
+        player.switch_output(OutputBackend::Null);
+        std::thread::sleep(Duration::from_millis(200));
+
+        let data = std::fs::read(&recording).unwrap();
+        let peak = data[44..]
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]).unsigned_abs())
+            .max()
+            .unwrap();
+        assert!(peak > 0);
+        assert!(peak as f32 <= 0.1 * i16::MAX as f32, "{peak}");
+    }
+
+    #[test]
+    fn test_announcement_ducks_music() {
+        let dir = test_dir("announce");
+        let tone = dir.join("tone.wav");
+        write_tone(&tone);
+
+        let (player, _titles, states, _runtime) = new_player(OutputBackend::Null);
+        player.append(vec![file_item(&tone)]);
+        player.play().unwrap();
+        player.announce(announcement::beep());
+        assert!(player.duck.is_ducked());
+
+        // The music goes on underneath the announcement.
+        assert!(wait_until_stopped(&states).is_empty());
+        let deadline = Instant::now() + EVENT_TIMEOUT;
+        while player.duck.is_ducked() {
+            assert!(Instant::now() < deadline, "music was not restored");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(player.announcements.empty());
+    }
+
+    #[test]
+    fn test_wav_file_output() {
+        let dir = test_dir("wav");
+        let tone = dir.join("tone.wav");
+        let recording = dir.join("recording.wav");
+        write_tone(&tone);
+
+        let (player, _titles, states, _runtime) =
+            new_player(OutputBackend::WavFile(recording.clone()));
+        player.append(vec![file_item(&tone)]);
+        player.play().unwrap();
+        assert!(wait_until_stopped(&states).is_empty());
+
+        // Closing the output finishes the WAV header.
+        player.switch_output(OutputBackend::Null);
+        std::thread::sleep(Duration::from_millis(200));
+        let data = std::fs::read(&recording).unwrap();
+        assert_eq!(&data[0..4], b"RIFF");
+        let data_len = u32::from_le_bytes(data[40..44].try_into().unwrap());
+        assert!(data_len as usize + 44 <= data.len());
+        // At least the 0.2 s tone has been recorded.
+        assert!(data_len >= 44_100 / 5 * 4);
+    }
 }
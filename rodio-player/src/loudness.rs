@@ -0,0 +1,465 @@
+//! Loudness normalisation for internet radio.
+//!
+//! Stations are mastered at very different levels.  A [`LoudnessNormalizer`]
+//! measures the loudness of a stream the way ITU-R BS.1770 does (K-weighted
+//! mean square, in LUFS) and slowly moves its gain towards the configured
+//! target.  The gain changes by at most a few dB per second and ignores
+//! silence and very quiet passages, so it levels out stations without
+//! pumping the dynamics of the music.  Peaks that the gain would push past
+//! full scale are caught by a lookahead limiter instead of being clipped.
+
+use std::collections::VecDeque;
+use std::num::NonZero;
+use std::time::Duration;
+
+use rodio::Source;
+
+/// Length of a measurement block.
+const BLOCK: Duration = Duration::from_millis(100);
+
+/// Time constant of the loudness average.
+const LOUDNESS_WINDOW: Duration = Duration::from_secs(10);
+
+/// Blocks quieter than this (in LUFS) are not measured, so that pauses and
+/// fade-outs don't turn the gain up.
+const GATE_LUFS: f32 = -50.0;
+
+/// How fast the gain goes up, in dB per second.
+const GAIN_RISE_DB_PER_SEC: f32 = 1.0;
+
+/// How fast the gain goes down, in dB per second.
+const GAIN_FALL_DB_PER_SEC: f32 = 3.0;
+
+/// The normalizer never changes the level by more than this.
+const MAX_GAIN_DB: f32 = 12.0;
+
+/// Samples between two computations of the gain; in between it is
+/// interpolated.
+const GAIN_RAMP_SAMPLES: usize = 64;
+
+/// Highest level of the output: -1 dBFS.
+const LIMITER_CEILING: f32 = 0.891;
+
+/// How far the limiter looks ahead, i.e. how long it takes to turn down
+/// for a peak.
+const LIMITER_LOOKAHEAD: Duration = Duration::from_millis(5);
+
+/// Time the limiter takes to come back from full reduction.
+const LIMITER_RELEASE: Duration = Duration::from_millis(200);
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Biquad filter in direct form I.
+#[derive(Clone)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl Biquad {
+    fn new(b: [f32; 3], a: [f32; 3]) -> Self {
+        Self {
+            b: b.map(|b| b / a[0]),
+            a: [a[1] / a[0], a[2] / a[0]],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    /// First stage of the K-weighting: a high shelf modelling the head.
+    fn high_shelf(sample_rate: f32) -> Self {
+        let (f0, gain_db, q) = (1_681.974_5, 3.999_844, 0.707_175_2);
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * std::f32::consts::PI * f0 / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let sqrt_a = 2.0 * a.sqrt() * alpha;
+        Self::new(
+            [
+                a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a),
+            ],
+            [
+                (a + 1.0) - (a - 1.0) * cos + sqrt_a,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - sqrt_a,
+            ],
+        )
+    }
+
+    /// Second stage of the K-weighting: a high pass.
+    fn high_pass(sample_rate: f32) -> Self {
+        let (f0, q) = (38.135_47, 0.500_327);
+        let w0 = 2.0 * std::f32::consts::PI * f0 / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        Self::new(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// Measures the loudness of interleaved samples in blocks of [`BLOCK`].
+pub(crate) struct LoudnessMeter {
+    /// K-weighting filters of each channel.
+    filters: Vec<(Biquad, Biquad)>,
+    channel: usize,
+    /// Sum of the squared, weighted samples of the current block.
+    energy: f32,
+    samples: usize,
+    block_samples: usize,
+}
+
+impl LoudnessMeter {
+    pub(crate) fn new(channels: NonZero<u16>, sample_rate: NonZero<u32>) -> Self {
+        let rate = sample_rate.get() as f32;
+        let channels = channels.get() as usize;
+        Self {
+            filters: vec![(Biquad::high_shelf(rate), Biquad::high_pass(rate)); channels],
+            channel: 0,
+            energy: 0.0,
+            samples: 0,
+            block_samples: (BLOCK.as_secs_f32() * rate) as usize * channels,
+        }
+    }
+
+    /// Add a sample.  Returns the loudness of the block in LUFS when the
+    /// sample completes one.
+    pub(crate) fn add(&mut self, sample: f32) -> Option<f32> {
+        let (shelf, high_pass) = &mut self.filters[self.channel];
+        let weighted = high_pass.process(shelf.process(sample));
+        self.channel = (self.channel + 1) % self.filters.len();
+        self.energy += weighted * weighted;
+        self.samples += 1;
+        if self.samples < self.block_samples {
+            return None;
+        }
+        // Mean square of each channel, summed over the channels.
+        let frames = (self.samples / self.filters.len()) as f32;
+        let loudness = -0.691 + 10.0 * (self.energy / frames).max(1e-12).log10();
+        self.energy = 0.0;
+        self.samples = 0;
+        Some(loudness)
+    }
+}
+
+/// Keeps interleaved samples below [`LIMITER_CEILING`].  Samples are
+/// delayed by the lookahead: the lowest gain any sample within the
+/// lookahead needs is held, and averaged over the lookahead, so the gain
+/// goes down smoothly and is low enough when the peak comes out.
+struct Limiter {
+    /// Samples of the lookahead, not yet played.
+    delay: VecDeque<f32>,
+    /// Index and needed gain of the recent samples that can still be the
+    /// lowest within the lookahead, increasing from the front.
+    minima: VecDeque<(u64, f32)>,
+    /// The held gains of the lookahead, and their sum.
+    held: VecDeque<f32>,
+    held_sum: f64,
+    /// Held gain, slowly coming back after a peak.
+    gain: f32,
+    release_per_sample: f32,
+    index: u64,
+    len: usize,
+}
+
+impl Limiter {
+    fn new(channels: NonZero<u16>, sample_rate: NonZero<u32>) -> Self {
+        let samples_per_sec = channels.get() as f32 * sample_rate.get() as f32;
+        let frames = (LIMITER_LOOKAHEAD.as_secs_f32() * sample_rate.get() as f32) as usize;
+        let len = frames.max(1) * channels.get() as usize;
+        Self {
+            delay: VecDeque::with_capacity(len),
+            minima: VecDeque::new(),
+            held: VecDeque::from(vec![1.0; len]),
+            held_sum: len as f64,
+            gain: 1.0,
+            release_per_sample: 1.0 / (LIMITER_RELEASE.as_secs_f32() * samples_per_sec),
+            index: 0,
+            len,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.delay.is_empty()
+    }
+
+    /// Add a sample, or nothing at the end of the source, and take the
+    /// sample that leaves the lookahead, if any.
+    fn process(&mut self, sample: Option<f32>) -> Option<f32> {
+        let needed = match sample {
+            Some(sample) if sample.abs() > LIMITER_CEILING => LIMITER_CEILING / sample.abs(),
+            _ => 1.0,
+        };
+        self.delay.extend(sample);
+        while self.minima.back().is_some_and(|&(_, gain)| gain >= needed) {
+            self.minima.pop_back();
+        }
+        self.minima.push_back((self.index, needed));
+        while self
+            .minima
+            .front()
+            .is_some_and(|&(index, _)| index + self.len as u64 <= self.index)
+        {
+            self.minima.pop_front();
+        }
+        self.index += 1;
+        let lowest = self.minima.front().map_or(1.0, |&(_, gain)| gain);
+        self.gain = (self.gain + self.release_per_sample).min(lowest);
+        self.held.push_back(self.gain);
+        self.held_sum += self.gain as f64 - self.held.pop_front().unwrap_or(1.0) as f64;
+        if sample.is_some() && self.delay.len() < self.len {
+            return None;
+        }
+        let gain = (self.held_sum / self.len as f64) as f32;
+        self.delay.pop_front().map(|sample| sample * gain)
+    }
+}
+
+/// Turns a stream up or down to a target loudness, plus a fixed offset.
+pub(crate) struct LoudnessNormalizer<S> {
+    source: S,
+    meter: LoudnessMeter,
+    target_lufs: f32,
+    offset_db: f32,
+    /// Average loudness of the stream so far, as mean square energy.
+    average_energy: Option<f32>,
+    /// Weight of a new block in the average.
+    average_coeff: f32,
+    gain_db: f32,
+    target_gain_db: f32,
+    gain: f32,
+    /// Change of `gain` per sample until the next computation.
+    gain_step: f32,
+    /// Samples until the gain is computed again.
+    ramp_left: usize,
+    rise_per_ramp: f32,
+    fall_per_ramp: f32,
+    limiter: Limiter,
+    ended: bool,
+}
+
+impl<S: Source> LoudnessNormalizer<S> {
+    /// Normalise `source` to `target_lufs`, then change the level by
+    /// `offset_db`.
+    pub(crate) fn new(source: S, target_lufs: f32, offset_db: f32) -> Self {
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        let samples_per_sec = channels.get() as f32 * sample_rate.get() as f32;
+        let ramp_secs = GAIN_RAMP_SAMPLES as f32 / samples_per_sec;
+        Self {
+            meter: LoudnessMeter::new(channels, sample_rate),
+            source,
+            target_lufs,
+            offset_db,
+            average_energy: None,
+            average_coeff: BLOCK.as_secs_f32() / LOUDNESS_WINDOW.as_secs_f32(),
+            gain_db: offset_db,
+            target_gain_db: offset_db,
+            gain: db_to_gain(offset_db),
+            gain_step: 0.0,
+            ramp_left: 0,
+            rise_per_ramp: GAIN_RISE_DB_PER_SEC * ramp_secs,
+            fall_per_ramp: GAIN_FALL_DB_PER_SEC * ramp_secs,
+            limiter: Limiter::new(channels, sample_rate),
+            ended: false,
+        }
+    }
+
+    /// Take the loudness of a finished block into account.
+    fn update_target(&mut self, block_lufs: f32) {
+        if block_lufs < GATE_LUFS {
+            return;
+        }
+        let energy = 10f32.powf((block_lufs + 0.691) / 10.0);
+        let average = match self.average_energy {
+            Some(average) => average + self.average_coeff * (energy - average),
+            None => energy,
+        };
+        self.average_energy = Some(average);
+        let loudness = -0.691 + 10.0 * average.log10();
+        self.target_gain_db =
+            (self.target_lufs - loudness).clamp(-MAX_GAIN_DB, MAX_GAIN_DB) + self.offset_db;
+    }
+
+    /// Move the gain a sample towards its target.  Every
+    /// [`GAIN_RAMP_SAMPLES`] the gain at the end of the next ramp is
+    /// computed, and the gain goes there in equal steps.
+    fn step_gain(&mut self) {
+        if self.ramp_left == 0 {
+            self.ramp_left = GAIN_RAMP_SAMPLES;
+            self.gain_step = 0.0;
+            if self.gain_db == self.target_gain_db {
+                return;
+            }
+            self.gain_db = if self.gain_db < self.target_gain_db {
+                (self.gain_db + self.rise_per_ramp).min(self.target_gain_db)
+            } else {
+                (self.gain_db - self.fall_per_ramp).max(self.target_gain_db)
+            };
+            self.gain_step = (db_to_gain(self.gain_db) - self.gain) / GAIN_RAMP_SAMPLES as f32;
+        }
+        self.ramp_left -= 1;
+        self.gain += self.gain_step;
+    }
+}
+
+impl<S: Source> Iterator for LoudnessNormalizer<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        loop {
+            let sample = if self.ended { None } else { self.source.next() };
+            let Some(sample) = sample else {
+                self.ended = true;
+                if self.limiter.is_empty() {
+                    return None;
+                }
+                return self.limiter.process(None);
+            };
+            if let Some(block_lufs) = self.meter.add(sample) {
+                self.update_target(block_lufs);
+            }
+            self.step_gain();
+            if let Some(sample) = self.limiter.process(Some(sample * self.gain)) {
+                return Some(sample);
+            }
+        }
+    }
+}
+
+impl<S: Source> Source for LoudnessNormalizer<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.source.current_span_len()
+    }
+
+    fn channels(&self) -> NonZero<u16> {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> NonZero<u32> {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const RATE: u32 = 44_100;
+
+    /// A stereo 1 kHz sine with the given amplitude.
+    fn sine(amplitude: f32, duration: Duration) -> SamplesBuffer {
+        let frames = (duration.as_secs_f32() * RATE as f32) as usize;
+        let samples = (0..frames)
+            .flat_map(|n| {
+                let value = amplitude
+                    * (2.0 * std::f32::consts::PI * 1000.0 * n as f32 / RATE as f32).sin();
+                [value, value]
+            })
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(
+            NonZero::new(2).unwrap(),
+            NonZero::new(RATE).unwrap(),
+            samples,
+        )
+    }
+
+    /// Loudness of the last block of `samples`.
+    fn loudness(samples: impl Iterator<Item = f32>) -> f32 {
+        let mut meter = LoudnessMeter::new(NonZero::new(2).unwrap(), NonZero::new(RATE).unwrap());
+        samples
+            .filter_map(|sample| meter.add(sample))
+            .last()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_meter_full_scale_sine() {
+        // A full scale 1 kHz sine on both channels is about 0 LUFS.
+        let lufs = loudness(sine(1.0, Duration::from_secs(1)));
+        assert!(lufs.abs() < 0.5, "{lufs}");
+    }
+
+    #[test]
+    fn test_quiet_and_loud_streams_reach_target() {
+        for amplitude in [0.05, 0.3] {
+            let normalizer =
+                LoudnessNormalizer::new(sine(amplitude, Duration::from_secs(30)), -18.0, 0.0);
+            let lufs = loudness(normalizer);
+            assert!((lufs + 18.0).abs() < 1.0, "{amplitude}: {lufs}");
+        }
+    }
+
+    #[test]
+    fn test_gain_changes_slowly() {
+        let mut normalizer =
+            LoudnessNormalizer::new(sine(0.03, Duration::from_secs(2)), -18.0, 0.0);
+        normalizer.by_ref().for_each(drop);
+        assert!(normalizer.gain_db <= 2.0 * GAIN_RISE_DB_PER_SEC + 0.01);
+    }
+
+    #[test]
+    fn test_boosted_peaks_are_limited() {
+        // A quiet sine with a loud click every half second is turned up a
+        // lot, which would push the clicks far past full scale.
+        let mut samples: Vec<f32> = sine(0.02, Duration::from_secs(30)).collect();
+        for click in samples.chunks_mut(RATE as usize) {
+            click[..2].fill(0.9);
+        }
+        let len = samples.len();
+        let source = SamplesBuffer::new(
+            NonZero::new(2).unwrap(),
+            NonZero::new(RATE).unwrap(),
+            samples,
+        );
+        let mut normalizer = LoudnessNormalizer::new(source, -18.0, 0.0);
+        let output: Vec<f32> = normalizer.by_ref().collect();
+        assert!(normalizer.gain_db > 6.0, "{}", normalizer.gain_db);
+        assert_eq!(output.len(), len);
+        let peak = output
+            .iter()
+            .fold(0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak <= LIMITER_CEILING + 1e-4, "{peak}");
+        // The clicks are turned down, not cut off.
+        let last_click = &output[len - RATE as usize..][..2];
+        assert!(
+            last_click.iter().all(|&sample| sample > 0.8),
+            "{last_click:?}"
+        );
+    }
+
+    #[test]
+    fn test_silence_keeps_gain() {
+        let mut normalizer = LoudnessNormalizer::new(sine(0.0, Duration::from_secs(5)), -18.0, 0.0);
+        normalizer.by_ref().for_each(drop);
+        assert_eq!(normalizer.gain_db, 0.0);
+    }
+
+    #[test]
+    fn test_offset_is_added() {
+        let normalizer = LoudnessNormalizer::new(sine(0.1, Duration::from_secs(30)), -18.0, -6.0);
+        let lufs = loudness(normalizer);
+        assert!((lufs + 24.0).abs() < 1.0, "{lufs}");
+    }
+}
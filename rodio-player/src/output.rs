@@ -0,0 +1,529 @@
+//! Connection between the player and the audio output.
+//!
+//! The player does not write to the device directly.  It plays into an
+//! internal mixer whose output is shared with whatever output is currently
+//! open.  This way the device can be opened late (e.g. a USB DAC that is
+//! plugged in after start), lost during playback or replaced by another one
+//! without touching the player or its queue: while no output pulls samples,
+//! playback simply does not advance.
+//!
+//! Besides sound card devices, the output can be a named pipe, a WAV file or
+//! a null sink, see [`OutputBackend`].  Several sound cards can play at once,
+//! see [`crate::fan_out`].
+
+use std::fmt;
+use std::num::NonZero;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Error, anyhow};
+use rodio::cpal;
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::mixer::{Mixer, MixerSource};
+use rodio::{DeviceSinkBuilder, MixerDeviceSink, Source};
+use tracing::{debug, error, info, warn};
+
+use crate::fan_out::FanOut;
+use crate::pcm_output::{self, NullWriter, PcmWriter, PipeWriter, Pump, WavWriter};
+
+/// Channel count of the internal mixer.
+pub(crate) const CHANNELS: u16 = 2;
+
+/// Sample rate of the internal mixer.  Device streams resample if needed.
+pub(crate) const SAMPLE_RATE: u32 = 44_100;
+
+/// Samples taken from the mixer at a time by an output, a whole number of
+/// frames.
+pub(crate) const BLOCK_SAMPLES: usize = 512;
+
+/// Name under which the system default device is configured and listed.
+pub(crate) const DEFAULT_DEVICE: &str = "Default";
+
+/// Where the player's audio goes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputBackend {
+    /// A sound card device by name, `None` for the system default device.
+    Device(Option<String>),
+    /// Several sound card devices playing the same audio at once.
+    Devices(Vec<DeviceOutput>),
+    /// Raw PCM (16 bit little endian, stereo, 44.1 kHz) into a named pipe,
+    /// e.g. for a Snapcast server.  The pipe is created if it is missing.
+    Pipe(PathBuf),
+    /// Record to a WAV file.
+    WavFile(PathBuf),
+    /// Discard the audio, but still play in real time.  Useful for headless
+    /// test runs.
+    Null,
+}
+
+/// One of several sound card devices of [`OutputBackend::Devices`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceOutput {
+    /// Device name, `None` for the system default device.
+    pub name: Option<String>,
+    /// Level of this device relative to the player volume, in dB.
+    pub trim_db: f32,
+    /// Played this much later than on the other devices, e.g. to line up a
+    /// Bluetooth speaker with a wired one.
+    pub delay: Duration,
+}
+
+impl DeviceOutput {
+    fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(DEFAULT_DEVICE)
+    }
+}
+
+/// Normalize the system default device to `None`.
+fn device_name(name: Option<String>) -> Option<String> {
+    name.filter(|name| !name.is_empty() && name != DEFAULT_DEVICE)
+}
+
+impl fmt::Display for OutputBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputBackend::Device(name) => f.write_str(name.as_deref().unwrap_or(DEFAULT_DEVICE)),
+            OutputBackend::Devices(devices) => f.write_str(&display_names(devices.iter())),
+            OutputBackend::Pipe(path) => write!(f, "pipe {}", path.display()),
+            OutputBackend::WavFile(path) => write!(f, "WAV file {}", path.display()),
+            OutputBackend::Null => f.write_str("null output"),
+        }
+    }
+}
+
+/// State of the audio output.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputStatus {
+    /// Audio is played on the configured device.
+    Connected(String),
+    /// The configured device is not available, audio is played on the
+    /// system default device until it appears.
+    Fallback(String),
+    /// The configured device was lost during playback.  Playback is paused
+    /// until it is back.
+    Lost(String),
+    /// Only some of several devices are available; audio is played on
+    /// those until the others appear.
+    Partial { playing: String, missing: String },
+    /// No output device could be opened at all.
+    NoOutput,
+}
+
+fn display_names<'a>(devices: impl Iterator<Item = &'a DeviceOutput>) -> String {
+    devices
+        .map(DeviceOutput::display_name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// What changed when the output was checked.
+pub(crate) enum OutputChange {
+    /// Nothing changed.
+    None,
+    /// The device was lost; playback should be paused.
+    Lost,
+    /// A device was (re)connected.
+    Connected,
+    /// Some of several devices were lost or came back; playback goes on.
+    Changed,
+}
+
+/// The output of the internal mixer, shared by successive device streams.
+/// Every clone reads the mixer a block at a time into its own buffer.
+struct SharedSource {
+    mixer: Arc<Mutex<MixerSource>>,
+    block: Vec<f32>,
+    /// Index of the next sample in `block`.
+    block_pos: usize,
+}
+
+impl SharedSource {
+    fn new(mixer: MixerSource) -> Self {
+        Self {
+            mixer: Arc::new(Mutex::new(mixer)),
+            block: Vec::with_capacity(BLOCK_SAMPLES),
+            block_pos: 0,
+        }
+    }
+}
+
+impl Clone for SharedSource {
+    fn clone(&self) -> Self {
+        Self {
+            mixer: Arc::clone(&self.mixer),
+            block: Vec::with_capacity(BLOCK_SAMPLES),
+            block_pos: 0,
+        }
+    }
+}
+
+impl Iterator for SharedSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.block_pos == self.block.len() {
+            let mut mixer = self.mixer.lock().unwrap();
+            self.block.clear();
+            // The mixer ends when it has nothing to play; the device stream
+            // must not drop us for that, so we play silence instead.
+            self.block
+                .extend((0..BLOCK_SAMPLES).map(|_| mixer.next().unwrap_or(0.0)));
+            self.block_pos = 0;
+        }
+        let sample = self.block[self.block_pos];
+        self.block_pos += 1;
+        Some(sample)
+    }
+}
+
+impl Source for SharedSource {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> NonZero<u16> {
+        NonZero::new(CHANNELS).unwrap()
+    }
+
+    fn sample_rate(&self) -> NonZero<u32> {
+        NonZero::new(SAMPLE_RATE).unwrap()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// An open output.  Dropping it closes the output.
+enum OutputSink {
+    Device {
+        _stream: MixerDeviceSink,
+    },
+    Devices {
+        fan_out: FanOut,
+        devices: Vec<DeviceSink>,
+    },
+    Pump {
+        _pump: Pump,
+    },
+}
+
+/// One of several devices, see [`OutputBackend::Devices`].
+struct DeviceSink {
+    config: DeviceOutput,
+    /// `None` while the device is not available.
+    stream: Option<MixerDeviceSink>,
+    failed: Arc<AtomicBool>,
+}
+
+/// The audio output of the player.
+pub(crate) struct Output {
+    source: SharedSource,
+    sink: Option<OutputSink>,
+    backend: OutputBackend,
+    status: OutputStatus,
+    /// Set when the open output failed, e.g. the device went away.
+    sink_failed: Arc<AtomicBool>,
+}
+
+impl Output {
+    /// Create the output and the mixer the player plays into, and try to open
+    /// the configured backend (or the default device as fallback).
+    pub(crate) fn new(backend: OutputBackend) -> (Self, Mixer) {
+        let (mixer, mixer_source) = rodio::mixer::mixer(
+            NonZero::new(CHANNELS).unwrap(),
+            NonZero::new(SAMPLE_RATE).unwrap(),
+        );
+        let mut output = Self {
+            source: SharedSource::new(mixer_source),
+            sink: None,
+            backend: OutputBackend::Null,
+            status: OutputStatus::NoOutput,
+            sink_failed: Arc::new(AtomicBool::new(false)),
+        };
+        output.switch(backend);
+        (output, mixer)
+    }
+
+    pub(crate) fn status(&self) -> OutputStatus {
+        self.status.clone()
+    }
+
+    /// Use another backend.  If a device cannot be opened the default device
+    /// is used until it appears.
+    pub(crate) fn switch(&mut self, backend: OutputBackend) {
+        let backend = match backend {
+            OutputBackend::Device(name) => OutputBackend::Device(device_name(name)),
+            OutputBackend::Devices(devices) if devices.is_empty() => OutputBackend::Device(None),
+            OutputBackend::Devices(devices) => OutputBackend::Devices(
+                devices
+                    .into_iter()
+                    .map(|device| DeviceOutput {
+                        name: device_name(device.name),
+                        ..device
+                    })
+                    .collect(),
+            ),
+            backend => backend,
+        };
+        if backend == self.backend && matches!(self.status, OutputStatus::Connected(_)) {
+            return;
+        }
+        self.backend = backend;
+        self.sink = None;
+        self.status = OutputStatus::NoOutput;
+        if let Err(e) = self.connect(true) {
+            error!("No audio output device available: {e}");
+        }
+    }
+
+    /// Close the output for good.
+    pub(crate) fn close(&mut self) {
+        self.sink = None;
+        self.backend = OutputBackend::Null;
+        self.status = OutputStatus::NoOutput;
+    }
+
+    /// Check for a lost device and try to (re)connect the configured device.
+    /// Called periodically by the output monitor of the player.
+    pub(crate) fn check(&mut self) -> OutputChange {
+        if matches!(self.sink, Some(OutputSink::Devices { .. })) {
+            return self.check_devices();
+        }
+        if self.sink.is_some() && self.sink_failed.load(Ordering::SeqCst) {
+            let name = self.backend.to_string();
+            warn!("Audio output '{name}' lost");
+            self.sink = None;
+            self.status = OutputStatus::Lost(name);
+            return OutputChange::Lost;
+        }
+        match self.status {
+            // Several devices are checked by `check_devices`.
+            OutputStatus::Connected(_) | OutputStatus::Partial { .. } => OutputChange::None,
+            // Never switch to the default device after a loss; the music
+            // should come out where it was playing before.
+            OutputStatus::Lost(_) | OutputStatus::Fallback(_) => self.reconnect(false),
+            OutputStatus::NoOutput => self.reconnect(true),
+        }
+    }
+
+    /// Like [`Self::check`] for several devices: lost devices are closed and
+    /// missing ones reopened, while the others keep playing.
+    fn check_devices(&mut self) -> OutputChange {
+        let Some(OutputSink::Devices { fan_out, devices }) = &mut self.sink else {
+            return OutputChange::None;
+        };
+        for device in devices.iter_mut() {
+            if device.stream.is_some() && device.failed.load(Ordering::SeqCst) {
+                warn!("Audio output '{}' lost", device.config.display_name());
+                device.stream = None;
+            }
+            if device.stream.is_none() {
+                match open_device_stream(&device.config, fan_out) {
+                    Ok((stream, failed)) => {
+                        info!("Opened audio output: {}", device.config.display_name());
+                        device.stream = Some(stream);
+                        device.failed = failed;
+                    }
+                    Err(e) => debug!(
+                        "Audio output '{}' still not available: {e}",
+                        device.config.display_name()
+                    ),
+                }
+            }
+        }
+        if devices.iter().all(|device| device.stream.is_none()) {
+            let name = self.backend.to_string();
+            warn!("Audio output '{name}' lost");
+            self.sink = None;
+            self.status = OutputStatus::Lost(name);
+            return OutputChange::Lost;
+        }
+        let status = devices_status(devices);
+        if status == self.status {
+            return OutputChange::None;
+        }
+        self.status = status;
+        OutputChange::Changed
+    }
+
+    fn reconnect(&mut self, allow_fallback: bool) -> OutputChange {
+        match self.connect(allow_fallback) {
+            Ok(()) => OutputChange::Connected,
+            Err(e) => {
+                // Retried periodically, so don't flood the log.
+                debug!("Audio output still not available: {e}");
+                OutputChange::None
+            }
+        }
+    }
+
+    /// Open the configured backend, or the default device if a configured
+    /// device fails and `allow_fallback` is set.
+    ///
+    /// Only one output may read the shared mixer output, so an open output
+    /// (the default device while falling back) is closed before another one
+    /// is opened.
+    fn connect(&mut self, allow_fallback: bool) -> Result<(), Error> {
+        let name = self.backend.to_string();
+        let sink_failed = Arc::new(AtomicBool::new(false));
+        if !matches!(self.backend, OutputBackend::Device(_)) {
+            self.sink = None;
+        }
+        let result = match self.backend.clone() {
+            OutputBackend::Device(device_name) => {
+                // Look for the device first, so the fallback keeps playing
+                // while the configured device is missing.
+                let opened = find_device(device_name.as_deref()).and_then(|device| {
+                    self.sink = None;
+                    open_stream(device, &sink_failed, self.source.clone())
+                });
+                if opened.is_err()
+                    && self.sink.is_none()
+                    && matches!(self.status, OutputStatus::Fallback(_))
+                {
+                    // The fallback was closed for nothing; open it again on
+                    // the next check.
+                    self.status = OutputStatus::NoOutput;
+                }
+                match opened {
+                    Err(e) if device_name.is_some() && allow_fallback => {
+                        self.sink = None;
+                        let stream = self.open_device(None, &sink_failed)?;
+                        warn!("Failed to open audio device '{name}': {e}, falling back to default");
+                        self.sink = Some(OutputSink::Device { _stream: stream });
+                        self.sink_failed = sink_failed;
+                        self.status = OutputStatus::Fallback(DEFAULT_DEVICE.to_string());
+                        return Ok(());
+                    }
+                    result => result.map(|stream| OutputSink::Device { _stream: stream }),
+                }
+            }
+            OutputBackend::Devices(configs) => {
+                let fan_out = FanOut::new(self.source.clone());
+                let devices: Vec<DeviceSink> = configs
+                    .into_iter()
+                    .map(|config| match open_device_stream(&config, &fan_out) {
+                        Ok((stream, failed)) => DeviceSink {
+                            config,
+                            stream: Some(stream),
+                            failed,
+                        },
+                        Err(e) => {
+                            warn!(
+                                "Failed to open audio device '{}': {e}",
+                                config.display_name()
+                            );
+                            DeviceSink {
+                                config,
+                                stream: None,
+                                failed: Arc::new(AtomicBool::new(false)),
+                            }
+                        }
+                    })
+                    .collect();
+                if devices.iter().all(|device| device.stream.is_none()) {
+                    return Err(anyhow!("none of the devices is available"));
+                }
+                info!("Opened audio outputs: {name}");
+                self.status = devices_status(&devices);
+                self.sink = Some(OutputSink::Devices { fan_out, devices });
+                return Ok(());
+            }
+            OutputBackend::Pipe(path) => {
+                PipeWriter::open(&path).map(|writer| self.open_pump(Box::new(writer), &sink_failed))
+            }
+            OutputBackend::WavFile(path) => WavWriter::create(&path, CHANNELS, SAMPLE_RATE)
+                .map(|writer| self.open_pump(Box::new(writer), &sink_failed)),
+            OutputBackend::Null => Ok(self.open_pump(Box::new(NullWriter), &sink_failed)),
+        };
+        self.sink = Some(result?);
+        self.sink_failed = sink_failed;
+        info!("Opened audio output: {name}");
+        self.status = OutputStatus::Connected(name);
+        Ok(())
+    }
+
+    fn open_pump(&self, writer: Box<dyn PcmWriter>, failed: &Arc<AtomicBool>) -> OutputSink {
+        OutputSink::Pump {
+            _pump: pcm_output::spawn_pump(self.source.clone(), writer, Arc::clone(failed)),
+        }
+    }
+
+    /// Open a stream on the named device (`None` for the default device) and
+    /// connect the shared mixer output to it.
+    fn open_device(
+        &self,
+        device_name: Option<&str>,
+        failed: &Arc<AtomicBool>,
+    ) -> Result<MixerDeviceSink, Error> {
+        open_stream(find_device(device_name)?, failed, self.source.clone())
+    }
+}
+
+/// Status of several devices, depending on which of them are open.
+fn devices_status(devices: &[DeviceSink]) -> OutputStatus {
+    let (open, missing): (Vec<&DeviceSink>, Vec<&DeviceSink>) =
+        devices.iter().partition(|device| device.stream.is_some());
+    let playing = display_names(open.iter().map(|device| &device.config));
+    if missing.is_empty() {
+        OutputStatus::Connected(playing)
+    } else {
+        OutputStatus::Partial {
+            playing,
+            missing: display_names(missing.iter().map(|device| &device.config)),
+        }
+    }
+}
+
+/// Open one of several devices as a new branch of `fan_out`.
+fn open_device_stream(
+    config: &DeviceOutput,
+    fan_out: &FanOut,
+) -> Result<(MixerDeviceSink, Arc<AtomicBool>), Error> {
+    let failed = Arc::new(AtomicBool::new(false));
+    let branch = fan_out.branch(config.trim_db, config.delay);
+    let stream = open_stream(find_device(config.name.as_deref())?, &failed, branch)?;
+    Ok((stream, failed))
+}
+
+/// The named device, `None` for the default device.
+fn find_device(device_name: Option<&str>) -> Result<cpal::Device, Error> {
+    let host = cpal::default_host();
+    match device_name {
+        None => host
+            .default_output_device()
+            .ok_or_else(|| anyhow!("no default output device")),
+        Some(requested) => host
+            .output_devices()?
+            .find(|device| device.name().is_ok_and(|name| name == requested))
+            .ok_or_else(|| anyhow!("device not found")),
+    }
+}
+
+/// Open a stream on `device` that plays `source`.  `failed` is set when the
+/// stream fails.
+fn open_stream<S>(
+    device: cpal::Device,
+    failed: &Arc<AtomicBool>,
+    source: S,
+) -> Result<MixerDeviceSink, Error>
+where
+    S: Source + Send + 'static,
+{
+    let failed = Arc::clone(failed);
+    let mut stream = DeviceSinkBuilder::from_device(device)?
+        .with_error_callback(move |err| match err {
+            cpal::StreamError::BufferUnderrun => {}
+            err => {
+                error!("Audio stream error: {err}");
+                failed.store(true, Ordering::SeqCst);
+            }
+        })
+        .open_stream()?;
+    stream.log_on_drop(false);
+    stream.mixer().add(source);
+    Ok(stream)
+}
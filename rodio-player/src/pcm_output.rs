@@ -0,0 +1,216 @@
+//! Output backends that don't need a sound card: raw PCM into a named pipe,
+//! a WAV file recording and a null sink.
+//!
+//! All of them are driven by a pump thread that pulls the mixed samples in
+//! real time, converts them to 16 bit little endian PCM and hands them to a
+//! [`PcmWriter`].
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+
+use anyhow::{Error, anyhow};
+use rodio::Source;
+use tracing::{debug, error};
+
+/// Number of frames pulled from the mixer per write.
+const CHUNK_FRAMES: usize = 1024;
+
+/// The WAV header is rewritten after this many chunks, so a recording stays
+/// readable even if homeplayer is killed.
+const WAV_HEADER_UPDATE_CHUNKS: u32 = 50;
+
+/// Size of the canonical WAV header written by [`WavWriter`].
+const WAV_HEADER_LEN: u32 = 44;
+
+/// Receives the PCM data of the pump thread.
+pub(crate) trait PcmWriter: Send {
+    fn write(&mut self, pcm: &[u8]) -> io::Result<()>;
+}
+
+/// Discards everything.
+pub(crate) struct NullWriter;
+
+impl PcmWriter for NullWriter {
+    fn write(&mut self, _pcm: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes raw PCM into a named pipe, e.g. the pipe source of a Snapcast
+/// server.
+pub(crate) struct PipeWriter(File);
+
+impl PipeWriter {
+    /// Open the pipe at `path`, creating it if it doesn't exist.  Fails if
+    /// nobody reads from the pipe yet, so the caller can retry later instead
+    /// of blocking.
+    pub(crate) fn open(path: &Path) -> Result<Self, Error> {
+        match std::fs::metadata(path) {
+            Ok(metadata) if !metadata.file_type().is_fifo() => {
+                return Err(anyhow!("{} is not a named pipe", path.display()));
+            }
+            Ok(_) => {}
+            Err(_) => {
+                let mut c_path = path.as_os_str().as_bytes().to_vec();
+                c_path.push(0);
+                // SAFETY: `c_path` is a valid NUL terminated string.
+                if unsafe { libc::mkfifo(c_path.as_ptr().cast(), 0o644) } != 0 {
+                    return Err(io::Error::last_os_error().into());
+                }
+            }
+        }
+        // Opening non-blocking fails with ENXIO while there is no reader.
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .map_err(|e| anyhow!("no reader on pipe {}: {e}", path.display()))?;
+        // Writes should block while the reader is busy, though.
+        // SAFETY: the file descriptor is valid while `file` lives.
+        unsafe {
+            use std::os::fd::AsRawFd;
+            let fd = file.as_raw_fd();
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK);
+        }
+        Ok(Self(file))
+    }
+}
+
+impl PcmWriter for PipeWriter {
+    fn write(&mut self, pcm: &[u8]) -> io::Result<()> {
+        self.0.write_all(pcm)
+    }
+}
+
+/// Records to a 16 bit PCM WAV file.
+pub(crate) struct WavWriter {
+    file: File,
+    channels: u16,
+    sample_rate: u32,
+    data_len: u32,
+    chunks_since_header: u32,
+}
+
+impl WavWriter {
+    pub(crate) fn create(path: &Path, channels: u16, sample_rate: u32) -> Result<Self, Error> {
+        let file = File::create(path)?;
+        let mut writer = Self {
+            file,
+            channels,
+            sample_rate,
+            data_len: 0,
+            chunks_since_header: 0,
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let block_align = self.channels * 2;
+        let mut header = Vec::with_capacity(WAV_HEADER_LEN as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(WAV_HEADER_LEN - 8 + self.data_len).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&self.channels.to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&self.data_len.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+impl PcmWriter for WavWriter {
+    fn write(&mut self, pcm: &[u8]) -> io::Result<()> {
+        self.file.write_all(pcm)?;
+        self.data_len = self.data_len.saturating_add(pcm.len() as u32);
+        self.chunks_since_header += 1;
+        if self.chunks_since_header >= WAV_HEADER_UPDATE_CHUNKS {
+            self.chunks_since_header = 0;
+            self.write_header()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.write_header() {
+            error!("Could not finish WAV file: {e}");
+        }
+    }
+}
+
+/// Running pump thread.  Dropping the handle stops the thread.
+pub(crate) struct Pump {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for Pump {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Spawn a thread that pulls samples from `source` in real time and writes
+/// them to `writer`.  `failed` is set if writing fails, e.g. because the
+/// reader of a pipe went away.
+pub(crate) fn spawn_pump<S>(
+    mut source: S,
+    mut writer: Box<dyn PcmWriter>,
+    failed: Arc<AtomicBool>,
+) -> Pump
+where
+    S: Source + Send + 'static,
+{
+    let stop = Arc::new(AtomicBool::new(false));
+    let pump = Pump {
+        stop: Arc::clone(&stop),
+    };
+    let _ = spawn(move || {
+        let channels = source.channels().get() as usize;
+        let sample_rate = source.sample_rate().get() as f64;
+        let mut pcm = Vec::with_capacity(CHUNK_FRAMES * channels * 2);
+        let started = Instant::now();
+        let mut frames_written: u64 = 0;
+
+        while !stop.load(Ordering::SeqCst) {
+            pcm.clear();
+            for _ in 0..CHUNK_FRAMES * channels {
+                let sample = source.next().unwrap_or(0.0);
+                let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                pcm.extend_from_slice(&value.to_le_bytes());
+            }
+            if let Err(e) = writer.write(&pcm) {
+                error!("Could not write audio output: {e}");
+                failed.store(true, Ordering::SeqCst);
+                break;
+            }
+            frames_written += CHUNK_FRAMES as u64;
+
+            // Keep real time, the sources must not play faster than usual.
+            let due = started + Duration::from_secs_f64(frames_written as f64 / sample_rate);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                sleep(wait);
+            }
+        }
+        debug!("Audio output pump stopped");
+    });
+    pump
+}
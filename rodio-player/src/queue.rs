@@ -0,0 +1,518 @@
+//! Items of the play queue and how they are turned into playable sources.
+//!
+//! The queue may mix local files, audio files on an HTTP server, internet
+//! radio stations and audio CD tracks.  All of them are played by the same
+//! queue loop in [`RodioPlayer`](crate::RodioPlayer).
+
+use std::io::Cursor;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use anyhow::{Error, anyhow};
+use icy_metadata::{IcyHeaders, IcyMetadataReader, RequestIcyMetadata};
+use rodio::Source;
+use stream_download::http::{HttpStream, reqwest::Client};
+use stream_download::storage::bounded::BoundedStorageProvider;
+use stream_download::storage::memory::MemoryStorageProvider;
+use stream_download::{Settings, StreamDownload};
+use tracing::{debug, error};
+
+use crate::cd_audio::{self, CdInfo, CdReadSettings, CdTrackInfo};
+use crate::iso9660::{self, DiscEntry};
+use crate::loudness::LoudnessNormalizer;
+use crate::stream_metadata::{self, StationMetadata, TitleSource};
+use crate::{TitleChanged, UNKNOWN};
+
+/// Timeout for downloading an audio file from an HTTP server.
+const HTTP_FILE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A playable source of any queue item type.
+pub(crate) type BoxedSource = Box<dyn Source + Send>;
+
+/// Data structure for a concrete file title which can be added
+/// to the internal play list of the player.  The path may also be an
+/// `http://` or `https://` URL.
+#[derive(Clone, Debug)]
+pub struct SoundItem {
+    pub artist: String,
+    pub album: String,
+    pub title: String,
+    pub path: String,
+    pub cover: String,
+}
+
+/// An internet radio station in the play queue.  A station plays until it is
+/// skipped or stopped.
+#[derive(Clone, Debug)]
+pub struct StationItem {
+    pub name: String,
+    pub url: String,
+    /// Station icon, reported as cover while the station plays.
+    pub icon: String,
+    /// Optional "now playing" endpoint, which takes precedence over the ICY
+    /// titles embedded in the stream.
+    pub metadata: Option<StationMetadata>,
+    /// Level change of the station in dB, on top of the loudness
+    /// normalisation.
+    pub gain_db: f32,
+}
+
+/// A track of an audio CD in the play queue.
+#[derive(Clone, Debug)]
+pub struct CdTrackItem {
+    /// CD device, e.g. `/dev/sr0`.
+    pub device: String,
+    pub track: CdTrackInfo,
+    pub artist: String,
+    pub album: String,
+    pub title: String,
+    pub cover: String,
+    /// How the track is read, and where read problems are counted.
+    pub read: CdReadSettings,
+}
+
+impl CdTrackItem {
+    /// Create an item for a track of a disc, with the disc's titles and
+    /// cover if there are any.
+    pub fn new(device: &str, disc: &CdInfo, track: CdTrackInfo, read: &CdReadSettings) -> Self {
+        Self {
+            device: device.to_string(),
+            read: read.clone(),
+            title: track.display_title(),
+            artist: track
+                .performer
+                .clone()
+                .or_else(|| disc.performer.clone())
+                .unwrap_or_default(),
+            album: disc.title.clone().unwrap_or_else(|| "Audio CD".to_string()),
+            track,
+            cover: disc.cover.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// An audio file on a data CD or DVD, read without mounting the disc.
+#[derive(Clone, Debug)]
+pub struct DiscFileItem {
+    /// CD device, e.g. `/dev/sr0`.
+    pub device: String,
+    /// Path of the file on the disc, e.g. `/Album/01 Song.mp3`.
+    pub path: String,
+    pub file: DiscEntry,
+    pub artist: String,
+    pub album: String,
+    pub title: String,
+    pub cover: String,
+}
+
+/// One entry of the play queue.
+#[derive(Clone, Debug)]
+pub enum QueueItem {
+    /// An audio file on the local file system.
+    File(SoundItem),
+    /// An audio file on an HTTP server.
+    HttpFile(SoundItem),
+    /// An internet radio station or other endless audio stream.
+    Station(StationItem),
+    /// A track of an audio CD.
+    CdTrack(CdTrackItem),
+    /// An audio file on a data CD or DVD.
+    DiscFile(DiscFileItem),
+}
+
+/// An item of the play queue together with its playback status.
+#[derive(Clone, Debug)]
+pub struct QueueEntry {
+    pub item: QueueItem,
+    /// Why the item could not be played the last time it was tried.
+    pub error: Option<String>,
+}
+
+impl From<QueueItem> for QueueEntry {
+    fn from(item: QueueItem) -> Self {
+        Self { item, error: None }
+    }
+}
+
+impl From<SoundItem> for QueueItem {
+    /// Sound items with an `http://` or `https://` path become
+    /// [`QueueItem::HttpFile`], all others [`QueueItem::File`].
+    fn from(item: SoundItem) -> Self {
+        if is_http_url(&item.path) {
+            QueueItem::HttpFile(item)
+        } else {
+            QueueItem::File(item)
+        }
+    }
+}
+
+impl QueueItem {
+    pub fn title(&self) -> &str {
+        match self {
+            QueueItem::File(item) | QueueItem::HttpFile(item) => &item.title,
+            QueueItem::Station(station) => &station.name,
+            QueueItem::CdTrack(track) => &track.title,
+            QueueItem::DiscFile(item) => &item.title,
+        }
+    }
+
+    pub fn artist(&self) -> &str {
+        match self {
+            QueueItem::File(item) | QueueItem::HttpFile(item) => &item.artist,
+            QueueItem::Station(_) => "",
+            QueueItem::CdTrack(track) => &track.artist,
+            QueueItem::DiscFile(item) => &item.artist,
+        }
+    }
+
+    pub fn album(&self) -> &str {
+        match self {
+            QueueItem::File(item) | QueueItem::HttpFile(item) => &item.album,
+            QueueItem::Station(_) => "",
+            QueueItem::CdTrack(track) => &track.album,
+            QueueItem::DiscFile(item) => &item.album,
+        }
+    }
+
+    pub fn cover(&self) -> &str {
+        match self {
+            QueueItem::File(item) | QueueItem::HttpFile(item) => &item.cover,
+            QueueItem::Station(station) => &station.icon,
+            QueueItem::CdTrack(track) => &track.cover,
+            QueueItem::DiscFile(item) => &item.cover,
+        }
+    }
+
+    /// Short name of the item for messages: the file name for files, the
+    /// title otherwise.
+    pub fn display_name(&self) -> String {
+        match self {
+            QueueItem::File(item) | QueueItem::HttpFile(item) => item
+                .path
+                .rsplit(['/', '\\'])
+                .find(|part| !part.is_empty())
+                .unwrap_or(&item.path)
+                .to_string(),
+            QueueItem::DiscFile(item) => item.file.name.clone(),
+            _ => self.title().to_string(),
+        }
+    }
+
+    /// File path, URL or CD device of the item; the path on the disc for
+    /// files of data discs.
+    pub fn location(&self) -> &str {
+        match self {
+            QueueItem::File(item) | QueueItem::HttpFile(item) => &item.path,
+            QueueItem::Station(station) => &station.url,
+            QueueItem::CdTrack(track) => &track.device,
+            QueueItem::DiscFile(item) => &item.path,
+        }
+    }
+
+    /// Whether seeking is possible while this item plays.
+    pub fn is_seekable(&self) -> bool {
+        !matches!(self, QueueItem::Station(_))
+    }
+
+    /// The title announcement sent when the item starts playing.
+    pub(crate) fn title_changed(&self) -> TitleChanged {
+        TitleChanged {
+            artist: self.artist().to_string(),
+            album: self.album().to_string(),
+            title: self.title().to_string(),
+            cover: self.cover().to_string(),
+            is_stream: matches!(self, QueueItem::Station(_)),
+        }
+    }
+}
+
+fn is_http_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Open a local audio file.
+pub(crate) fn open_file(path: &str) -> Result<BoxedSource, Error> {
+    debug!("Open file: {path}");
+    let file = std::fs::File::open(path)?;
+    let metadata = file.metadata()?;
+    let source = rodio::Decoder::builder()
+        .with_seekable(true)
+        .with_byte_len(metadata.len())
+        .with_data(file)
+        .build()?;
+    debug!("Duration: {:?}", source.total_duration());
+    Ok(Box::new(source))
+}
+
+/// Download an audio file from an HTTP server into memory and open it.
+/// Keeping the whole file in memory makes it seekable like a local file.
+pub(crate) fn open_http_file(url: &str) -> Result<BoxedSource, Error> {
+    debug!("Download file: {url}");
+    let client = reqwest::blocking::Client::builder()
+        .timeout(HTTP_FILE_TIMEOUT)
+        .build()?;
+    let bytes = client.get(url).send()?.error_for_status()?.bytes()?;
+    let byte_len = bytes.len() as u64;
+    let source = rodio::Decoder::builder()
+        .with_seekable(true)
+        .with_byte_len(byte_len)
+        .with_data(Cursor::new(bytes))
+        .build()?;
+    Ok(Box::new(source))
+}
+
+/// Open an audio file on a data disc.  It is read from the disc while it
+/// plays.
+pub(crate) fn open_disc_file(item: &DiscFileItem) -> Result<BoxedSource, Error> {
+    debug!("Open {} on {}", item.path, item.device);
+    let reader = iso9660::open_file(&item.device, &item.file)?;
+    let mut builder = rodio::Decoder::builder()
+        .with_seekable(true)
+        .with_byte_len(item.file.size)
+        .with_data(reader);
+    if let Some(extension) = item.file.extension() {
+        builder = builder.with_hint(&extension);
+    }
+    Ok(Box::new(builder.build()?))
+}
+
+/// The CD tracks of the queue from `index` on that can be played without a
+/// gap: tracks in the same drive that follow each other on the disc.
+pub(crate) fn gapless_cd_tracks(queue: &[QueueEntry], index: usize) -> Vec<CdTrackItem> {
+    let mut tracks: Vec<CdTrackItem> = Vec::new();
+    for entry in queue.iter().skip(index) {
+        let QueueItem::CdTrack(item) = &entry.item else {
+            break;
+        };
+        if let Some(previous) = tracks.last()
+            && (previous.device != item.device
+                || previous.track.end_lba != item.track.start_lba
+                || previous.track.pre_emphasis != item.track.pre_emphasis)
+        {
+            break;
+        }
+        tracks.push(item.clone());
+    }
+    tracks
+}
+
+/// Open consecutive tracks of an audio CD as one source.  The returned
+/// index tells which of them is playing.
+pub(crate) fn open_cd_tracks(
+    items: &[CdTrackItem],
+) -> Result<(BoxedSource, Arc<AtomicUsize>), Error> {
+    let first = items
+        .first()
+        .ok_or_else(|| anyhow!("No CD tracks to play"))?;
+    let tracks: Vec<CdTrackInfo> = items.iter().map(|item| item.track.clone()).collect();
+    let source = cd_audio::open_tracks(&first.device, &tracks, &first.read)?;
+    let current_track = source.current_track();
+    Ok((Box::new(source), current_track))
+}
+
+/// Connect to an internet radio station.
+///
+/// The connection is established on `runtime`.  While `active` is `true` the
+/// station's titles are announced via `title_changed_sender`, from the
+/// station's "now playing" endpoint if one is configured and works, and from
+/// the ICY metadata of the stream otherwise.
+pub(crate) fn open_station(
+    runtime: &tokio::runtime::Handle,
+    station: &StationItem,
+    target_lufs: Option<f32>,
+    active: Arc<AtomicBool>,
+    title_changed_sender: Sender<TitleChanged>,
+) -> Result<BoxedSource, Error> {
+    let (reader, icy_headers) = runtime.block_on(connect_stream(&station.url))?;
+
+    let icon = station.icon.clone();
+    let titles = TitleSource::new(title_changed_sender);
+    if let Some(metadata) = station.metadata.clone() {
+        stream_metadata::spawn_metadata_poller(metadata, icon.clone(), active, titles.clone());
+    }
+
+    let stream_reader = IcyMetadataReader::new(
+        reader,
+        // Since we requested icy metadata, the metadata interval header should be present in the
+        // response. This will allow us to parse the metadata within the stream
+        icy_headers.metadata_interval(),
+        // Parse stream metadata whenever we receive new values.
+        move |metadata| {
+            // ICY stream titles typically use the format "Artist - Title"
+            // and some stations append extra info after a single-quote
+            // (e.g. "Artist - Title'extra"). We split on "-" for
+            // artist/title and strip anything after "'" from the title.
+            let stream_title = match metadata {
+                Ok(meta) => meta.stream_title().unwrap_or(UNKNOWN).to_string(),
+                Err(e) => {
+                    error!("Could not get music title from stream: {}", e);
+                    UNKNOWN.to_string()
+                }
+            };
+            debug!("Update title: {}", &stream_title);
+            let (artist, title) = stream_title
+                .split_once("-")
+                .unwrap_or((&stream_title, UNKNOWN));
+            let (normalized_title, _) = title.split_once("'").unwrap_or((title, ""));
+            titles.icy_title(TitleChanged {
+                title: normalized_title.trim().to_string(),
+                artist: artist.trim().to_string(),
+                album: UNKNOWN.to_string(),
+                cover: icon.clone(),
+                is_stream: true,
+            });
+        },
+    );
+
+    let source = rodio::Decoder::new(stream_reader)
+        .map_err(|e| anyhow!("Could not decode stream {}: {e}", station.url))?;
+    match target_lufs {
+        Some(target_lufs) => Ok(Box::new(LoudnessNormalizer::new(
+            source,
+            target_lufs,
+            station.gain_db,
+        ))),
+        None => Ok(Box::new(source.amplify_decibel(station.gain_db))),
+    }
+}
+
+async fn connect_stream(
+    url: &str,
+) -> Result<
+    (
+        StreamDownload<BoundedStorageProvider<MemoryStorageProvider>>,
+        IcyHeaders,
+    ),
+    Error,
+> {
+    let client = Client::builder().request_icy_metadata().build()?;
+    let stream = HttpStream::new(client, url.parse()?).await?;
+
+    debug!("content type={:?}", stream.content_type());
+    let bitrate: u64 = stream.header("Icy-Br").unwrap_or("256").parse()?;
+    debug!("bitrate={bitrate}");
+
+    let icy_headers = IcyHeaders::parse_from_headers(stream.headers());
+
+    // buffer 5 seconds of audio
+    // bitrate (in kilobits) / bits per byte * bytes per kilobyte * 5 seconds
+    let prefetch_bytes = bitrate / 8 * 1024 * 5;
+    debug!("prefetch bytes={prefetch_bytes}");
+
+    let reader = StreamDownload::from_stream(
+        stream,
+        // use bounded storage to keep the underlying size from growing indefinitely
+        BoundedStorageProvider::new(
+            MemoryStorageProvider,
+            // be liberal with the buffer size, you need to make sure it holds enough space to
+            // prevent any out-of-bounds reads
+            NonZeroUsize::new(512 * 1024).unwrap(),
+        ),
+        Settings::default().prefetch_bytes(prefetch_bytes),
+    )
+    .await?;
+
+    Ok((reader, icy_headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sound_item(path: &str) -> SoundItem {
+        SoundItem {
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            title: "Title".to_string(),
+            path: path.to_string(),
+            cover: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_from_sound_item() {
+        assert!(matches!(
+            QueueItem::from(sound_item("/music/a.flac")),
+            QueueItem::File(_)
+        ));
+        assert!(matches!(
+            QueueItem::from(sound_item("https://example.com/a.mp3")),
+            QueueItem::HttpFile(_)
+        ));
+    }
+
+    #[test]
+    fn test_display_name() {
+        assert_eq!(
+            QueueItem::from(sound_item("/music/Artist/01 Song.flac")).display_name(),
+            "01 Song.flac"
+        );
+        assert_eq!(
+            QueueItem::from(sound_item("http://example.com/files/a.mp3")).display_name(),
+            "a.mp3"
+        );
+    }
+
+    #[test]
+    fn test_station_title_changed() {
+        let item = QueueItem::Station(StationItem {
+            name: "News".to_string(),
+            url: "http://example.com/stream".to_string(),
+            icon: "news.png".to_string(),
+            metadata: None,
+            gain_db: 0.0,
+        });
+        let title = item.title_changed();
+        assert_eq!(title.title, "News");
+        assert_eq!(title.cover, "news.png");
+        assert!(title.is_stream);
+        assert!(!item.is_seekable());
+    }
+
+    fn cd_track(device: &str, number: u8, start_lba: i32, end_lba: i32) -> QueueEntry {
+        let track = CdTrackInfo {
+            number,
+            start_lba,
+            end_lba,
+            duration: Duration::ZERO,
+            is_audio: true,
+            pre_emphasis: false,
+            pregap: 0,
+            title: None,
+            performer: None,
+        };
+        let read = CdReadSettings::default();
+        QueueItem::CdTrack(CdTrackItem::new(device, &CdInfo::default(), track, &read)).into()
+    }
+
+    #[test]
+    fn test_gapless_cd_tracks() {
+        let queue = vec![
+            cd_track("/dev/sr0", 1, 0, 100),
+            cd_track("/dev/sr0", 2, 100, 250),
+            cd_track("/dev/sr0", 3, 250, 400),
+            // Played out of order, so there is a gap.
+            cd_track("/dev/sr0", 5, 600, 700),
+            cd_track("/dev/sr0", 6, 700, 800),
+            // Another drive.
+            cd_track("/dev/sr1", 7, 800, 900),
+            QueueItem::from(sound_item("/music/a.flac")).into(),
+            cd_track("/dev/sr1", 8, 900, 1000),
+        ];
+        let numbers = |index| {
+            gapless_cd_tracks(&queue, index)
+                .iter()
+                .map(|item| item.track.number)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(0), vec![1, 2, 3]);
+        assert_eq!(numbers(1), vec![2, 3]);
+        assert_eq!(numbers(3), vec![5, 6]);
+        assert_eq!(numbers(5), vec![7]);
+        assert_eq!(numbers(6), Vec::<u8>::new());
+        assert_eq!(numbers(7), vec![8]);
+    }
+}
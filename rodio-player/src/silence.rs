@@ -0,0 +1,374 @@
+//! Silence handling: trimming leading and trailing silence of files, and
+//! detecting streams that went silent ("dead air") while still connected.
+
+use std::collections::VecDeque;
+use std::num::NonZero;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use rodio::Source;
+use rodio::source::SeekError;
+
+/// Most silence held back because it may be the end of a file.  Longer
+/// trailing silence is only trimmed by this much.
+const MAX_TRAILING_SILENCE: Duration = Duration::from_secs(10);
+
+/// Frames read per frame played while it isn't known yet whether silence
+/// is trailing.  Reading ahead a little at a time keeps the audio output
+/// from waiting for the decoder; 7/8 of short trailing silence is skipped.
+const LOOKAHEAD: usize = 8;
+
+/// Frames of leading silence skipped at most per frame played.  A long
+/// silent intro is skipped a bit at a time, with a silent frame played in
+/// between, so the audio output doesn't wait for all of it to be decoded.
+const MAX_LEADING_SKIP: usize = 4_096;
+
+/// How the player treats silence.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SilenceSettings {
+    /// Samples below this level in dBFS count as silence.
+    pub threshold_db: f32,
+    /// Skip silence at the start and end of files.
+    pub trim_files: bool,
+    /// A station that stays silent this long is reconnected; `None` never
+    /// reconnects.
+    pub dead_air: Option<Duration>,
+}
+
+impl Default for SilenceSettings {
+    fn default() -> Self {
+        Self {
+            threshold_db: -60.0,
+            trim_files: false,
+            dead_air: None,
+        }
+    }
+}
+
+impl SilenceSettings {
+    fn threshold(&self) -> f32 {
+        10f32.powf(self.threshold_db / 20.0)
+    }
+}
+
+/// Skips the silence at the start and the end of a source.
+pub(crate) struct TrimSilence<S> {
+    source: S,
+    threshold: f32,
+    channels: usize,
+    sample_rate: u32,
+    /// Still at the start of the source.
+    leading: bool,
+    /// Frames of silence skipped at the start.  Seeks are shifted by them,
+    /// so positions are counted from the first sound.
+    skipped: u64,
+    /// Silent samples read ahead, held back because they may be the end.
+    pending: VecDeque<f32>,
+    max_pending: usize,
+    /// Samples ready to be played.
+    out: VecDeque<f32>,
+    frame: Vec<f32>,
+}
+
+impl<S: Source> TrimSilence<S> {
+    pub(crate) fn new(source: S, settings: &SilenceSettings) -> Self {
+        let channels = source.channels().get() as usize;
+        let sample_rate = source.sample_rate().get();
+        let max_pending =
+            (MAX_TRAILING_SILENCE.as_secs_f32() * sample_rate as f32) as usize * channels;
+        Self {
+            source,
+            threshold: settings.threshold(),
+            channels,
+            sample_rate,
+            leading: true,
+            skipped: 0,
+            pending: VecDeque::new(),
+            max_pending,
+            out: VecDeque::new(),
+            frame: Vec::with_capacity(channels),
+        }
+    }
+
+    /// Read the next frame into `frame`.  Returns whether it is silent, or
+    /// `None` at the end of the source.
+    fn read_frame(&mut self) -> Option<bool> {
+        self.frame.clear();
+        self.frame.extend(self.source.by_ref().take(self.channels));
+        if self.frame.len() < self.channels {
+            return None;
+        }
+        Some(
+            self.frame
+                .iter()
+                .all(|sample| sample.abs() < self.threshold),
+        )
+    }
+
+    /// Move the next frame to play to `out`, or leave it empty at the end.
+    fn fill(&mut self) {
+        if self.pending.is_empty() {
+            let mut skipped = 0;
+            loop {
+                match self.read_frame() {
+                    None => return,
+                    Some(true) if self.leading && skipped < MAX_LEADING_SKIP => {
+                        self.skipped += 1;
+                        skipped += 1;
+                    }
+                    Some(true) if self.leading => {
+                        self.out.extend(&self.frame);
+                        return;
+                    }
+                    Some(true) => {
+                        self.pending.extend(&self.frame);
+                        break;
+                    }
+                    Some(false) => {
+                        self.leading = false;
+                        self.out.extend(&self.frame);
+                        return;
+                    }
+                }
+            }
+        }
+        // In silence: read ahead to find out whether sound follows.
+        for _ in 0..LOOKAHEAD {
+            if self.pending.len() >= self.max_pending {
+                break;
+            }
+            match self.read_frame() {
+                None => {
+                    self.pending.clear();
+                    return;
+                }
+                Some(true) => self.pending.extend(&self.frame),
+                Some(false) => {
+                    self.out.extend(self.pending.drain(..));
+                    self.out.extend(&self.frame);
+                    return;
+                }
+            }
+        }
+        self.out.extend(self.pending.drain(..self.channels));
+    }
+}
+
+impl<S: Source> Iterator for TrimSilence<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.out.is_empty() {
+            self.fill();
+        }
+        self.out.pop_front()
+    }
+}
+
+impl<S: Source> Source for TrimSilence<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> NonZero<u16> {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> NonZero<u32> {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let skipped = Duration::from_secs_f64(self.skipped as f64 / self.sample_rate as f64);
+        self.source.try_seek(pos + skipped)?;
+        // Whoever seeks wants to hear that position, silent or not.
+        self.leading = false;
+        self.pending.clear();
+        self.out.clear();
+        Ok(())
+    }
+}
+
+/// Ends a stream that stays silent for too long and reports it through the
+/// flag, so the queue can reconnect.
+pub(crate) struct DeadAirDetector<S> {
+    source: S,
+    threshold: f32,
+    silent_samples: usize,
+    max_silent_samples: usize,
+    dead_air: Arc<AtomicBool>,
+}
+
+impl<S: Source> DeadAirDetector<S> {
+    pub(crate) fn new(
+        source: S,
+        threshold_db: f32,
+        limit: Duration,
+        dead_air: Arc<AtomicBool>,
+    ) -> Self {
+        let samples_per_sec = source.channels().get() as f32 * source.sample_rate().get() as f32;
+        Self {
+            source,
+            threshold: 10f32.powf(threshold_db / 20.0),
+            silent_samples: 0,
+            max_silent_samples: (limit.as_secs_f32() * samples_per_sec) as usize,
+            dead_air,
+        }
+    }
+}
+
+impl<S: Source> Iterator for DeadAirDetector<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.source.next()?;
+        if sample.abs() >= self.threshold {
+            self.silent_samples = 0;
+        } else {
+            self.silent_samples += 1;
+            if self.silent_samples > self.max_silent_samples {
+                self.dead_air.store(true, Ordering::SeqCst);
+                return None;
+            }
+        }
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for DeadAirDetector<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.source.current_span_len()
+    }
+
+    fn channels(&self) -> NonZero<u16> {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> NonZero<u32> {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn stereo(samples: Vec<f32>) -> SamplesBuffer {
+        SamplesBuffer::new(NonZero::new(2).unwrap(), NonZero::new(10).unwrap(), samples)
+    }
+
+    fn trim_settings() -> SilenceSettings {
+        SilenceSettings {
+            trim_files: true,
+            ..SilenceSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_trims_leading_and_trailing_silence() {
+        let source = stereo(vec![
+            0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 0.0, 0.0, 0.5, 0.5, 0.0, 0.0,
+        ]);
+        let trimmed: Vec<f32> = TrimSilence::new(source, &trim_settings()).collect();
+        // The left channel of the first frame is silent, but the frame isn't.
+        assert_eq!(trimmed, [0.0, 0.5, 0.5, 0.5, 0.0, 0.0, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_long_leading_silence_is_skipped_a_bit_at_a_time() {
+        let mut samples = vec![0.0; 2 * 3 * MAX_LEADING_SKIP];
+        samples.extend([0.5, 0.5]);
+        let mut trim = TrimSilence::new(stereo(samples), &trim_settings());
+        assert_eq!(trim.next(), Some(0.0));
+        // One silent frame was played after skipping as much as allowed.
+        assert_eq!(trim.skipped, MAX_LEADING_SKIP as u64);
+        let rest: Vec<f32> = trim.collect();
+        assert_eq!(rest, [0.0, 0.0, 0.0, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_trailing_silence_is_mostly_skipped() {
+        let mut samples = vec![0.5, 0.5];
+        // 8 s of silence at 10 Hz
+        samples.extend(vec![0.0; 2 * 10 * 8]);
+        let trimmed = TrimSilence::new(stereo(samples), &trim_settings()).count();
+        // The end is found after 9 frames were played.
+        assert_eq!(trimmed, 2 + 2 * 9);
+    }
+
+    #[test]
+    fn test_long_trailing_silence_is_cut_short() {
+        let mut samples = vec![0.5, 0.5];
+        // 40 s of silence at 10 Hz
+        samples.extend(vec![0.0; 2 * 10 * 40]);
+        let trimmed = TrimSilence::new(stereo(samples), &trim_settings()).count();
+        // 10 s are held back when the end is found, the rest was played.
+        assert_eq!(trimmed, 2 + 2 * 301);
+    }
+
+    #[test]
+    fn test_long_silence_within_a_file_is_played() {
+        let mut samples = vec![0.5, 0.5];
+        // 60 s of silence at 10 Hz
+        samples.extend(vec![0.0; 2 * 10 * 60]);
+        samples.extend([0.5, 0.5]);
+        let mut trim = TrimSilence::new(stereo(samples.clone()), &trim_settings());
+        let mut played = Vec::new();
+        while let Some(sample) = trim.next() {
+            // Never more than a little is read ahead.
+            assert!(trim.pending.len() <= trim.max_pending);
+            assert!(trim.pending.len() <= 2 * LOOKAHEAD * (played.len() / 2 + 1));
+            played.push(sample);
+        }
+        assert_eq!(played, samples);
+    }
+
+    #[test]
+    fn test_seek_skips_leading_silence() {
+        let samples = [vec![0.0; 2 * 10], (1..=20).map(|n| n as f32).collect()].concat();
+        let mut trim = TrimSilence::new(stereo(samples), &trim_settings());
+        assert_eq!(trim.next(), Some(1.0));
+        trim.try_seek(Duration::from_millis(500)).unwrap();
+        assert_eq!(trim.next(), Some(11.0));
+    }
+
+    #[test]
+    fn test_silent_stream_is_dead_air() {
+        let dead_air = Arc::new(AtomicBool::new(false));
+        let mut samples = vec![0.5; 20];
+        samples.extend(vec![0.0; 100]);
+        let detector = DeadAirDetector::new(
+            stereo(samples),
+            -60.0,
+            Duration::from_secs(2),
+            Arc::clone(&dead_air),
+        );
+        // 2 s of stereo at 10 Hz are 40 samples
+        assert_eq!(detector.count(), 20 + 40);
+        assert!(dead_air.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_short_pauses_are_not_dead_air() {
+        let dead_air = Arc::new(AtomicBool::new(false));
+        let samples = [vec![0.0; 30], vec![0.5; 2], vec![0.0; 30]].concat();
+        let detector = DeadAirDetector::new(
+            stereo(samples),
+            -60.0,
+            Duration::from_secs(2),
+            Arc::clone(&dead_air),
+        );
+        assert_eq!(detector.count(), 62);
+        assert!(!dead_air.load(Ordering::SeqCst));
+    }
+}
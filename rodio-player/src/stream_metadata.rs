@@ -0,0 +1,335 @@
+//! "Now playing" metadata from station specific JSON endpoints.
+//!
+//! Many internet radio stations don't embed ICY titles into their streams but
+//! offer a small JSON document describing the song that is currently on air.
+//! This module polls such an endpoint while the station plays, extracts
+//! artist, title and cover URL via simple dotted paths (e.g.
+//! `now_playing.song.artist` or `data.0.title`) and forwards the result as
+//! [`TitleChanged`] messages.  Until the endpoint has answered, and
+//! whenever it fails, the ICY titles of the stream are shown instead.
+//!
+//! Remote cover art is downloaded into a cache directory so that the UI can
+//! load it like any local cover image.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+
+use anyhow::{Error, anyhow};
+use serde_json::Value;
+use tracing::{debug, error, info, warn};
+
+use crate::{TitleChanged, UNKNOWN};
+
+/// Name of the directory (below the system temp directory) where downloaded
+/// cover images are cached.
+const COVER_CACHE_DIR: &str = "homeplayer-covers";
+
+/// Timeout for a single request to the metadata endpoint or cover URL.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Granularity in which the poll thread checks whether the station is still
+/// playing while it waits for the next poll.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Describes a JSON "now playing" endpoint of a radio station and where to
+/// find the interesting fields in its response.
+///
+/// Paths are dot separated object keys; numeric segments index into arrays.
+/// An empty path means the field is not provided by the endpoint.
+#[derive(Clone, Debug, Default)]
+pub struct StationMetadata {
+    /// URL of the JSON endpoint.
+    pub url: String,
+    /// Path to the artist name.
+    pub artist_path: String,
+    /// Path to the song title.
+    pub title_path: String,
+    /// Path to the URL of the cover image.
+    pub cover_path: String,
+    /// How often the endpoint is requested.
+    pub poll_interval: Duration,
+}
+
+/// The values extracted from one endpoint response.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct NowPlaying {
+    artist: String,
+    title: String,
+    cover_url: String,
+}
+
+/// Decides which titles of a station are announced: those of its "now
+/// playing" endpoint while it works, and the ICY titles of the stream
+/// otherwise.
+#[derive(Clone)]
+pub(crate) struct TitleSource {
+    sender: Sender<TitleChanged>,
+    /// The endpoint returned a title and has not failed since.
+    endpoint_ok: Arc<AtomicBool>,
+    /// The latest ICY title, to go back to when the endpoint fails.
+    last_icy: Arc<Mutex<Option<TitleChanged>>>,
+}
+
+impl TitleSource {
+    pub(crate) fn new(sender: Sender<TitleChanged>) -> Self {
+        Self {
+            sender,
+            endpoint_ok: Arc::default(),
+            last_icy: Arc::default(),
+        }
+    }
+
+    /// A title from the ICY metadata of the stream.
+    pub(crate) fn icy_title(&self, title: TitleChanged) {
+        *self.last_icy.lock().unwrap() = Some(title.clone());
+        if !self.endpoint_ok.load(Ordering::SeqCst) {
+            let _ = self.sender.send(title);
+        }
+    }
+
+    /// A title from the endpoint.
+    fn endpoint_title(&self, title: TitleChanged) {
+        self.endpoint_ok.store(true, Ordering::SeqCst);
+        let _ = self.sender.send(title);
+    }
+
+    /// The endpoint could not be asked; go back to the ICY titles.
+    fn endpoint_failed(&self) {
+        if self.endpoint_ok.swap(false, Ordering::SeqCst)
+            && let Some(title) = self.last_icy.lock().unwrap().clone()
+        {
+            let _ = self.sender.send(title);
+        }
+    }
+}
+
+/// Spawn a thread that polls the given metadata endpoint until `active` is
+/// reset, i.e. until the station is stopped, skipped or replaced by other
+/// playback.
+///
+/// `icon` is used as cover whenever the endpoint does not provide one (or the
+/// download fails).
+pub(crate) fn spawn_metadata_poller(
+    metadata: StationMetadata,
+    icon: String,
+    active: Arc<AtomicBool>,
+    titles: TitleSource,
+) {
+    let _ = spawn(move || {
+        let client = match reqwest::blocking::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Could not create HTTP client for station metadata: {e}");
+                return;
+            }
+        };
+        info!("Start polling station metadata from {}", metadata.url);
+
+        let is_current = || active.load(Ordering::SeqCst);
+        let mut last: Option<NowPlaying> = None;
+
+        while is_current() {
+            match fetch_now_playing(&client, &metadata) {
+                Ok(now_playing) if last.as_ref() != Some(&now_playing) => {
+                    let cover = cover_for(&client, &now_playing.cover_url, &icon);
+                    // The station might have been stopped while we were
+                    // waiting for the response.
+                    if !is_current() {
+                        break;
+                    }
+                    debug!(
+                        "Station metadata: {} - {}",
+                        now_playing.artist, now_playing.title
+                    );
+                    titles.endpoint_title(TitleChanged {
+                        artist: now_playing.artist.clone(),
+                        album: UNKNOWN.to_string(),
+                        title: now_playing.title.clone(),
+                        cover,
+                        is_stream: true,
+                    });
+                    last = Some(now_playing);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Could not get station metadata: {e}");
+                    titles.endpoint_failed();
+                    // Announce the title again once the endpoint is back.
+                    last = None;
+                }
+            }
+
+            let next_poll = Instant::now() + metadata.poll_interval;
+            while is_current() && Instant::now() < next_poll {
+                sleep(STOP_CHECK_INTERVAL);
+            }
+        }
+        debug!("Stop polling station metadata from {}", metadata.url);
+    });
+}
+
+/// Request the endpoint once and extract the configured fields.
+fn fetch_now_playing(
+    client: &reqwest::blocking::Client,
+    metadata: &StationMetadata,
+) -> Result<NowPlaying, Error> {
+    let body = client
+        .get(&metadata.url)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    let json: Value = serde_json::from_str(&body)?;
+    let title = lookup(&json, &metadata.title_path)
+        .ok_or_else(|| anyhow!("No title at '{}'", metadata.title_path))?;
+    Ok(NowPlaying {
+        artist: lookup(&json, &metadata.artist_path).unwrap_or_default(),
+        title,
+        cover_url: lookup(&json, &metadata.cover_path).unwrap_or_default(),
+    })
+}
+
+/// Resolve a dotted path like `data.0.song.title` in `json` and return the
+/// value as string.  Returns `None` for empty paths, missing entries, `null`
+/// and empty strings.
+fn lookup(json: &Value, path: &str) -> Option<String> {
+    if path.trim().is_empty() {
+        return None;
+    }
+    let mut current = json;
+    for segment in path.split('.') {
+        current = match current {
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            Value::Object(map) => map.get(segment)?,
+            _ => return None,
+        };
+    }
+    let value = match current {
+        Value::String(s) => s.trim().to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => return None,
+    };
+    (!value.is_empty()).then_some(value)
+}
+
+/// Return a local path to the cover image at `cover_url`, downloading it into
+/// the cache if necessary.  Falls back to `icon` if there is no cover URL or
+/// it cannot be downloaded.
+fn cover_for(client: &reqwest::blocking::Client, cover_url: &str, icon: &str) -> String {
+    if cover_url.is_empty() {
+        return icon.to_string();
+    }
+    match download_cover(client, cover_url) {
+        Ok(path) => path.to_string_lossy().to_string(),
+        Err(e) => {
+            warn!("Could not download cover {cover_url}: {e}");
+            icon.to_string()
+        }
+    }
+}
+
+fn download_cover(client: &reqwest::blocking::Client, cover_url: &str) -> Result<PathBuf, Error> {
+    let path = cover_cache_path(cover_url);
+    if path.exists() {
+        return Ok(path);
+    }
+    let bytes = client.get(cover_url).send()?.error_for_status()?.bytes()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, &bytes)?;
+    debug!("Cached cover {cover_url} as {}", path.display());
+    Ok(path)
+}
+
+/// Cache file name for a cover URL.  The extension of the URL is kept (if it
+/// is a known image type) because the UI detects the image format by it.
+fn cover_cache_path(cover_url: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    cover_url.hash(&mut hasher);
+    let url_path = cover_url.split(['?', '#']).next().unwrap_or_default();
+    let extension = match url_path.rsplit_once('.') {
+        Some((_, ext)) if ext.eq_ignore_ascii_case("png") => "png",
+        _ => "jpg",
+    };
+    std::env::temp_dir()
+        .join(COVER_CACHE_DIR)
+        .join(format!("{:016x}.{extension}", hasher.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn title(title: &str) -> TitleChanged {
+        TitleChanged {
+            title: title.to_string(),
+            ..TitleChanged::default()
+        }
+    }
+
+    #[test]
+    fn test_icy_titles_until_endpoint_works() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let titles = TitleSource::new(sender);
+        let received = || -> Vec<String> { receiver.try_iter().map(|t| t.title).collect() };
+
+        titles.icy_title(title("icy 1"));
+        assert_eq!(received(), ["icy 1"]);
+        titles.endpoint_title(title("endpoint 1"));
+        titles.icy_title(title("icy 2"));
+        assert_eq!(received(), ["endpoint 1"]);
+        // Back to the latest ICY title when the endpoint fails.
+        titles.endpoint_failed();
+        titles.endpoint_failed();
+        titles.icy_title(title("icy 3"));
+        assert_eq!(received(), ["icy 2", "icy 3"]);
+        titles.endpoint_title(title("endpoint 2"));
+        assert_eq!(received(), ["endpoint 2"]);
+    }
+
+    #[test]
+    fn test_lookup_nested_object() {
+        let value = json!({"now": {"song": {"artist": " Queen "}}});
+        assert_eq!(lookup(&value, "now.song.artist"), Some("Queen".to_string()));
+    }
+
+    #[test]
+    fn test_lookup_array_index() {
+        let value = json!({"data": [{"title": "First"}, {"title": "Second"}]});
+        assert_eq!(lookup(&value, "data.1.title"), Some("Second".to_string()));
+    }
+
+    #[test]
+    fn test_lookup_missing_and_empty() {
+        let value = json!({"title": "", "artist": null});
+        assert_eq!(lookup(&value, "title"), None);
+        assert_eq!(lookup(&value, "artist"), None);
+        assert_eq!(lookup(&value, "album"), None);
+        assert_eq!(lookup(&value, ""), None);
+    }
+
+    #[test]
+    fn test_cover_cache_path_extension() {
+        assert!(
+            cover_cache_path("http://example.com/a.PNG?size=300")
+                .to_string_lossy()
+                .ends_with(".png")
+        );
+        assert!(
+            cover_cache_path("http://example.com/cover")
+                .to_string_lossy()
+                .ends_with(".jpg")
+        );
+    }
+}
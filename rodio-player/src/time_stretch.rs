@@ -0,0 +1,493 @@
+//! Playback speed control that keeps the pitch, for audio books and
+//! language courses.
+//!
+//! [`TimeStretch`] uses WSOLA (waveform similarity overlap-add): the output is
+//! built from overlapping, Hann-windowed segments of the input.  The segments
+//! are taken from the input faster or slower than they are written, and each
+//! one is shifted by a few milliseconds so that it continues the waveform of
+//! the previous segment as closely as possible.  Speech stays natural up to
+//! about twice the speed.
+//!
+//! Sources are passed through untouched while the speed is 1.0.  The sink
+//! counts played time, which drifts from the position in the item while the
+//! speed is not 1.0, so [`TimeStretch`] reports the position in its source
+//! as [`PlaybackPosition`] instead.
+
+use std::collections::VecDeque;
+use std::num::NonZero;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+use rodio::Source;
+use rodio::source::SeekError;
+
+/// Slowest supported speed.
+pub const MIN_SPEED: f32 = 0.5;
+
+/// Fastest supported speed.
+pub const MAX_SPEED: f32 = 2.0;
+
+/// Length of a segment.
+const WINDOW: Duration = Duration::from_millis(30);
+
+/// How far a segment may be shifted to match the previous one.
+const TOLERANCE: Duration = Duration::from_millis(10);
+
+/// Step of the coarse similarity search, in frames.
+const COARSE_STEP: usize = 4;
+
+/// Playback speed, shared between the player and its sources.
+#[derive(Clone)]
+pub(crate) struct SpeedControl(Arc<AtomicU32>);
+
+impl Default for SpeedControl {
+    fn default() -> Self {
+        Self(Arc::new(AtomicU32::new(1f32.to_bits())))
+    }
+}
+
+impl SpeedControl {
+    pub(crate) fn set(&self, speed: f32) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.0.store(speed.to_bits(), Ordering::SeqCst);
+    }
+
+    pub(crate) fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// Position in the source of the audio being played, shared between the
+/// player and its sources.
+#[derive(Clone, Default)]
+pub(crate) struct PlaybackPosition(Arc<AtomicU64>);
+
+impl PlaybackPosition {
+    pub(crate) fn set(&self, position: Duration) {
+        self.0.store(position.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn get(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// Changes the speed of a source without changing its pitch.
+pub(crate) struct TimeStretch<S> {
+    source: S,
+    speed: SpeedControl,
+    /// Where in the source the output is, for the player.
+    reported: PlaybackPosition,
+    channels: usize,
+    sample_rate: u32,
+    /// Segment length in frames; even.
+    window_len: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+    /// Frames read from the source so far (while passing through) or the
+    /// position at which stretching started, counted from the start.
+    consumed: i64,
+    /// Source position of the next output frame while stretching.
+    output_position: f64,
+    /// Samples of the current frame already played.
+    frame_pos: usize,
+    stretching: bool,
+    /// Buffered input, interleaved, starting at frame `base`.
+    input: Vec<f32>,
+    /// Mono mix of `input`, used to compare waveforms.
+    mono: Vec<f32>,
+    base: i64,
+    /// Frame after the last one of the source, once it ended.
+    end: Option<i64>,
+    /// Nominal input position of the next segment.
+    position: f64,
+    /// Input position the previous segment was taken from.
+    previous: i64,
+    /// Overlap-add accumulator, one segment long.
+    accumulator: Vec<f32>,
+    /// Output while stretching; input read ahead after stretching stopped.
+    out: VecDeque<f32>,
+    finished: bool,
+}
+
+impl<S: Source> TimeStretch<S> {
+    pub(crate) fn new(source: S, speed: SpeedControl, reported: PlaybackPosition) -> Self {
+        let channels = source.channels().get() as usize;
+        let sample_rate = source.sample_rate().get();
+        let frames = |time: Duration| (time.as_secs_f64() * sample_rate as f64) as usize;
+        let window_len = frames(WINDOW).max(8) & !1;
+        let window = (0..window_len)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * i as f32 / window_len as f32;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+        reported.set(Duration::ZERO);
+        Self {
+            source,
+            speed,
+            reported,
+            channels,
+            sample_rate,
+            window_len,
+            tolerance: frames(TOLERANCE),
+            window,
+            consumed: 0,
+            output_position: 0.0,
+            frame_pos: 0,
+            stretching: false,
+            input: Vec::new(),
+            mono: Vec::new(),
+            base: 0,
+            end: None,
+            position: 0.0,
+            previous: 0,
+            accumulator: vec![0.0; window_len * channels],
+            out: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    fn hop(&self) -> usize {
+        self.window_len / 2
+    }
+
+    /// Report source frame `frame` as the position played.
+    fn report(&self, frame: f64) {
+        self.reported.set(Duration::from_secs_f64(
+            frame.max(0.0) / self.sample_rate as f64,
+        ));
+    }
+
+    /// Buffer the input up to (excluding) frame `until`.
+    fn fill(&mut self, until: i64) {
+        while self.end.is_none() && self.base + (self.mono.len() as i64) < until {
+            let start = self.input.len();
+            self.input.extend(self.source.by_ref().take(self.channels));
+            if self.input.len() - start < self.channels {
+                self.input.truncate(start);
+                self.end = Some(self.base + self.mono.len() as i64);
+                break;
+            }
+            let sum: f32 = self.input[start..].iter().sum();
+            self.mono.push(sum / self.channels as f32);
+        }
+    }
+
+    /// Sample of `channel` at absolute `frame`; silence outside the input.
+    fn sample(&self, frame: i64, channel: usize) -> f32 {
+        let index = frame - self.base;
+        if index < 0 || index as usize >= self.mono.len() {
+            return 0.0;
+        }
+        self.input[index as usize * self.channels + channel]
+    }
+
+    fn mono(&self, frame: i64) -> f32 {
+        let index = frame - self.base;
+        if index < 0 || index as usize >= self.mono.len() {
+            return 0.0;
+        }
+        self.mono[index as usize]
+    }
+
+    /// Similarity of the segment at `candidate` with the one at `natural`,
+    /// comparing every `step`th frame of the overlap.
+    fn similarity(&self, candidate: i64, natural: i64, step: usize) -> f32 {
+        let (mut product, mut energy) = (0.0, 1e-9);
+        for i in (0..self.hop() as i64).step_by(step) {
+            let value = self.mono(candidate + i);
+            product += value * self.mono(natural + i);
+            energy += value * value;
+        }
+        product / energy.sqrt()
+    }
+
+    /// Input position near `target` whose waveform continues the previous
+    /// segment best.
+    fn best_position(&self, target: i64) -> i64 {
+        let natural = self.previous + self.hop() as i64;
+        let tolerance = self.tolerance as i64;
+        let lowest = (target - tolerance).max(self.base);
+        let highest = (target + tolerance).max(lowest);
+        let mut best = (lowest, f32::MIN);
+        for candidate in (lowest..=highest).step_by(COARSE_STEP) {
+            let similarity = self.similarity(candidate, natural, COARSE_STEP);
+            if similarity > best.1 {
+                best = (candidate, similarity);
+            }
+        }
+        let coarse = best.0;
+        best = (coarse, self.similarity(coarse, natural, 1));
+        for candidate in (coarse - COARSE_STEP as i64 + 1)..(coarse + COARSE_STEP as i64) {
+            if candidate < lowest || candidate > highest {
+                continue;
+            }
+            let similarity = self.similarity(candidate, natural, 1);
+            if similarity > best.1 {
+                best = (candidate, similarity);
+            }
+        }
+        best.0
+    }
+
+    /// Start stretching at the current position.  The accumulator gets the
+    /// fading half of an imaginary previous segment, so the first output
+    /// matches the input without a dip.
+    fn start_stretching(&mut self) {
+        let start = self.consumed;
+        let hop = self.hop();
+        self.stretching = true;
+        self.base = start;
+        self.input.clear();
+        self.mono.clear();
+        self.end = None;
+        self.position = start as f64;
+        self.previous = start - hop as i64;
+        self.accumulator.fill(0.0);
+        self.fill(start + hop as i64);
+        for i in 0..hop {
+            for channel in 0..self.channels {
+                self.accumulator[i * self.channels + channel] =
+                    self.sample(start + i as i64, channel) * self.window[hop + i];
+            }
+        }
+    }
+
+    /// Go back to passing the source through, with the input that the last
+    /// segment continues into.  The input read ahead for stretching is
+    /// played first.
+    fn stop_stretching(&mut self) {
+        let start = self.previous + self.hop() as i64;
+        let skip = (start - self.base).max(0) as usize * self.channels;
+        self.out.extend(self.input.drain(..).skip(skip));
+        self.mono.clear();
+        self.consumed = start;
+        self.stretching = false;
+    }
+
+    /// Overlap-add the next segment and move a hop of output to `out`.
+    fn stretch_step(&mut self) {
+        let hop = self.hop();
+        let target = self.position.round() as i64;
+        let tolerance = self.tolerance as i64;
+        self.fill((target + tolerance).max(self.previous + hop as i64) + self.window_len as i64);
+        if self.end.is_some_and(|end| target >= end) {
+            // Play the fading tail of the last segment, then stop.
+            self.out
+                .extend(self.accumulator.drain(..hop * self.channels));
+            self.finished = true;
+            return;
+        }
+
+        let chosen = self.best_position(target);
+        for i in 0..self.window_len {
+            for channel in 0..self.channels {
+                self.accumulator[i * self.channels + channel] +=
+                    self.sample(chosen + i as i64, channel) * self.window[i];
+            }
+        }
+        self.out
+            .extend(self.accumulator.drain(..hop * self.channels));
+        self.accumulator
+            .extend(std::iter::repeat_n(0.0, hop * self.channels));
+        self.previous = chosen;
+        self.output_position = chosen as f64;
+        self.position += hop as f64 * self.speed.get() as f64;
+
+        // Drop input no later segment needs.
+        let needed = (self.previous + hop as i64)
+            .min(self.position as i64 - tolerance)
+            .max(self.base);
+        let unused = (needed - self.base) as usize;
+        if unused > self.window_len {
+            self.input.drain(..unused * self.channels);
+            self.mono.drain(..unused);
+            self.base = needed;
+        }
+    }
+}
+
+impl<S: Source> Iterator for TimeStretch<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if !self.stretching {
+            // Switch only on a frame boundary, to keep the channels in order,
+            // and once the input read ahead is played.
+            if self.frame_pos == 0 && self.out.is_empty() && self.speed.get() != 1.0 {
+                self.start_stretching();
+            } else {
+                let sample = match self.out.pop_front() {
+                    Some(sample) => sample,
+                    None => self.source.next()?,
+                };
+                self.frame_pos += 1;
+                if self.frame_pos == self.channels {
+                    self.frame_pos = 0;
+                    self.consumed += 1;
+                    self.report(self.consumed as f64);
+                }
+                return Some(sample);
+            }
+        }
+        // `out` holds whole hops, so it is empty on a frame boundary.
+        if self.out.is_empty() && !self.finished && self.speed.get() == 1.0 {
+            self.stop_stretching();
+            return self.next();
+        }
+        while self.out.is_empty() {
+            if self.finished {
+                return None;
+            }
+            self.stretch_step();
+        }
+        let sample = self.out.pop_front();
+        self.frame_pos += 1;
+        if self.frame_pos == self.channels {
+            self.frame_pos = 0;
+            self.output_position += self.speed.get() as f64;
+            self.report(self.output_position);
+        }
+        sample
+    }
+}
+
+impl<S: Source> Source for TimeStretch<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> NonZero<u16> {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> NonZero<u32> {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)?;
+        self.consumed = (pos.as_secs_f64() * self.sample_rate as f64) as i64;
+        self.frame_pos = 0;
+        self.stretching = false;
+        self.finished = false;
+        self.input.clear();
+        self.mono.clear();
+        self.out.clear();
+        self.report(self.consumed as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const RATE: u32 = 44_100;
+
+    /// A stereo sine of the given frequency, one second long.
+    fn sine(frequency: f32) -> SamplesBuffer {
+        let samples = (0..RATE)
+            .flat_map(|n| {
+                let value =
+                    0.5 * (2.0 * std::f32::consts::PI * frequency * n as f32 / RATE as f32).sin();
+                [value, value]
+            })
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(
+            NonZero::new(2).unwrap(),
+            NonZero::new(RATE).unwrap(),
+            samples,
+        )
+    }
+
+    fn stretched(speed: f32) -> Vec<f32> {
+        let control = SpeedControl::default();
+        control.set(speed);
+        TimeStretch::new(sine(440.0), control, PlaybackPosition::default()).collect()
+    }
+
+    /// Frequency of the left channel, from its zero crossings.
+    fn frequency(samples: &[f32]) -> f32 {
+        let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+        let crossings = left
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        crossings as f32 * RATE as f32 / left.len() as f32
+    }
+
+    #[test]
+    fn test_speed_changes_duration() {
+        for speed in [0.5, 1.5, 2.0] {
+            let frames = stretched(speed).len() / 2;
+            let expected = RATE as f32 / speed;
+            assert!(
+                (frames as f32 - expected).abs() < 0.05 * expected,
+                "{speed}: {frames}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_pitch_is_kept() {
+        for speed in [0.5, 2.0] {
+            let samples = stretched(speed);
+            // Leave out the fades at both ends.
+            let middle = &samples[samples.len() / 4..samples.len() * 3 / 4];
+            let frequency = frequency(middle);
+            assert!((frequency - 440.0).abs() < 10.0, "{speed}: {frequency}");
+        }
+    }
+
+    #[test]
+    fn test_normal_speed_passes_through() {
+        let samples: Vec<f32> = TimeStretch::new(
+            sine(440.0),
+            SpeedControl::default(),
+            PlaybackPosition::default(),
+        )
+        .collect();
+        assert_eq!(samples, sine(440.0).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_position_follows_the_source() {
+        let control = SpeedControl::default();
+        let position = PlaybackPosition::default();
+        let mut stretch = TimeStretch::new(sine(440.0), control.clone(), position.clone());
+        // A quarter of a second of output each
+        let quarter = RATE as usize / 2;
+        stretch.by_ref().take(quarter).count();
+        assert_eq!(position.get(), Duration::from_millis(250));
+
+        control.set(2.0);
+        stretch.by_ref().take(quarter).count();
+        let seconds = position.get().as_secs_f32();
+        assert!((seconds - 0.75).abs() < 0.02, "{seconds}");
+
+        // Back at normal speed, the rest of the source passes through.
+        control.set(1.0);
+        stretch.by_ref().take(quarter / 5).count();
+        let frame = (position.get().as_secs_f64() * RATE as f64).round() as usize;
+        let rest: Vec<f32> = stretch.collect();
+        assert_eq!(rest, sine(440.0).skip(frame * 2).collect::<Vec<_>>());
+        assert_eq!(position.get(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_speed_is_clamped() {
+        let control = SpeedControl::default();
+        control.set(5.0);
+        assert_eq!(control.get(), MAX_SPEED);
+        control.set(0.1);
+        assert_eq!(control.get(), MIN_SPEED);
+    }
+}
@@ -0,0 +1,363 @@
+//! Ripping the audio tracks of a CD into the folder of a library source.
+//!
+//! The tracks are written to `<source folder>/<artist>/<album>/NN title.flac`
+//! and tagged with the names of the disc, which come from CD-Text, the
+//! online lookup or the CD editor.  The cover of the disc is copied next to
+//! them, so the scan of the folder picks it up like for any other album.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+
+use anyhow::Error;
+use lofty::config::WriteOptions;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::tag::{Accessor, Tag};
+use rodio_player::cd_audio::{CdInfo, CdReadSettings, CdTrackInfo};
+use rodio_player::cd_rip::{self, RipFormat};
+use tracing::{info, warn};
+
+/// Longest file or folder name written, in bytes.  Leaves room for the
+/// track number and extension within the usual limit of 255 bytes.
+const MAX_NAME_LEN: usize = 200;
+
+/// What to rip and where to.
+pub struct RipJob {
+    /// CD device, e.g. `/dev/cdrom`.
+    pub device: String,
+    pub disc: CdInfo,
+    /// How the disc is read, and where read errors are counted.
+    pub read: CdReadSettings,
+    /// Folder of the library source the album folder is created in.
+    pub target_dir: PathBuf,
+    pub format: RipFormat,
+    /// Read every track a second time and compare the checksums.
+    pub verify: bool,
+}
+
+/// How ripping a track went.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackOutcome {
+    /// Ripped, without a second read.
+    Ripped {
+        crc: u32,
+    },
+    /// Ripped, and a second read gave the same checksum.
+    Verified {
+        crc: u32,
+    },
+    /// The second read gave a different checksum, so at least one of the
+    /// reads was bad.
+    Mismatch {
+        crc: u32,
+        reread_crc: u32,
+    },
+    Failed(String),
+}
+
+/// Sent by the ripping thread.
+#[derive(Debug)]
+pub enum RipEvent {
+    /// The audio track at `index` is being read; `done` is the part of the
+    /// whole disc that is done.
+    Progress {
+        index: usize,
+        done: f32,
+    },
+    Track {
+        number: u8,
+        outcome: TrackOutcome,
+    },
+    /// No more tracks will be ripped.  The album folder can be scanned.
+    Finished {
+        album_dir: PathBuf,
+        cancelled: bool,
+    },
+    /// The album folder could not be created; nothing was ripped.
+    Failed(String),
+}
+
+/// Progress and results of a rip, shown on the CD page.
+#[derive(Debug, Clone)]
+pub struct RipStatus {
+    /// Index of the library source the disc is ripped into.
+    pub target_idx: usize,
+    /// Index of the audio track being read.
+    pub index: usize,
+    /// Number of audio tracks.
+    pub total: usize,
+    /// Part of the whole disc that is done.
+    pub done: f32,
+    /// Outcome by track number, in the order the tracks were ripped.
+    pub results: Vec<(u8, TrackOutcome)>,
+    pub finished: bool,
+    pub cancelled: bool,
+    pub error: Option<String>,
+}
+
+impl RipStatus {
+    pub fn new(target_idx: usize, total: usize) -> Self {
+        Self {
+            target_idx,
+            index: 0,
+            total,
+            done: 0.0,
+            results: Vec::new(),
+            finished: false,
+            cancelled: false,
+            error: None,
+        }
+    }
+
+    pub fn apply(&mut self, event: &RipEvent) {
+        match event {
+            RipEvent::Progress { index, done } => {
+                self.index = *index;
+                self.done = *done;
+            }
+            RipEvent::Track { number, outcome } => self.results.push((*number, outcome.clone())),
+            RipEvent::Finished { cancelled, .. } => {
+                self.finished = true;
+                self.cancelled = *cancelled;
+                if !cancelled {
+                    self.done = 1.0;
+                }
+            }
+            RipEvent::Failed(error) => {
+                self.finished = true;
+                self.error = Some(error.clone());
+            }
+        }
+    }
+}
+
+/// Rip the disc on a background thread.  Setting `cancel` stops after the
+/// part of the track that is being read.
+pub fn start(job: RipJob, cancel: Arc<AtomicBool>) -> mpsc::Receiver<RipEvent> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || rip(&job, &cancel, &tx));
+    rx
+}
+
+fn rip(job: &RipJob, cancel: &AtomicBool, tx: &mpsc::Sender<RipEvent>) {
+    let album_dir = album_dir(&job.target_dir, &job.disc);
+    info!("Ripping disc to {}", album_dir.display());
+    if let Err(e) = fs::create_dir_all(&album_dir) {
+        let _ = tx.send(RipEvent::Failed(format!(
+            "Could not create {}: {e}",
+            album_dir.display()
+        )));
+        return;
+    }
+    if let Some(cover) = &job.disc.cover
+        && let Err(e) = copy_cover(Path::new(cover), &album_dir)
+    {
+        warn!("Could not copy cover {cover}: {e}");
+    }
+
+    let tracks = job.disc.audio_tracks();
+    let total = tracks.len();
+    // With verification, reading a track twice takes twice as long.
+    let passes = if job.verify { 2.0 } else { 1.0 };
+    for (index, track) in tracks.iter().enumerate() {
+        if cancel.load(Ordering::SeqCst) {
+            break;
+        }
+        let progress = |pass: f32, part: f32| {
+            let _ = tx.send(RipEvent::Progress {
+                index,
+                done: (index as f32 + (pass + part) / passes) / total as f32,
+            });
+        };
+        let path = album_dir.join(track_file_name(track, job.format));
+        let outcome = match cd_rip::rip_track(
+            &job.device,
+            track,
+            &job.read,
+            &path,
+            job.format,
+            cancel,
+            |p| progress(0.0, p),
+        ) {
+            Ok(crc) => {
+                if let Err(e) = tag_track(&path, &job.disc, track, total) {
+                    warn!("Could not tag {}: {e}", path.display());
+                }
+                if job.verify {
+                    match cd_rip::read_track_crc(
+                        &job.device,
+                        &job.disc,
+                        track,
+                        &job.read,
+                        cancel,
+                        |p| progress(1.0, p),
+                    ) {
+                        Ok(reread_crc) if reread_crc == crc => TrackOutcome::Verified { crc },
+                        Ok(reread_crc) => TrackOutcome::Mismatch { crc, reread_crc },
+                        Err(e) => TrackOutcome::Failed(e.to_string()),
+                    }
+                } else {
+                    TrackOutcome::Ripped { crc }
+                }
+            }
+            Err(e) => TrackOutcome::Failed(e.to_string()),
+        };
+        if cancel.load(Ordering::SeqCst) {
+            break;
+        }
+        info!("Track {}: {outcome:?}", track.number);
+        let _ = tx.send(RipEvent::Track {
+            number: track.number,
+            outcome,
+        });
+    }
+    let _ = tx.send(RipEvent::Finished {
+        album_dir,
+        cancelled: cancel.load(Ordering::SeqCst),
+    });
+}
+
+/// The album folder of the disc: `<target>/<artist>/<album>`.
+fn album_dir(target_dir: &Path, disc: &CdInfo) -> PathBuf {
+    target_dir
+        .join(file_name(&album_artist(disc)))
+        .join(file_name(&album_name(disc)))
+}
+
+fn album_artist(disc: &CdInfo) -> String {
+    disc.performer
+        .clone()
+        .unwrap_or_else(|| "Unknown Artist".to_string())
+}
+
+/// Album title of the disc.  Discs without one get their FreeDB ID in the
+/// name, so rips of different unnamed discs don't end up in one album.
+fn album_name(disc: &CdInfo) -> String {
+    disc.title.clone().unwrap_or_else(|| match disc.disc_id() {
+        Some(id) => format!("Audio CD {}", id.freedb),
+        None => "Audio CD".to_string(),
+    })
+}
+
+fn track_file_name(track: &CdTrackInfo, format: RipFormat) -> String {
+    format!(
+        "{:02} {}.{}",
+        track.number,
+        file_name(&track.display_title()),
+        format.extension()
+    )
+}
+
+/// Make a name usable as a file name on all file systems the library may be
+/// on: no path separators or characters Windows forbids, no leading or
+/// trailing dots and spaces, and not too long.
+fn file_name(name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let mut trimmed = replaced.trim_matches(|c| c == '.' || c == ' ').to_string();
+    if trimmed.len() > MAX_NAME_LEN {
+        let mut end = MAX_NAME_LEN;
+        while !trimmed.is_char_boundary(end) {
+            end -= 1;
+        }
+        trimmed.truncate(end);
+        trimmed = trimmed.trim_end_matches([' ', '.']).to_string();
+    }
+    if trimmed.is_empty() {
+        "_".to_string()
+    } else {
+        trimmed
+    }
+}
+
+fn tag_track(path: &Path, disc: &CdInfo, track: &CdTrackInfo, total: usize) -> Result<(), Error> {
+    let mut file = lofty::read_from_path(path)?;
+    let mut tag = Tag::new(file.primary_tag_type());
+    tag.set_artist(
+        track
+            .performer
+            .clone()
+            .unwrap_or_else(|| album_artist(disc)),
+    );
+    tag.set_album(album_name(disc));
+    tag.set_title(track.display_title());
+    tag.set_track(track.number as u32);
+    tag.set_track_total(total as u32);
+    file.insert_tag(tag);
+    file.save_to_path(path, WriteOptions::default())?;
+    Ok(())
+}
+
+/// Copy the cover into the album folder as `cover.<extension>`.
+fn copy_cover(cover: &Path, album_dir: &Path) -> Result<(), Error> {
+    let extension = cover
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("jpg")
+        .to_ascii_lowercase();
+    fs::copy(cover, album_dir.join(format!("cover.{extension}")))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn track(number: u8, title: Option<&str>) -> CdTrackInfo {
+        CdTrackInfo {
+            number,
+            start_lba: 0,
+            end_lba: 75,
+            duration: Duration::from_secs(1),
+            is_audio: true,
+            pre_emphasis: false,
+            pregap: 0,
+            title: title.map(str::to_string),
+            performer: None,
+        }
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("AC/DC"), "AC_DC");
+        assert_eq!(file_name("What? Me: Worry*"), "What_ Me_ Worry_");
+        assert_eq!(file_name("..."), "_");
+        assert_eq!(file_name(" .hidden. "), "hidden");
+        assert_eq!(file_name("tab\there"), "tab_here");
+        let long = "ä".repeat(150);
+        let name = file_name(&long);
+        assert!(name.len() <= MAX_NAME_LEN);
+        assert_eq!(name, "ä".repeat(100));
+    }
+
+    #[test]
+    fn test_paths_from_disc_names() {
+        let disc = CdInfo {
+            title: Some("Greatest Hits".to_string()),
+            performer: Some("Simon & Garfunkel".to_string()),
+            tracks: vec![track(1, Some("Mrs. Robinson")), track(2, None)],
+            ..CdInfo::default()
+        };
+        assert_eq!(
+            album_dir(Path::new("/music"), &disc),
+            Path::new("/music/Simon & Garfunkel/Greatest Hits")
+        );
+        assert_eq!(
+            track_file_name(&disc.tracks[0], RipFormat::Flac),
+            "01 Mrs. Robinson.flac"
+        );
+        assert_eq!(
+            track_file_name(&disc.tracks[1], RipFormat::Wav),
+            "02 Track 2.wav"
+        );
+    }
+}
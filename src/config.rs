@@ -1,7 +1,11 @@
 use std::fs::File;
 use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::anyhow;
+use chrono::NaiveTime;
+use rodio_player::{DeviceOutput, HardwareMixer, OutputBackend, SilenceSettings, announcement};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, error};
 
@@ -10,8 +14,24 @@ use tracing::{debug, error};
 pub struct Config {
     pub sources: Vec<Source>,
     pub audio: AudioConfig,
+    /// Additional playback zones, each with its own output, queue and
+    /// volume.  `audio` configures the main zone.
+    #[serde(default)]
+    pub zones: Vec<ZoneConfig>,
     #[serde(default)]
     pub ui: UiConfig,
+    #[serde(default)]
+    pub cd_metadata: CdMetadataConfig,
+    #[serde(default)]
+    pub cd_read: CdReadConfig,
+    #[serde(default)]
+    pub cd_insert: CdInsertConfig,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ZoneConfig {
+    pub name: String,
+    pub audio: AudioConfig,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
@@ -37,10 +57,51 @@ pub struct Station {
     pub name: String,
     pub url: String,
     pub icon: String,
+    /// Optional JSON "now playing" endpoint of the station.
+    #[serde(default)]
+    pub metadata: Option<StationMetadataConfig>,
+    /// Level change of the station in dB, for stations that are still too
+    /// loud or too quiet after loudness normalisation.
+    #[serde(default)]
+    pub gain_db: f32,
 }
 
+/// Configuration of a station's "now playing" endpoint.  The paths are dot
+/// separated keys into the JSON response (numeric segments index arrays),
+/// e.g. `data.0.song.title`.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(unused)]
+pub struct StationMetadataConfig {
+    pub url: String,
+    #[serde(default)]
+    pub artist_path: String,
+    #[serde(default)]
+    pub title_path: String,
+    #[serde(default)]
+    pub cover_path: String,
+    /// Poll interval in seconds.
+    #[serde(default = "default_metadata_interval")]
+    pub interval_secs: u64,
+}
+
+fn default_metadata_interval() -> u64 {
+    15
+}
+
+impl Default for StationMetadataConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            artist_path: String::new(),
+            title_path: String::new(),
+            cover_path: String::new(),
+            interval_secs: default_metadata_interval(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[allow(unused)]
 pub struct AudioConfig {
     pub start_volume: u8,
     /// Maximum volume the player is allowed to reach (0–100).  Defaults to
@@ -51,12 +112,224 @@ pub struct AudioConfig {
     /// system default device is used.
     #[serde(default)]
     pub device: Option<String>,
+    /// Several devices to play on at once.  Replaces `device` when not
+    /// empty.
+    #[serde(default)]
+    pub devices: Vec<OutputDeviceConfig>,
+    /// Where the audio goes.  Defaults to the sound card `device`.
+    #[serde(default)]
+    pub backend: AudioBackend,
+    /// Path of the named pipe or WAV file for the `Pipe` and `WavFile`
+    /// backends.
+    #[serde(default)]
+    pub output_path: String,
+    /// ALSA mixer control to set the volume with.  When `None` the volume is
+    /// applied in software.
+    #[serde(default)]
+    pub hardware_mixer: Option<HardwareMixerConfig>,
+    /// How much the music is turned down while an announcement plays, in dB.
+    #[serde(default = "default_duck_db")]
+    pub duck_db: f32,
+    /// Lower maximum volume and compressed dynamics at night.
+    #[serde(default)]
+    pub night_mode: Option<NightModeConfig>,
+    /// Loudness internet streams are normalised to, in LUFS.  When `None`
+    /// streams play at their own level.
+    #[serde(default)]
+    pub stream_loudness_lufs: Option<f32>,
+    /// Skip silence at the start and end of files.
+    #[serde(default)]
+    pub trim_silence: bool,
+    /// Audio below this level in dBFS counts as silence.
+    #[serde(default = "default_silence_threshold_db")]
+    pub silence_threshold_db: f32,
+    /// Seconds of silence after which a station is reconnected, or another
+    /// station of the same source is played.  `0` (the default) disables the
+    /// check.
+    #[serde(default)]
+    pub dead_air_secs: u32,
+}
+
+fn default_silence_threshold_db() -> f32 {
+    -60.0
+}
+
+/// Stream loudness when normalisation is switched on in the settings.
+pub const DEFAULT_STREAM_LOUDNESS_LUFS: f32 = -18.0;
+
+fn default_duck_db() -> f32 {
+    announcement::DEFAULT_DUCK_DB
 }
 
 fn default_max_volume() -> u8 {
     100
 }
 
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub enum AudioBackend {
+    /// Sound card device, see `AudioConfig::device`.
+    #[default]
+    Device,
+    /// Raw PCM (16 bit, stereo, 44.1 kHz) into a named pipe, e.g. for Snapcast.
+    Pipe,
+    /// Record into a WAV file.
+    WavFile,
+    /// Discard the audio (headless operation).
+    Null,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct OutputDeviceConfig {
+    /// Device name as listed by the audio back-end, or `"Default"`.
+    pub name: String,
+    /// Level relative to the player volume, in dB (usually negative).
+    #[serde(default)]
+    pub trim_db: f32,
+    /// Delay in milliseconds, to line up devices with different latency.
+    #[serde(default)]
+    pub delay_ms: u32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct NightModeConfig {
+    /// Local time night mode starts, `HH:MM`.
+    #[serde(default = "default_night_start")]
+    pub start: String,
+    /// Local time night mode ends, `HH:MM`.  May be on the next day.
+    #[serde(default = "default_night_end")]
+    pub end: String,
+    /// Maximum volume during night mode (0–100).
+    #[serde(default = "default_night_max_volume")]
+    pub max_volume: u8,
+    /// Compress the dynamic range, so quiet passages get louder and loud
+    /// ones softer.
+    #[serde(default = "default_true")]
+    pub compress: bool,
+}
+
+fn default_night_start() -> String {
+    "21:00".to_string()
+}
+
+fn default_night_end() -> String {
+    "07:00".to_string()
+}
+
+fn default_night_max_volume() -> u8 {
+    40
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for NightModeConfig {
+    fn default() -> Self {
+        Self {
+            start: default_night_start(),
+            end: default_night_end(),
+            max_volume: default_night_max_volume(),
+            compress: true,
+        }
+    }
+}
+
+impl NightModeConfig {
+    /// Whether night mode is active at the given local time.  Invalid times
+    /// disable night mode.
+    pub fn is_active_at(&self, time: NaiveTime) -> bool {
+        let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M");
+        let (Ok(start), Ok(end)) = (parse(&self.start), parse(&self.end)) else {
+            return false;
+        };
+        if start <= end {
+            start <= time && time < end
+        } else {
+            // Over midnight
+            time >= start || time < end
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct HardwareMixerConfig {
+    /// Mixer device, e.g. `default` or `hw:1`.
+    #[serde(default = "default_mixer_card")]
+    pub card: String,
+    /// Simple mixer control, e.g. `PCM` or `Master`.
+    #[serde(default = "default_mixer_control")]
+    pub control: String,
+}
+
+fn default_mixer_card() -> String {
+    "default".to_string()
+}
+
+fn default_mixer_control() -> String {
+    "Master".to_string()
+}
+
+impl Default for HardwareMixerConfig {
+    fn default() -> Self {
+        Self {
+            card: default_mixer_card(),
+            control: default_mixer_control(),
+        }
+    }
+}
+
+impl AudioConfig {
+    /// Volume at start, limited to the maximum volume (0.0 – 1.0).
+    pub fn initial_volume(&self) -> f32 {
+        self.start_volume.min(self.max_volume).min(100) as f32 / 100.0
+    }
+
+    /// Maximum volume (0.0 – 1.0).
+    pub fn max_volume(&self) -> f32 {
+        self.max_volume as f32 / 100.0
+    }
+
+    /// How the player should treat silence.
+    pub fn silence_settings(&self) -> SilenceSettings {
+        SilenceSettings {
+            threshold_db: self.silence_threshold_db,
+            trim_files: self.trim_silence,
+            dead_air: (self.dead_air_secs > 0)
+                .then(|| Duration::from_secs(self.dead_air_secs as u64)),
+        }
+    }
+
+    /// The hardware mixer described by this configuration, if any.
+    pub fn hardware_mixer(&self) -> Option<HardwareMixer> {
+        self.hardware_mixer.as_ref().map(|mixer| HardwareMixer {
+            card: mixer.card.clone(),
+            control: mixer.control.clone(),
+        })
+    }
+
+    /// The player output described by this configuration.
+    pub fn output_backend(&self) -> OutputBackend {
+        match self.backend {
+            AudioBackend::Device if self.devices.is_empty() => {
+                OutputBackend::Device(self.device.clone())
+            }
+            AudioBackend::Device => OutputBackend::Devices(
+                self.devices
+                    .iter()
+                    .map(|device| DeviceOutput {
+                        name: Some(device.name.clone()),
+                        trim_db: device.trim_db,
+                        delay: Duration::from_millis(device.delay_ms as u64),
+                    })
+                    .collect(),
+            ),
+            AudioBackend::Pipe => OutputBackend::Pipe(PathBuf::from(&self.output_path)),
+            AudioBackend::WavFile => OutputBackend::WavFile(PathBuf::from(&self.output_path)),
+            AudioBackend::Null => OutputBackend::Null,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(unused)]
 pub struct UiConfig {
@@ -79,6 +352,83 @@ impl Default for UiConfig {
     }
 }
 
+/// Where names and covers of audio CDs are looked up.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct CdMetadataConfig {
+    /// Look discs up online; known discs are named from the cache anyway.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_musicbrainz_url")]
+    pub musicbrainz_url: String,
+    #[serde(default = "default_cover_art_url")]
+    pub cover_art_url: String,
+}
+
+fn default_musicbrainz_url() -> String {
+    rodio_player::cd_metadata::MUSICBRAINZ_URL.to_string()
+}
+
+fn default_cover_art_url() -> String {
+    rodio_player::cd_metadata::COVER_ART_ARCHIVE_URL.to_string()
+}
+
+impl Default for CdMetadataConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            musicbrainz_url: default_musicbrainz_url(),
+            cover_art_url: default_cover_art_url(),
+        }
+    }
+}
+
+/// How audio CDs are read.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct CdReadConfig {
+    /// How often a failed or unmatched read is repeated before unreadable
+    /// sectors are interpolated or muted.  More retries save more audio of
+    /// scratched discs, but playback may stall while they are read.
+    #[serde(default = "default_cd_read_retries")]
+    pub retries: u32,
+}
+
+fn default_cd_read_retries() -> u32 {
+    rodio_player::cd_audio::DEFAULT_READ_RETRIES
+}
+
+impl Default for CdReadConfig {
+    fn default() -> Self {
+        Self {
+            retries: default_cd_read_retries(),
+        }
+    }
+}
+
+/// What happens when a disc is inserted into the drive of a CD source.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct CdInsertConfig {
+    /// Watch the drives and read the TOC of inserted discs, so Refresh
+    /// doesn't need to be pressed.  Opening the tray clears the CD page.
+    #[serde(default = "default_true")]
+    pub watch: bool,
+    /// Switch to the CD page when a disc was inserted.
+    #[serde(default = "default_true")]
+    pub show_page: bool,
+    /// Start playing an inserted audio CD in the current zone.
+    #[serde(default)]
+    pub autoplay: bool,
+}
+
+impl Default for CdInsertConfig {
+    fn default() -> Self {
+        Self {
+            watch: true,
+            show_page: true,
+            autoplay: false,
+        }
+    }
+}
+
 impl Config {
     pub fn new() -> Result<Self, config::ConfigError> {
         let config = config::Config::builder()
@@ -122,3 +472,48 @@ impl Config {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_night_mode_over_midnight() {
+        let night = NightModeConfig::default();
+        assert!(night.is_active_at(time(21, 0)));
+        assert!(night.is_active_at(time(23, 59)));
+        assert!(night.is_active_at(time(3, 0)));
+        assert!(!night.is_active_at(time(7, 0)));
+        assert!(!night.is_active_at(time(12, 0)));
+    }
+
+    #[test]
+    fn test_night_mode_same_day_and_invalid() {
+        let mut night = NightModeConfig {
+            start: "13:00".to_string(),
+            end: "15:30".to_string(),
+            ..NightModeConfig::default()
+        };
+        assert!(night.is_active_at(time(14, 0)));
+        assert!(!night.is_active_at(time(15, 30)));
+        night.end = "late".to_string();
+        assert!(!night.is_active_at(time(14, 0)));
+    }
+
+    #[test]
+    fn test_silence_settings() {
+        let mut audio: AudioConfig = serde_json::from_str(r#"{"start_volume": 30}"#).unwrap();
+        let silence = audio.silence_settings();
+        assert!(!silence.trim_files);
+        assert_eq!(silence.dead_air, None);
+        audio.dead_air_secs = 30;
+        assert_eq!(
+            audio.silence_settings().dead_air,
+            Some(Duration::from_secs(30))
+        );
+    }
+}
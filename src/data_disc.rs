@@ -0,0 +1,207 @@
+//! Browsing the files of data CDs and DVDs ("MP3 CDs") and of the data
+//! session of enhanced CDs on the CD page.
+//!
+//! The folders are read from the disc without mounting it.  Audio files are
+//! named by their tags and played through the normal queue, read from the
+//! disc while they play.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Error;
+use lofty::config::ParseOptions;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::probe::Probe;
+use lofty::tag::Accessor;
+use rodio_player::iso9660::{self, DiscEntry, DiscFs};
+use rodio_player::{DiscFileItem, QueueItem};
+use tracing::warn;
+
+use crate::music_store::MusicStore;
+
+/// A folder of a data disc with its files.
+#[derive(Debug, Clone)]
+pub struct DiscFolder {
+    /// Folders from the root down to this one, the root first.
+    pub path: Vec<DiscEntry>,
+    pub entries: Vec<DiscFileEntry>,
+}
+
+/// A file or folder in a [`DiscFolder`].
+#[derive(Debug, Clone)]
+pub struct DiscFileEntry {
+    pub entry: DiscEntry,
+    /// Tags of audio files; `None` for folders and other files.
+    pub tags: Option<FileTags>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileTags {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub duration: Duration,
+}
+
+impl DiscFileEntry {
+    /// The title from the tags, or the file name without the extension.
+    pub fn title(&self) -> String {
+        self.tags
+            .as_ref()
+            .and_then(|tags| tags.title.clone())
+            .unwrap_or_else(|| match self.entry.name.rsplit_once('.') {
+                Some((stem, _)) if !stem.is_empty() => stem.to_string(),
+                _ => self.entry.name.clone(),
+            })
+    }
+}
+
+impl DiscFolder {
+    /// Path of the folder on the disc, e.g. `/Album/CD 1`.
+    pub fn display_path(&self) -> String {
+        let names: Vec<&str> = self.path.iter().skip(1).map(|e| e.name.as_str()).collect();
+        format!("/{}", names.join("/"))
+    }
+
+    /// The audio files of the folder as queue items.  Files without an
+    /// album tag get the name of the folder as album.
+    pub fn queue_items(&self, device: &str) -> Vec<QueueItem> {
+        let folder = self.display_path();
+        let folder_name = self.path.last().map(|e| e.name.clone()).unwrap_or_default();
+        self.entries
+            .iter()
+            .filter_map(|file| {
+                let tags = file.tags.as_ref()?;
+                Some(QueueItem::DiscFile(DiscFileItem {
+                    device: device.to_string(),
+                    path: format!("{}/{}", folder.trim_end_matches('/'), file.entry.name),
+                    file: file.entry.clone(),
+                    artist: tags.artist.clone().unwrap_or_default(),
+                    album: tags.album.clone().unwrap_or_else(|| folder_name.clone()),
+                    title: file.title(),
+                    cover: String::new(),
+                }))
+            })
+            .collect()
+    }
+}
+
+/// Read the folder at the end of `path` (the root if it is empty) from the
+/// data session of the disc in `device`, with the tags of its audio files.
+pub fn read_folder(
+    device: &str,
+    session_lba: u32,
+    mut path: Vec<DiscEntry>,
+) -> Result<DiscFolder, Error> {
+    let mut fs = DiscFs::open(device, session_lba)?;
+    if path.is_empty() {
+        path.push(fs.root());
+    }
+    let dir = path[path.len() - 1].clone();
+    let entries = fs
+        .read_dir(&dir)?
+        .into_iter()
+        .map(|entry| {
+            let tags = (!entry.is_dir
+                && MusicStore::is_supported_extension(Path::new(&entry.name)))
+            .then(|| read_tags(device, &entry));
+            DiscFileEntry { entry, tags }
+        })
+        .collect();
+    Ok(DiscFolder { path, entries })
+}
+
+/// Tags and duration of an audio file.  Files without tags are still audio
+/// files, so a file that can't be read gets empty tags.
+fn read_tags(device: &str, entry: &DiscEntry) -> FileTags {
+    let read = || -> Result<FileTags, Error> {
+        let reader = iso9660::open_file(device, entry)?;
+        let file = Probe::new(reader)
+            .options(ParseOptions::new().read_cover_art(false))
+            .guess_file_type()?
+            .read()?;
+        let tag = file.primary_tag().or_else(|| file.first_tag());
+        let text = |value: Option<std::borrow::Cow<'_, str>>| {
+            value
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        Ok(FileTags {
+            artist: tag.and_then(|t| text(t.artist())),
+            album: tag.and_then(|t| text(t.album())),
+            title: tag.and_then(|t| text(t.title())),
+            duration: file.properties().duration(),
+        })
+    };
+    read().unwrap_or_else(|e| {
+        warn!("Could not read the tags of {}: {e}", entry.name);
+        FileTags::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, is_dir: bool) -> DiscEntry {
+        DiscEntry {
+            name: name.to_string(),
+            is_dir,
+            lba: 100,
+            size: 2048,
+        }
+    }
+
+    #[test]
+    fn test_queue_items() {
+        let folder = DiscFolder {
+            path: vec![entry("", true), entry("Best Of", true)],
+            entries: vec![
+                DiscFileEntry {
+                    entry: entry("Live", true),
+                    tags: None,
+                },
+                DiscFileEntry {
+                    entry: entry("01 Intro.mp3", false),
+                    tags: Some(FileTags::default()),
+                },
+                DiscFileEntry {
+                    entry: entry("cover.jpg", false),
+                    tags: None,
+                },
+                DiscFileEntry {
+                    entry: entry("02 Song.flac", false),
+                    tags: Some(FileTags {
+                        artist: Some("Band".to_string()),
+                        album: Some("Greatest".to_string()),
+                        title: Some("Song".to_string()),
+                        duration: Duration::from_secs(180),
+                    }),
+                },
+            ],
+        };
+        assert_eq!(folder.display_path(), "/Best Of");
+
+        let items = folder.queue_items("/dev/sr0");
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title(), "01 Intro");
+        assert_eq!(items[0].album(), "Best Of");
+        assert_eq!(items[0].location(), "/Best Of/01 Intro.mp3");
+        assert_eq!(items[1].title(), "Song");
+        assert_eq!(items[1].artist(), "Band");
+        assert_eq!(items[1].album(), "Greatest");
+    }
+
+    #[test]
+    fn test_root_path() {
+        let root = DiscFolder {
+            path: vec![entry("", true)],
+            entries: vec![DiscFileEntry {
+                entry: entry("track.mp3", false),
+                tags: Some(FileTags::default()),
+            }],
+        };
+        assert_eq!(root.display_path(), "/");
+        assert_eq!(root.queue_items("/dev/sr0")[0].location(), "/track.mp3");
+    }
+}
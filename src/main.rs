@@ -1,32 +1,101 @@
 mod bluetooth;
+mod cd_ripper;
 mod config;
+mod data_disc;
 mod music_store;
 mod pages;
+mod playback_speed;
 mod swipe_view;
+mod toast;
+mod zone;
 
 use std::collections::HashMap;
 use std::path::Path;
-use std::sync::mpsc::{self, Receiver};
+use std::sync::mpsc;
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
 };
+use std::time::Duration;
 
 use eframe::{NativeOptions, egui};
 use egui::{ColorImage, Frame, Margin, TextureHandle, TextureOptions, Visuals};
-use rodio_player::{PlayerState, RodioPlayer, SoundItem, TitleChanged};
+use rodio_player::cd_metadata::{DiscMetadata, MetadataProvider, MusicBrainz};
+use rodio_player::cd_rip::RipFormat;
+use rodio_player::cd_watcher::{CdWatcher, DiscEvent};
+use rodio_player::iso9660::DiscEntry;
+use rodio_player::{
+    AbLoop, PlayerState, QueueItem, SoundItem, StationItem, StationMetadata, TitleChanged,
+};
 use rusqlite::Connection;
 use tracing::{debug, error, info, warn};
 
 use crate::bluetooth::BluetoothManager;
-use crate::config::{AudioConfig, Config, ConfigSourceType, UiConfig};
-use crate::music_store::{KidsAlbumItem, MusicItem, MusicStore, MusicTitleItem};
+use crate::cd_ripper::{RipEvent, RipJob, RipStatus};
+use crate::config::{
+    AudioBackend, AudioConfig, CdInsertConfig, CdMetadataConfig, CdReadConfig, Config,
+    ConfigSourceType, Source, Station, UiConfig,
+};
+use crate::data_disc::DiscFolder;
+use crate::music_store::{CdMetadata, KidsAlbumItem, MusicItem, MusicStore, MusicTitleItem};
 use crate::pages::{
-    CdSourceState, FileRenderData, KidsFileRenderData, SettingsState, paint_cd_source,
-    paint_file_source, paint_kids_file_source, paint_now_playing, paint_playlist, paint_settings,
-    paint_stream_source, source_type_icon,
+    CdEditorState, CdSourceState, FileRenderData, KidsFileRenderData, SettingsState,
+    paint_cd_source, paint_file_source, paint_kids_file_source, paint_now_playing, paint_playlist,
+    paint_settings, paint_stream_source, paint_zone_selector, source_type_icon,
 };
 use crate::swipe_view::SwipeView;
+use crate::toast::Toasts;
+use crate::zone::{Zone, zone_configs};
+
+/// How often the night mode schedules are checked.
+const NIGHT_MODE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Directory for the covers of audio CDs found online.
+const CD_COVER_DIR: &str = "cd_covers";
+
+/// Receives the result of an online disc lookup.
+type DiscLookupRx = mpsc::Receiver<Result<Option<DiscMetadata>, anyhow::Error>>;
+
+/// The File and KidsFile sources a CD can be ripped into: index and name.
+fn rip_targets(sources: &[Source]) -> Vec<(usize, String)> {
+    sources
+        .iter()
+        .enumerate()
+        .filter(|(_, source)| {
+            matches!(
+                source.source_type,
+                ConfigSourceType::File | ConfigSourceType::KidsFile
+            )
+        })
+        .map(|(idx, source)| (idx, source.name.clone()))
+        .collect()
+}
+
+/// Disc changes in the drives of the CD sources, by source index.
+type DiscEventRx = mpsc::Receiver<(usize, DiscEvent)>;
+
+/// Watch the drive of every CD source, if enabled.  Events wake up the UI.
+fn start_cd_watchers(config: &Config, ctx: &egui::Context) -> (Vec<CdWatcher>, DiscEventRx) {
+    let (tx, rx) = mpsc::channel();
+    if !config.cd_insert.watch {
+        return (Vec::new(), rx);
+    }
+    let watchers = config
+        .sources
+        .iter()
+        .enumerate()
+        .filter(|(_, source)| matches!(source.source_type, ConfigSourceType::CD))
+        .map(|(idx, source)| {
+            let tx = tx.clone();
+            let ctx = ctx.clone();
+            CdWatcher::start(&source.path, move |event| {
+                let _ = tx.send((idx, event));
+                ctx.request_repaint();
+            })
+        })
+        .collect();
+    (watchers, rx)
+}
 
 fn init_i18n(language: &str) {
     let en = String::from_utf8_lossy(include_bytes!("../assets/languages/en.egl"));
@@ -54,21 +123,28 @@ fn main() -> eframe::Result<()> {
                     start_volume: 50,
                     max_volume: 100,
                     device: None,
+                    devices: Vec::new(),
+                    backend: AudioBackend::default(),
+                    output_path: String::new(),
+                    hardware_mixer: None,
+                    duck_db: rodio_player::announcement::DEFAULT_DUCK_DB,
+                    night_mode: None,
+                    stream_loudness_lufs: None,
+                    trim_silence: false,
+                    silence_threshold_db: -60.0,
+                    dead_air_secs: 0,
                 },
+                zones: Vec::new(),
                 ui: UiConfig::default(),
+                cd_metadata: CdMetadataConfig::default(),
+                cd_read: CdReadConfig::default(),
+                cd_insert: CdInsertConfig::default(),
             }
         }
     };
 
     init_i18n(&config.ui.language);
 
-    let initial_volume = (config
-        .audio
-        .start_volume
-        .min(config.audio.max_volume)
-        .min(100) as f32)
-        / 100.0;
-
     // Initialize music store
     let music_store = match Connection::open("music_store.db3") {
         Ok(conn) => {
@@ -84,13 +160,8 @@ fn main() -> eframe::Result<()> {
         }
     };
 
-    // Create player channels
-    let (title_tx, title_rx) = mpsc::channel();
-    let (button_tx, button_rx) = mpsc::channel();
-
-    // Create player
-    let player = RodioPlayer::new(title_tx, button_tx, config.audio.device.as_deref());
-    player.set_volume(initial_volume);
+    // Tokio runtime for async stream playback
+    let tokio_rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
 
     // Build dynamic pages
     let mut pages: Vec<DynamicPage> = Vec::new();
@@ -143,22 +214,18 @@ fn main() -> eframe::Result<()> {
     let mut cd_source_states: HashMap<usize, CdSourceState> = HashMap::new();
     for (i, source) in config.sources.iter().enumerate() {
         if matches!(source.source_type, ConfigSourceType::CD) {
-            cd_source_states.insert(i, CdSourceState::new());
+            let mut state = CdSourceState::new();
+            state.rip_targets = rip_targets(&config.sources);
+            state.read.retries = config.cd_read.retries;
+            cd_source_states.insert(i, state);
         }
     }
 
-    // Tokio runtime for async stream playback
-    let tokio_rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
-
     let options = NativeOptions::default();
     eframe::run_native(
         "Homeplayer",
         options,
         Box::new(move |cc| {
-            // Bridge player channels through repaint-requesting threads.
-            // Whenever the player sends a title change or state update, these
-            // threads forward the message and wake up the UI so it processes
-            // the change immediately — even if no other interaction is happening.
             let ctx = cc.egui_ctx.clone();
 
             let mut dark_visuals = Visuals::dark();
@@ -166,30 +233,17 @@ fn main() -> eframe::Result<()> {
             ctx.set_visuals_of(egui::Theme::Dark, dark_visuals);
 
             replace_fonts(&ctx);
-            let (bridged_title_tx, bridged_title_rx) = mpsc::channel();
-            let ctx_for_titles = ctx.clone();
-            std::thread::Builder::new()
-                .name("title-bridge".into())
-                .spawn(move || {
-                    while let Ok(msg) = title_rx.recv() {
-                        let _ = bridged_title_tx.send(msg);
-                        ctx_for_titles.request_repaint();
-                    }
-                })
-                .expect("Failed to spawn title bridge thread");
-
-            let (bridged_button_tx, bridged_button_rx) = mpsc::channel();
-            std::thread::Builder::new()
-                .name("button-state-bridge".into())
-                .spawn(move || {
-                    while let Ok(msg) = button_rx.recv() {
-                        let _ = bridged_button_tx.send(msg);
-                        ctx.request_repaint();
-                    }
-                })
-                .expect("Failed to spawn button-state bridge thread");
 
-            let settings_state = SettingsState::new(&config);
+            // One player per zone, the main zone first.
+            let zones: Vec<Zone> = zone_configs(&config)
+                .into_iter()
+                .map(|(name, audio)| Zone::new(name, audio, tokio_rt.handle().clone(), &ctx))
+                .collect();
+
+            let (cd_watchers, cd_disc_rx) = start_cd_watchers(&config, &ctx);
+
+            let mut settings_state = SettingsState::new(&config);
+            settings_state.output_status = zones[0].output_status.clone();
 
             // Try to initialise the Bluetooth manager.  Failure here is
             // non-fatal (e.g. no adapter, BlueZ not running) – the settings
@@ -202,28 +256,24 @@ fn main() -> eframe::Result<()> {
             Ok(Box::new(Homeplayer {
                 swipe_view: SwipeView::new(num_pages),
                 config,
-                player,
+                zones,
+                selected_zone: 0,
                 music_store,
-                title_rx: bridged_title_rx,
-                button_state_rx: bridged_button_rx,
-                is_playing: false,
-                is_paused: false,
-                is_muted: false,
-                current_title: TitleChanged {
-                    artist: String::new(),
-                    album: String::new(),
-                    title: String::new(),
-                    cover: String::new(),
-                },
-                volume: initial_volume,
+                toasts: Toasts::default(),
                 pages,
-                playlist_queue: Vec::new(),
-                playlist_index: 0,
                 file_source_states,
                 kids_file_source_states,
                 cd_source_states,
-                cd_toc_rx: None,
+                cd_toc_rx: HashMap::new(),
+                cd_files_rx: HashMap::new(),
+                cd_watchers,
+                cd_disc_rx,
+                cd_autoplay: None,
+                cd_editor: None,
+                cd_metadata_rx: None,
+                cd_rip: None,
                 tokio_rt,
+                ctx,
                 scanning: Arc::new(AtomicBool::new(false)),
                 scan_completed_source: None,
                 backgrounds: BackgroundImages::new(),
@@ -323,6 +373,39 @@ enum DynamicPage {
     Settings,
 }
 
+/// Convert a configured station into a play queue item.
+fn station_item(station: &Station) -> StationItem {
+    let metadata = station
+        .metadata
+        .clone()
+        .filter(|m| !m.url.trim().is_empty())
+        .map(|m| StationMetadata {
+            url: m.url,
+            artist_path: m.artist_path,
+            title_path: m.title_path,
+            cover_path: m.cover_path,
+            poll_interval: Duration::from_secs(m.interval_secs.max(1)),
+        });
+    StationItem {
+        name: station.name.clone(),
+        url: station.url.clone(),
+        icon: station.icon.clone(),
+        metadata,
+        gain_db: station.gain_db,
+    }
+}
+
+/// Convert a title of the music store into a play queue item.
+fn queue_item(title: &MusicTitleItem) -> QueueItem {
+    QueueItem::from(SoundItem {
+        artist: title.artist.clone(),
+        album: title.album.clone(),
+        title: title.name.clone(),
+        path: title.path.clone(),
+        cover: title.cover.clone(),
+    })
+}
+
 fn page_label(page: &DynamicPage, config: &Config) -> String {
     match page {
         DynamicPage::Source(idx) => {
@@ -414,8 +497,10 @@ pub(crate) enum UiAction {
         start_index: usize,
     },
     PlayStream {
-        url: String,
-        icon: String,
+        station: Station,
+    },
+    AddStationToPlaylist {
+        station: Station,
     },
     BrowseAlbums {
         source_idx: usize,
@@ -449,9 +534,45 @@ pub(crate) enum UiAction {
         source_idx: usize,
         start_track: usize,
     },
+    /// Play the audio hidden before track 1, then the rest of the disc.
+    PlayCdHiddenTrack {
+        source_idx: usize,
+    },
+    /// Show a folder of a data disc; `path` leads from the root to it.
+    OpenDiscFolder {
+        source_idx: usize,
+        path: Vec<DiscEntry>,
+    },
+    /// Play the audio files of the folder shown, from the `start`th one.
+    PlayDiscFiles {
+        source_idx: usize,
+        start: usize,
+    },
     EjectCd {
         source_idx: usize,
     },
+    /// Type in the names of the disc and pick a cover.
+    EditCdMetadata {
+        source_idx: usize,
+    },
+    SaveCdMetadata {
+        source_idx: usize,
+        disc_id: String,
+        metadata: CdMetadata,
+    },
+    CloseCdEditor,
+    /// Rip the audio tracks of the disc into the folder of a library source.
+    RipCd {
+        source_idx: usize,
+        target_idx: usize,
+        format: RipFormat,
+        verify: bool,
+    },
+    CancelRip,
+    /// Hide the outcome of the last rip.
+    DismissRip {
+        source_idx: usize,
+    },
     PlayKidsAlbum {
         source_idx: usize,
         album_id: i32,
@@ -482,6 +603,11 @@ pub(crate) enum UiAction {
         index: usize,
     },
     PlaylistClear,
+    GoToArtist {
+        source_id: i32,
+        artist_id: i32,
+        artist_name: String,
+    },
     PlaylistPlayFrom {
         index: usize,
     },
@@ -489,6 +615,15 @@ pub(crate) enum UiAction {
         config: Config,
     },
     ResetSettings,
+    /// Direct the player controls and player actions to another zone.
+    SelectZone(usize),
+    /// Play the doorbell chime over the music of the main zone.
+    TestAnnouncement,
+    /// Change the playback speed and remember it for the album or source.
+    SetSpeed(f32),
+    /// Set A, then B of the A-B repeat, or end it.
+    AbLoopMark,
+    AbLoopClear,
 }
 
 // ---------------------------------------------------------------------------
@@ -498,33 +633,43 @@ pub(crate) enum UiAction {
 struct Homeplayer {
     swipe_view: SwipeView,
     config: Config,
-    player: RodioPlayer,
+    /// Playback zones, the main zone first.  There is always at least one.
+    zones: Vec<Zone>,
+    /// Zone the player controls and player actions apply to.
+    selected_zone: usize,
     music_store: Option<Arc<MusicStore>>,
-    title_rx: Receiver<TitleChanged>,
-    button_state_rx: Receiver<PlayerState>,
-    is_playing: bool,
-    is_paused: bool,
-    is_muted: bool,
-    current_title: TitleChanged,
-    volume: f32,
+    /// Non-blocking messages, e.g. about queue items that could not be played.
+    toasts: Toasts,
     pages: Vec<DynamicPage>,
-    /// Snapshot of the player queue, updated every frame.
-    playlist_queue: Vec<rodio_player::SoundItem>,
-    /// Index of the next item the playback thread will pick up.
-    playlist_index: usize,
     file_source_states: HashMap<usize, FileSourceState>,
     kids_file_source_states: HashMap<usize, KidsFileSourceState>,
     cd_source_states: HashMap<usize, CdSourceState>,
-    cd_toc_rx: Option<(
-        usize,
-        mpsc::Receiver<Result<rodio_player::cd_audio::CdInfo, anyhow::Error>>,
-    )>,
+    /// TOC reads in progress, by CD source index.
+    cd_toc_rx:
+        HashMap<usize, mpsc::Receiver<Result<rodio_player::cd_audio::CdInfo, anyhow::Error>>>,
+    /// Folders of data discs being read, by CD source index.
+    cd_files_rx: HashMap<usize, mpsc::Receiver<Result<DiscFolder, anyhow::Error>>>,
+    /// Stop watching the drives when dropped.
+    cd_watchers: Vec<CdWatcher>,
+    cd_disc_rx: DiscEventRx,
+    /// CD source whose disc starts playing once its TOC is read.
+    cd_autoplay: Option<usize>,
+    /// Names of a disc being typed in on the CD page.
+    cd_editor: Option<CdEditorState>,
+    /// Online lookup of a disc: source index, disc ID and result channel.
+    cd_metadata_rx: Option<(usize, String, DiscLookupRx)>,
+    /// Rip of a disc: CD source index, cancel flag and progress channel.
+    cd_rip: Option<(usize, Arc<AtomicBool>, mpsc::Receiver<RipEvent>)>,
+    /// Runtime that stream connections and Bluetooth tasks run on.
     tokio_rt: tokio::runtime::Runtime,
+    /// Handed to the players of new zones, so they can wake up the UI.
+    ctx: egui::Context,
     scanning: Arc<AtomicBool>,
     scan_completed_source: Option<usize>,
     backgrounds: BackgroundImages,
     cover_texture: Option<TextureHandle>,
     cover_texture_path: String,
+    /// Album covers by path, for the KidsFile pages and the CD editor.
     kids_cover_textures: HashMap<String, TextureHandle>,
     station_textures: HashMap<String, TextureHandle>,
     settings_state: SettingsState,
@@ -551,100 +696,359 @@ impl Homeplayer {
         // events.  Processing button states first means `Stopped` clears the
         // old title, and the subsequent title-channel drain then sets the new
         // track title correctly.
-        while let Ok(state) = self.button_state_rx.try_recv() {
+        for zone_idx in 0..self.zones.len() {
+            self.drain_zone_channels(zone_idx);
+        }
+
+        // Poll for discs inserted into or removed from the drives
+        while let Ok((source_idx, event)) = self.cd_disc_rx.try_recv() {
+            self.disc_event(source_idx, event);
+        }
+
+        // Poll for CD TOC read completion
+        let finished: Vec<_> = self
+            .cd_toc_rx
+            .iter()
+            .filter_map(|(&idx, rx)| rx.try_recv().ok().map(|result| (idx, result)))
+            .collect();
+        for (idx, result) in finished {
+            self.cd_toc_rx.remove(&idx);
+            let autoplay = self.cd_autoplay.take_if(|source| *source == idx).is_some();
+            if let Some(state) = self.cd_source_states.get_mut(&idx) {
+                state.loading = false;
+                match result {
+                    Ok(cd_info) => {
+                        let audio_count = cd_info.audio_tracks().len();
+                        info!(
+                            "CD TOC loaded: {} tracks ({} audio)",
+                            cd_info.tracks.len(),
+                            audio_count
+                        );
+                        state.disc_present = true;
+                        state.status = format!("{audio_count} audio tracks found.");
+                        state.disc = cd_info;
+                        let has_files = state.disc.data_session_lba().is_some();
+                        self.name_disc(idx);
+                        if has_files {
+                            self.open_disc_folder(idx, Vec::new());
+                        }
+                        if autoplay && audio_count > 0 {
+                            self.play_cd(idx, 0);
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to read CD TOC: {e}");
+                        state.disc_present = false;
+                        state.disc = rodio_player::cd_audio::CdInfo::default();
+                        state.status = format!("Failed to read disc: {e}");
+                    }
+                }
+            }
+        }
+
+        // Poll for folders of data discs
+        let finished: Vec<_> = self
+            .cd_files_rx
+            .iter()
+            .filter_map(|(&idx, rx)| rx.try_recv().ok().map(|result| (idx, result)))
+            .collect();
+        for (idx, result) in finished {
+            self.cd_files_rx.remove(&idx);
+            if let Some(state) = self.cd_source_states.get_mut(&idx) {
+                match result {
+                    Ok(folder) => {
+                        info!(
+                            "Read {} entries of {} on the disc",
+                            folder.entries.len(),
+                            folder.display_path()
+                        );
+                        state.files = Some(folder);
+                        state.files_status.clear();
+                    }
+                    Err(e) => {
+                        error!("Failed to read the files of the disc: {e}");
+                        state.files_status = format!("Could not read files: {e}");
+                    }
+                }
+            }
+        }
+
+        // Poll for the online lookup of the disc
+        if let Some((source_idx, ref disc_id, ref rx)) = self.cd_metadata_rx
+            && let Ok(result) = rx.try_recv()
+        {
+            let disc_id = disc_id.clone();
+            self.cd_metadata_rx = None;
+            match result {
+                Ok(Some(found)) => self.store_disc_metadata(source_idx, &disc_id, found),
+                Ok(None) => info!("Disc {disc_id} is not known to MusicBrainz"),
+                Err(e) => warn!("Could not look up disc {disc_id}: {e}"),
+            }
+        }
+
+        // Poll for the progress of a rip
+        if let Some((source_idx, _, ref rx)) = self.cd_rip {
+            let mut finished_dir = None;
+            while let Ok(event) = rx.try_recv() {
+                if let Some(rip) = self
+                    .cd_source_states
+                    .get_mut(&source_idx)
+                    .and_then(|state| state.rip.as_mut())
+                {
+                    rip.apply(&event);
+                }
+                match event {
+                    RipEvent::Finished { album_dir, .. } => finished_dir = Some(Some(album_dir)),
+                    RipEvent::Failed(_) => finished_dir = Some(None),
+                    _ => {}
+                }
+            }
+            if let Some(album_dir) = finished_dir {
+                self.cd_rip = None;
+                let target_idx = self
+                    .cd_source_states
+                    .get(&source_idx)
+                    .and_then(|state| state.rip.as_ref())
+                    .map(|rip| rip.target_idx);
+                if let (Some(album_dir), Some(target_idx)) = (album_dir, target_idx) {
+                    self.scan_folder(target_idx, album_dir);
+                }
+            }
+        }
+    }
+
+    fn drain_zone_channels(&mut self, zone_idx: usize) {
+        let zone = &mut self.zones[zone_idx];
+        let mut dead_station = None;
+        while let Ok(state) = zone.button_state_rx.try_recv() {
             match state {
                 PlayerState::Playing => {
-                    self.is_playing = true;
-                    self.is_paused = false;
+                    zone.is_playing = true;
+                    zone.is_paused = false;
                 }
                 PlayerState::Paused => {
-                    self.is_paused = true;
+                    zone.is_paused = true;
                 }
                 PlayerState::Stopped => {
-                    self.is_playing = false;
-                    self.is_paused = false;
-                    self.current_title = TitleChanged {
-                        artist: String::new(),
-                        album: String::new(),
-                        title: String::new(),
-                        cover: String::new(),
-                    };
-                    self.cover_texture = None;
-                    self.cover_texture_path = String::new();
+                    zone.is_playing = false;
+                    zone.is_paused = false;
+                    zone.current_title = TitleChanged::default();
+                    zone.library_match = None;
+                    zone.library_match_rx = None;
                 }
                 PlayerState::StartPlaying => {
-                    self.is_playing = true;
-                    self.is_paused = false;
+                    zone.is_playing = true;
+                    zone.is_paused = false;
                 }
                 PlayerState::Muted => {
-                    self.is_muted = true;
+                    zone.is_muted = true;
                 }
                 PlayerState::Unmuted => {
-                    self.is_muted = false;
+                    zone.is_muted = false;
                 }
                 PlayerState::Seekable | PlayerState::Unseekable => {}
+                PlayerState::OutputChanged(status) => {
+                    // The settings page edits the main zone.
+                    if zone_idx == 0 {
+                        self.settings_state.output_status = status.clone();
+                    }
+                    zone.output_status = status;
+                }
+                PlayerState::VolumeChanged(volume) => {
+                    // Changed on the hardware mixer by another program; the
+                    // configured maximum still applies.
+                    let max_vol = zone.max_volume();
+                    if volume > max_vol {
+                        zone.player.set_volume(max_vol);
+                    }
+                    zone.volume = volume.min(max_vol);
+                    if zone.volume > 0.0 {
+                        zone.is_muted = false;
+                    }
+                }
+                PlayerState::Failed(failure) => {
+                    self.toasts.push(egui_i18n::tr!("playback_failed_toast", {
+                        name: failure.name,
+                        reason: failure.reason
+                    }));
+                }
+                PlayerState::DeadAir(station) => {
+                    dead_station = Some(station);
+                }
             }
         }
+        if let Some(station) = dead_station {
+            self.fall_back_from_station(zone_idx, &station);
+        }
+        let zone = &mut self.zones[zone_idx];
 
         // Drain title changes (after button states, so a fresh TitleChanged is
         // never clobbered by a Stopped that arrived in the same drain cycle)
-        while let Ok(title) = self.title_rx.try_recv() {
+        let mut title_changed = false;
+        while let Ok(title) = zone.title_rx.try_recv() {
             debug!("Title changed: {} - {}", title.artist, title.title);
-            self.current_title = title;
+            zone.current_title = title;
+            title_changed = true;
         }
 
-        // Poll for CD TOC read completion
-        if let Some((source_idx, ref rx)) = self.cd_toc_rx {
-            if let Ok(result) = rx.try_recv() {
-                let idx = source_idx;
-                self.cd_toc_rx = None;
-                if let Some(state) = self.cd_source_states.get_mut(&idx) {
-                    state.loading = false;
-                    match result {
-                        Ok(cd_info) => {
-                            let audio_count = cd_info.audio_tracks().len();
-                            info!(
-                                "CD TOC loaded: {} tracks ({} audio)",
-                                cd_info.tracks.len(),
-                                audio_count
-                            );
-                            state.disc_present = true;
-                            state.status = format!("{audio_count} audio tracks found.");
-                            state.tracks = cd_info.tracks;
-                        }
-                        Err(e) => {
-                            error!("Failed to read CD TOC: {e}");
-                            state.disc_present = false;
-                            state.tracks.clear();
-                            state.status = format!("Failed to read disc: {e}");
-                        }
-                    }
-                }
-            }
+        // Refresh the playlist snapshot so the playlist page shows live data.
+        let (queue, idx) = zone.player.get_queue();
+        zone.playlist_queue = queue;
+        zone.playlist_index = idx;
+        zone.ab_loop = zone.player.ab_loop();
+        if let Some(Ok(library_match)) = zone.library_match_rx.as_ref().map(|rx| rx.try_recv()) {
+            zone.library_match = library_match;
+            zone.library_match_rx = None;
         }
 
-        // Refresh the playlist snapshot so the playlist page shows live data.
-        let (queue, idx) = self.player.get_queue();
-        self.playlist_queue = queue;
-        self.playlist_index = idx;
+        if title_changed {
+            self.update_library_match(zone_idx);
+            self.apply_remembered_speed(zone_idx);
+        }
+    }
+
+    /// Keys under which the speed of the title playing in the zone is
+    /// remembered; see [`playback_speed::speed_keys`].
+    fn speed_keys(&self, zone_idx: usize) -> Vec<String> {
+        let zone = &self.zones[zone_idx];
+        zone.playlist_index
+            .checked_sub(1)
+            .and_then(|current| zone.playlist_queue.get(current))
+            .map(|entry| playback_speed::speed_keys(&self.config, &entry.item, &zone.current_title))
+            .unwrap_or_default()
+    }
+
+    /// Play the new title at the speed remembered for its album or source,
+    /// or at normal speed.
+    fn apply_remembered_speed(&mut self, zone_idx: usize) {
+        if self.zones[zone_idx].current_title.is_stream {
+            return;
+        }
+        let keys = self.speed_keys(zone_idx);
+        let speed = match &self.music_store {
+            Some(store) => store.get_playback_speed(&keys).unwrap_or_else(|e| {
+                error!("Failed to load playback speed: {e}");
+                None
+            }),
+            None => None,
+        };
+        let zone = &mut self.zones[zone_idx];
+        let speed = speed.unwrap_or(1.0);
+        if zone.speed != speed {
+            zone.speed = speed;
+            zone.player.set_speed(speed);
+        }
+    }
+
+    /// The zone the player controls apply to.
+    fn zone(&self) -> &Zone {
+        &self.zones[self.selected_zone]
+    }
+
+    fn zone_mut(&mut self) -> &mut Zone {
+        &mut self.zones[self.selected_zone]
+    }
+
+    /// Look up the title announced by a stream in the local library, so the
+    /// now-playing page can show the local cover and offer to play the song
+    /// from the library.  The library is searched in the background.
+    fn update_library_match(&mut self, zone_idx: usize) {
+        let zone = &mut self.zones[zone_idx];
+        zone.library_match = None;
+        zone.library_match_rx = None;
+        if !zone.current_title.is_stream {
+            return;
+        }
+        let Some(store) = self.music_store.clone() else {
+            return;
+        };
+        let artist = zone.current_title.artist.clone();
+        let title = zone.current_title.title.clone();
+        let (match_tx, match_rx) = mpsc::channel();
+        std::thread::spawn(move || match store.find_title_match(&artist, &title) {
+            Ok(library_match) => {
+                let _ = match_tx.send(library_match);
+            }
+            Err(e) => error!("Failed to match title against the library: {e}"),
+        });
+        zone.library_match_rx = Some(match_rx);
+    }
+
+    /// Show the albums of an artist on the page of the file source the
+    /// artist belongs to.
+    fn go_to_artist(&mut self, source_id: i32, artist_id: i32, artist_name: String) {
+        let file_source_idx = self
+            .file_source_states
+            .iter()
+            .find(|(_, state)| state.source_id == Some(source_id))
+            .map(|(idx, _)| *idx);
+        let source_idx = file_source_idx.or_else(|| {
+            self.kids_file_source_states
+                .iter()
+                .find(|(_, state)| state.source_id == Some(source_id))
+                .map(|(idx, _)| *idx)
+        });
+        let Some(source_idx) = source_idx else {
+            warn!("No source page found for source {source_id}");
+            return;
+        };
+        if file_source_idx.is_some() {
+            self.browse_albums(source_idx, artist_id, artist_name);
+        }
+        if let Some(page_idx) = self
+            .pages
+            .iter()
+            .position(|p| matches!(p, DynamicPage::Source(idx) if *idx == source_idx))
+        {
+            self.swipe_view.set_page(page_idx);
+        }
     }
 
     /// Rebuild pages, source states, and player configuration from the
     /// current `self.config`.  Called after saving a new configuration so
     /// that changes take effect immediately without a restart.
     fn apply_config(&mut self) {
-        // ── 1. Audio device ────────────────────────────────────────────
-        // Switch the audio output device if it changed.  The player
-        // preserves its volume across the switch.
-        self.player
-            .switch_device(self.config.audio.device.as_deref());
-
-        // ── 1a. Clamp live volume to the (possibly reduced) max_volume ──
-        let max_vol = self.config.audio.max_volume as f32 / 100.0;
-        if self.volume > max_vol {
-            self.volume = max_vol;
-            self.player.set_volume(max_vol);
+        // ── 1. Zones ───────────────────────────────────────────────────
+        // Switch the audio output of every zone if it changed.  Playback
+        // continues on the new output, and the live volume is clamped to a
+        // (possibly reduced) max_volume.  Zones are matched by name, so the
+        // others keep playing when one is added, removed or renamed.  Zones
+        // that were added get a new player, removed ones are closed.
+        let selected = self.zone().name.clone();
+        let mut old_zones: Vec<Zone> = self.zones.drain(..).collect();
+        let mut zones = Vec::new();
+        for (name, audio) in zone_configs(&self.config) {
+            let Some(i) = old_zones.iter().position(|zone| zone.name == name) else {
+                zones.push(Zone::new(
+                    name,
+                    audio,
+                    self.tokio_rt.handle().clone(),
+                    &self.ctx,
+                ));
+                continue;
+            };
+            let mut zone = old_zones.remove(i);
+            if zone.audio != audio
+                && let Err(e) = zone.apply_audio_config(audio)
+            {
+                error!("Could not open hardware mixer, using software volume: {e}");
+                self.toasts
+                    .push(egui_i18n::tr!("hardware_mixer_failed_toast", {
+                        reason: e
+                    }));
+            }
+            zones.push(zone);
+        }
+        for zone in old_zones {
+            info!("Close zone {}", zone.label());
+            zone.player.close();
         }
+        self.zones = zones;
+        self.selected_zone = self
+            .zones
+            .iter()
+            .position(|zone| zone.name == selected)
+            .unwrap_or(self.selected_zone.min(self.zones.len() - 1));
 
         // ── 1b. Language ───────────────────────────────────────────────
         egui_i18n::set_language(&self.config.ui.language);
@@ -699,13 +1103,27 @@ impl Homeplayer {
         self.kids_file_source_states = kids_file_source_states;
 
         // ── 5. Rebuild CD source states ────────────────────────────────
+        // A running rip belongs to a CD source that may be gone now.
+        if let Some((_, cancel, _)) = self.cd_rip.take() {
+            cancel.store(true, Ordering::SeqCst);
+        }
         let mut cd_source_states: HashMap<usize, CdSourceState> = HashMap::new();
         for (i, source) in self.config.sources.iter().enumerate() {
             if matches!(source.source_type, ConfigSourceType::CD) {
-                cd_source_states.insert(i, CdSourceState::new());
+                let mut state = CdSourceState::new();
+                state.rip_targets = rip_targets(&self.config.sources);
+                state.read.retries = self.config.cd_read.retries;
+                cd_source_states.insert(i, state);
             }
         }
         self.cd_source_states = cd_source_states;
+        self.cd_toc_rx.clear();
+        self.cd_files_rx.clear();
+        self.cd_autoplay = None;
+        // New watchers report the discs that are in the drives, which
+        // reads them again.
+        self.cd_watchers.clear();
+        (self.cd_watchers, self.cd_disc_rx) = start_cd_watchers(&self.config, &self.ctx);
 
         // ── 6. Clear cached textures so they are re-loaded ─────────────
         // Station icon textures may have changed (different sources or
@@ -731,8 +1149,13 @@ impl Homeplayer {
             } => {
                 self.play_titles(titles, start_index);
             }
-            UiAction::PlayStream { url, icon } => {
-                self.play_stream(url, icon);
+            UiAction::PlayStream { station } => {
+                self.play_stream(&station);
+            }
+            UiAction::AddStationToPlaylist { station } => {
+                self.zone()
+                    .player
+                    .append(vec![QueueItem::Station(station_item(&station))]);
             }
             UiAction::BrowseAlbums {
                 source_idx,
@@ -768,23 +1191,23 @@ impl Homeplayer {
                 self.play();
             }
             UiAction::PlayerPause => {
-                self.player.pause();
+                self.zone().player.pause();
             }
             UiAction::PlayerStop => {
-                self.player.stop();
+                self.zone().player.stop();
             }
             UiAction::PlayerNext => {
-                self.player.skip_next();
+                self.zone().player.skip_next();
             }
             UiAction::PlayerPrevious => {
-                self.player.skip_previous();
+                self.zone().player.skip_previous();
             }
             UiAction::PlayerVolume(vol) => {
-                self.player.set_volume(vol);
-                self.is_muted = false;
+                self.zone().player.set_volume(vol);
+                self.zone_mut().is_muted = false;
             }
             UiAction::PlayerMute => {
-                self.player.mute();
+                self.zone().player.mute();
             }
             UiAction::LoadCdToc { source_idx } => {
                 self.load_cd_toc(source_idx);
@@ -795,9 +1218,49 @@ impl Homeplayer {
             } => {
                 self.play_cd(source_idx, start_track);
             }
+            UiAction::PlayCdHiddenTrack { source_idx } => {
+                self.play_cd_hidden_track(source_idx);
+            }
+            UiAction::OpenDiscFolder { source_idx, path } => {
+                self.open_disc_folder(source_idx, path);
+            }
+            UiAction::PlayDiscFiles { source_idx, start } => {
+                self.play_disc_files(source_idx, start);
+            }
             UiAction::EjectCd { source_idx } => {
                 self.eject_cd(source_idx);
             }
+            UiAction::EditCdMetadata { source_idx } => {
+                self.edit_cd_metadata(source_idx);
+            }
+            UiAction::SaveCdMetadata {
+                source_idx,
+                disc_id,
+                metadata,
+            } => {
+                self.save_cd_metadata(source_idx, &disc_id, metadata);
+            }
+            UiAction::CloseCdEditor => {
+                self.cd_editor = None;
+            }
+            UiAction::RipCd {
+                source_idx,
+                target_idx,
+                format,
+                verify,
+            } => {
+                self.rip_cd(source_idx, target_idx, format, verify);
+            }
+            UiAction::CancelRip => {
+                if let Some((_, ref cancel, _)) = self.cd_rip {
+                    cancel.store(true, Ordering::SeqCst);
+                }
+            }
+            UiAction::DismissRip { source_idx } => {
+                if let Some(state) = self.cd_source_states.get_mut(&source_idx) {
+                    state.rip.take_if(|rip| rip.finished);
+                }
+            }
             UiAction::PlayKidsAlbum {
                 source_idx,
                 album_id,
@@ -846,25 +1309,62 @@ impl Homeplayer {
             UiAction::ResetSettings => {
                 self.settings_state.reset(&self.config);
             }
+            UiAction::TestAnnouncement => {
+                self.zone()
+                    .player
+                    .announce(rodio_player::announcement::chime());
+            }
+            UiAction::SetSpeed(speed) => {
+                let zone = self.zone_mut();
+                zone.speed = speed;
+                zone.player.set_speed(speed);
+                let keys = self.speed_keys(self.selected_zone);
+                if let (Some(store), Some(key)) = (&self.music_store, keys.first())
+                    && let Err(e) = store.set_playback_speed(key, speed)
+                {
+                    error!("Failed to save playback speed: {e}");
+                }
+            }
+            UiAction::AbLoopMark => {
+                let zone = self.zone_mut();
+                zone.ab_loop = zone.player.ab_loop_mark();
+            }
+            UiAction::AbLoopClear => {
+                let zone = self.zone_mut();
+                zone.player.ab_loop_clear();
+                zone.ab_loop = AbLoop::Off;
+            }
+            UiAction::SelectZone(zone_idx) => {
+                if zone_idx < self.zones.len() {
+                    self.selected_zone = zone_idx;
+                }
+            }
             UiAction::PlaylistRemove { index } => {
-                self.player.remove_from_queue(index);
+                self.zone().player.remove_from_queue(index);
             }
             UiAction::PlaylistClear => {
-                self.player.clear();
+                self.zone().player.clear();
+            }
+            UiAction::GoToArtist {
+                source_id,
+                artist_id,
+                artist_name,
+            } => {
+                self.go_to_artist(source_id, artist_id, artist_name);
             }
             UiAction::PlaylistPlayFrom { index } => {
-                if self.is_playing || self.is_paused {
+                if self.zone().is_playing || self.zone().is_paused {
                     // Playback thread is already running – just reposition it.
-                    if let Err(e) = self.player.play_from(index) {
+                    if let Err(e) = self.zone().player.play_from(index) {
                         error!("Failed to jump to playlist index {index}: {e}");
                     }
                 } else {
                     // Nothing playing yet: set the start index then kick off
                     // the playback thread.
-                    if let Err(e) = self.player.play_from(index) {
+                    if let Err(e) = self.zone().player.play_from(index) {
                         error!("Failed to set playlist start index {index}: {e}");
                     }
-                    if let Err(e) = self.player.play() {
+                    if let Err(e) = self.zone().player.play() {
                         error!("Failed to start playlist playback: {e}");
                     } else {
                         self.navigate_to_now_playing();
@@ -875,15 +1375,16 @@ impl Homeplayer {
     }
 
     fn eject_cd(&mut self, source_idx: usize) {
-        let source = &self.config.sources[source_idx];
-        let device = source.path.clone();
-        self.player.stop();
+        let device = self.config.sources[source_idx].path.clone();
+        self.stop_zones_playing(&device);
         match rodio_player::cd_audio::eject_cd(&device) {
             Ok(_) => {
                 info!("CD ejected");
                 if let Some(state) = self.cd_source_states.get_mut(&source_idx) {
-                    state.tracks.clear();
+                    state.disc = rodio_player::cd_audio::CdInfo::default();
                     state.disc_present = false;
+                    state.files = None;
+                    state.files_status.clear();
                     state.status = "Disc ejected. Insert a CD and press Refresh.".to_string();
                 }
             }
@@ -896,26 +1397,179 @@ impl Homeplayer {
         }
     }
 
+    /// Read a disc found in or inserted into the drive of a CD source, or
+    /// forget the disc when the tray was opened.
+    fn disc_event(&mut self, source_idx: usize, event: DiscEvent) {
+        if !self.cd_source_states.contains_key(&source_idx) {
+            return;
+        }
+        match event {
+            DiscEvent::Found => self.load_cd_toc(source_idx),
+            DiscEvent::Inserted => {
+                self.load_cd_toc(source_idx);
+                if self.config.cd_insert.autoplay {
+                    self.cd_autoplay = Some(source_idx);
+                }
+                if self.config.cd_insert.show_page
+                    && let Some(idx) = self
+                        .pages
+                        .iter()
+                        .position(|p| matches!(p, DynamicPage::Source(i) if *i == source_idx))
+                {
+                    self.swipe_view.set_page(idx);
+                }
+            }
+            DiscEvent::Removed => self.disc_removed(source_idx),
+        }
+    }
+
+    /// Stop the zones playing from the disc in `device`.
+    fn stop_zones_playing(&self, device: &str) {
+        for zone in &self.zones {
+            let (queue, index) = zone.player.get_queue();
+            if let Some(entry) = queue.get(index.saturating_sub(1))
+                && match &entry.item {
+                    QueueItem::CdTrack(track) => track.device == device,
+                    QueueItem::DiscFile(file) => file.device == device,
+                    _ => false,
+                }
+            {
+                info!("Disc in {device} going away, stopping {}", zone.label());
+                zone.player.stop();
+            }
+        }
+    }
+
+    /// The disc of a CD source is gone: stop playing and ripping it and
+    /// clear the CD page.
+    fn disc_removed(&mut self, source_idx: usize) {
+        let device = self.config.sources[source_idx].path.clone();
+        self.stop_zones_playing(&device);
+        if let Some((rip_source, ref cancel, _)) = self.cd_rip
+            && rip_source == source_idx
+        {
+            cancel.store(true, Ordering::SeqCst);
+        }
+        self.cd_editor
+            .take_if(|editor| editor.source_idx == source_idx);
+        self.cd_autoplay.take_if(|source| *source == source_idx);
+        self.cd_toc_rx.remove(&source_idx);
+        self.cd_files_rx.remove(&source_idx);
+        if let Some(state) = self.cd_source_states.get_mut(&source_idx) {
+            state.loading = false;
+            state.disc = rodio_player::cd_audio::CdInfo::default();
+            state.disc_present = false;
+            state.files = None;
+            state.files_status.clear();
+            state.status = "No disc. Insert a CD.".to_string();
+        }
+    }
+
     fn play_cd(&mut self, source_idx: usize, start_track: usize) {
-        let source = &self.config.sources[source_idx];
-        let device = source.path.clone();
         if let Some(state) = self.cd_source_states.get(&source_idx) {
-            let tracks = state.tracks.clone();
-            if let Err(e) = self.player.play_cd(&device, tracks, start_track) {
-                error!("Failed to start CD playback: {e}");
-            } else {
-                self.navigate_to_now_playing();
-            }
+            let disc = state.disc.clone();
+            self.start_cd_playback(source_idx, &disc, start_track);
+        }
+    }
+
+    /// Play the hidden track as track 0 of the disc, followed by the others.
+    fn play_cd_hidden_track(&mut self, source_idx: usize) {
+        let Some(state) = self.cd_source_states.get(&source_idx) else {
+            return;
+        };
+        let Some(hidden) = state.disc.hidden_track() else {
+            return;
+        };
+        let mut disc = state.disc.clone();
+        disc.tracks.insert(0, hidden);
+        self.start_cd_playback(source_idx, &disc, 0);
+    }
+
+    fn start_cd_playback(
+        &mut self,
+        source_idx: usize,
+        disc: &rodio_player::cd_audio::CdInfo,
+        start_track: usize,
+    ) {
+        let device = self.config.sources[source_idx].path.clone();
+        let Some(read) = self
+            .cd_source_states
+            .get(&source_idx)
+            .map(|state| state.read.clone())
+        else {
+            return;
+        };
+        if let Err(e) = self
+            .zone()
+            .player
+            .play_cd(&device, disc, start_track, &read)
+        {
+            error!("Failed to start CD playback: {e}");
+        } else {
+            self.navigate_to_now_playing();
+        }
+    }
+
+    /// Read a folder of the data session of the disc in the background;
+    /// an empty `path` reads the root folder.
+    fn open_disc_folder(&mut self, source_idx: usize, path: Vec<DiscEntry>) {
+        let device = self.config.sources[source_idx].path.clone();
+        let Some(state) = self.cd_source_states.get_mut(&source_idx) else {
+            return;
+        };
+        let Some(session_lba) = state.disc.data_session_lba() else {
+            return;
+        };
+        state.files_status = "Reading files…".to_string();
+        let (files_tx, files_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = files_tx.send(data_disc::read_folder(&device, session_lba, path));
+        });
+        self.cd_files_rx.insert(source_idx, files_rx);
+    }
+
+    /// Play the audio files of the folder shown on the CD page, starting
+    /// with the `start`th one.
+    fn play_disc_files(&mut self, source_idx: usize, start: usize) {
+        let device = self.config.sources[source_idx].path.clone();
+        let Some(folder) = self
+            .cd_source_states
+            .get(&source_idx)
+            .and_then(|state| state.files.as_ref())
+        else {
+            return;
+        };
+        let items: Vec<QueueItem> = folder
+            .queue_items(&device)
+            .into_iter()
+            .skip(start)
+            .collect();
+        if items.is_empty() {
+            return;
+        }
+        self.zone().player.clear();
+        self.zone().player.append(items);
+        if let Err(e) = self.zone().player.play() {
+            error!("Failed to start playback: {e}");
+        } else {
+            self.navigate_to_now_playing();
         }
     }
 
     fn load_cd_toc(&mut self, source_idx: usize) {
         let source = &self.config.sources[source_idx];
         let device = source.path.clone();
+        // The editor belongs to the disc that was loaded before.
+        self.cd_editor
+            .take_if(|editor| editor.source_idx == source_idx);
         if let Some(state) = self.cd_source_states.get_mut(&source_idx) {
             state.loading = true;
             state.status = "Reading disc…".to_string();
-            state.tracks.clear();
+            state.disc = rodio_player::cd_audio::CdInfo::default();
+            state.files = None;
+            state.files_status.clear();
+            // Read errors are counted per disc.
+            state.read.stats = Arc::default();
         }
         // Read the TOC synchronously on a background thread so the UI
         // stays responsive.
@@ -926,24 +1580,162 @@ impl Homeplayer {
         });
         // We cannot block the UI thread, so we poll the result channel
         // in drain_channels.  Store the receiver for later polling.
-        self.cd_toc_rx = Some((source_idx, toc_rx));
+        self.cd_toc_rx.insert(source_idx, toc_rx);
+    }
+
+    /// Name the disc loaded for the CD source from the cache, or look it up
+    /// online in the background.
+    fn name_disc(&mut self, source_idx: usize) {
+        let Some(state) = self.cd_source_states.get_mut(&source_idx) else {
+            return;
+        };
+        let Some(disc_id) = state.disc.disc_id() else {
+            return;
+        };
+        debug!(
+            "Disc ID {} (FreeDB {})",
+            disc_id.musicbrainz, disc_id.freedb
+        );
+        if let Some(ref store) = self.music_store {
+            match store.get_cd_metadata(&disc_id.musicbrainz) {
+                Ok(Some(metadata)) => {
+                    state.apply_metadata(&metadata);
+                    return;
+                }
+                Ok(None) => {}
+                Err(e) => error!("Could not read cached disc metadata: {e}"),
+            }
+        }
+        let config = &self.config.cd_metadata;
+        if !config.enabled {
+            return;
+        }
+        let musicbrainz_url = config.musicbrainz_url.clone();
+        let cover_art_url = config.cover_art_url.clone();
+        let key = disc_id.musicbrainz.clone();
+        let (metadata_tx, metadata_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let result = MusicBrainz::new(&musicbrainz_url, &cover_art_url)
+                .and_then(|provider| provider.lookup(&disc_id));
+            let _ = metadata_tx.send(result);
+        });
+        self.cd_metadata_rx = Some((source_idx, key, metadata_rx));
+    }
+
+    fn edit_cd_metadata(&mut self, source_idx: usize) {
+        let Some(state) = self.cd_source_states.get(&source_idx) else {
+            return;
+        };
+        let covers = match self.music_store {
+            Some(ref store) => store.get_album_covers().unwrap_or_else(|e| {
+                error!("Could not get album covers: {e}");
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        self.cd_editor = CdEditorState::new(source_idx, &state.disc, covers);
+    }
+
+    /// Store the names typed in for a disc, so it is named whenever it is
+    /// inserted again.
+    fn save_cd_metadata(&mut self, source_idx: usize, disc_id: &str, metadata: CdMetadata) {
+        if let Some(ref store) = self.music_store
+            && let Err(e) = store.set_cd_metadata(disc_id, &metadata)
+        {
+            error!("Could not save disc metadata: {e}");
+            self.toasts
+                .push(egui_i18n::tr!("cd_editor_save_failed", { error: e.to_string() }));
+            return;
+        }
+        if let Some(state) = self.cd_source_states.get_mut(&source_idx) {
+            state.apply_metadata(&metadata);
+        }
+        self.cd_editor = None;
+    }
+
+    /// Cache what the lookup found for a disc and name it on its page.
+    fn store_disc_metadata(&mut self, source_idx: usize, disc_id: &str, found: DiscMetadata) {
+        info!("Disc {disc_id} is {} - {}", found.artist, found.album);
+        let cover = match found.cover {
+            Some(image) => {
+                let extension = if image.starts_with(b"\x89PNG") {
+                    "png"
+                } else {
+                    "jpg"
+                };
+                let path = Path::new(CD_COVER_DIR).join(format!("{disc_id}.{extension}"));
+                match std::fs::create_dir_all(CD_COVER_DIR)
+                    .and_then(|_| std::fs::write(&path, image))
+                {
+                    Ok(()) => path.to_string_lossy().to_string(),
+                    Err(e) => {
+                        warn!("Could not save cover of disc {disc_id}: {e}");
+                        String::new()
+                    }
+                }
+            }
+            None => String::new(),
+        };
+        let metadata = CdMetadata {
+            artist: found.artist,
+            album: found.album,
+            cover,
+            tracks: found.tracks,
+        };
+        if let Some(ref store) = self.music_store
+            && let Err(e) = store.set_cd_metadata(disc_id, &metadata)
+        {
+            error!("Could not cache disc metadata: {e}");
+        }
+        // The disc may have been ejected or replaced in the meantime.
+        if let Some(state) = self.cd_source_states.get_mut(&source_idx)
+            && state
+                .disc
+                .disc_id()
+                .is_some_and(|id| id.musicbrainz == disc_id)
+        {
+            state.apply_metadata(&metadata);
+        }
+    }
+
+    /// Rip the disc of a CD source into the folder of a library source, in
+    /// the background.  The ripped album is scanned when it is done.
+    fn rip_cd(&mut self, source_idx: usize, target_idx: usize, format: RipFormat, verify: bool) {
+        if self.cd_rip.is_some() {
+            return;
+        }
+        let Some(state) = self.cd_source_states.get_mut(&source_idx) else {
+            return;
+        };
+        let job = RipJob {
+            device: self.config.sources[source_idx].path.clone(),
+            disc: state.disc.clone(),
+            read: state.read.clone(),
+            target_dir: self.config.sources[target_idx].path.clone().into(),
+            format,
+            verify,
+        };
+        state.rip = Some(RipStatus::new(target_idx, state.disc.audio_tracks().len()));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let rx = cd_ripper::start(job, Arc::clone(&cancel));
+        self.cd_rip = Some((source_idx, cancel, rx));
     }
 
     fn play(&mut self) {
-        if self.is_paused {
-            self.player.pause(); // toggles pause→play
-        } else if !self.is_playing {
+        if self.zone().is_paused {
+            self.zone().player.pause(); // toggles pause→play
+        } else if !self.zone().is_playing {
             // Nothing playing – start playback depending on the
             // current page type (file source, CD source, or playlist).
             let current_page = self.swipe_view.current_page();
 
             // Playlist page: start from the beginning of the queue.
             if let Some(DynamicPage::Playlist) = self.pages.get(current_page) {
-                if !self.playlist_queue.is_empty() {
-                    if let Err(e) = self.player.play_from(0) {
+                if !self.zone().playlist_queue.is_empty() {
+                    if let Err(e) = self.zone().player.play_from(0) {
                         error!("Failed to set playlist start: {e}");
                     }
-                    if let Err(e) = self.player.play() {
+                    if let Err(e) = self.zone().player.play() {
                         error!("Failed to start playlist playback: {e}");
                     } else {
                         self.navigate_to_now_playing();
@@ -989,7 +1781,7 @@ impl Homeplayer {
                     ConfigSourceType::CD => {
                         // Play all audio tracks from the beginning
                         if let Some(state) = self.cd_source_states.get(&source_idx) {
-                            if !state.tracks.is_empty() {
+                            if !state.disc.tracks.is_empty() {
                                 self.process_action(UiAction::PlayCd {
                                     source_idx,
                                     start_track: 0,
@@ -1069,6 +1861,26 @@ impl Homeplayer {
         }
     }
 
+    /// Add a folder within a source to the library, e.g. a ripped album.
+    fn scan_folder(&mut self, source_idx: usize, folder: std::path::PathBuf) {
+        let source = &self.config.sources[source_idx];
+        if let Some(ref store) = self.music_store {
+            let store = Arc::clone(store);
+            let source_name = source.name.clone();
+            let folder = folder.to_string_lossy().to_string();
+            let scanning = Arc::clone(&self.scanning);
+            scanning.store(true, Ordering::SeqCst);
+            std::thread::spawn(move || {
+                info!("Scanning '{folder}' of source '{source_name}'...");
+                if let Err(e) = store.incremental_update(&source_name, &folder) {
+                    error!("Scan of '{folder}' failed: {e}");
+                }
+                scanning.store(false, Ordering::SeqCst);
+            });
+            self.scan_completed_source = Some(source_idx);
+        }
+    }
+
     fn browse_album_titles(&mut self, source_idx: usize, album_id: i32, album_name: String) {
         if let Some(state) = self.file_source_states.get_mut(&source_idx)
             && let Some(source_id) = state.source_id
@@ -1135,16 +1947,40 @@ impl Homeplayer {
         }
     }
 
-    fn play_stream(&mut self, url: String, icon: String) {
-        self.player.stop();
-        self.player.clear();
-        let player_clone = self.player.clone();
-        self.tokio_rt.spawn(async move {
-            if let Err(e) = player_clone.play_stream(&url, &icon).await {
-                error!("Failed to play stream: {e}");
-            }
+    /// Play another station of the same stream source in the zone, after
+    /// `dead` stayed silent.
+    fn fall_back_from_station(&mut self, zone_idx: usize, dead: &StationItem) {
+        let fallback = self.config.sources.iter().find_map(|source| {
+            let pos = source.stations.iter().position(|s| s.url == dead.url)?;
+            let len = source.stations.len();
+            (1..len)
+                .map(|offset| &source.stations[(pos + offset) % len])
+                .find(|s| s.url != dead.url)
         });
-        self.navigate_to_now_playing();
+        let Some(fallback) = fallback.cloned() else {
+            self.toasts
+                .push(egui_i18n::tr!("dead_air_toast", {station: dead.name}));
+            return;
+        };
+        info!("Falling back from {} to {}", dead.name, fallback.name);
+        self.toasts.push(egui_i18n::tr!("dead_air_fallback_toast", {
+            station: dead.name,
+            fallback: fallback.name
+        }));
+        if let Err(e) = self.zones[zone_idx]
+            .player
+            .play_stream(station_item(&fallback))
+        {
+            error!("Failed to play fallback stream: {e}");
+        }
+    }
+
+    fn play_stream(&mut self, station: &Station) {
+        if let Err(e) = self.zone().player.play_stream(station_item(station)) {
+            error!("Failed to play stream: {e}");
+        } else {
+            self.navigate_to_now_playing();
+        }
     }
 
     fn play_kids_album(&mut self, source_idx: usize, album_id: i32) {
@@ -1162,22 +1998,12 @@ impl Homeplayer {
     }
 
     fn play_titles(&mut self, titles: Vec<MusicTitleItem>, start_index: usize) {
-        self.player.clear();
-        let sound_items: Vec<SoundItem> = titles
-            .iter()
-            .map(|t| SoundItem {
-                artist: t.artist.clone(),
-                album: t.album.clone(),
-                title: t.name.clone(),
-                path: t.path.clone(),
-                cover: t.cover.clone(),
-            })
-            .collect();
-
+        self.zone().player.clear();
         // Skip to start_index by only appending from that index
-        let items_to_play: Vec<SoundItem> = sound_items.into_iter().skip(start_index).collect();
-        self.player.append(items_to_play);
-        if let Err(e) = self.player.play() {
+        let items_to_play: Vec<QueueItem> =
+            titles.iter().skip(start_index).map(queue_item).collect();
+        self.zone().player.append(items_to_play);
+        if let Err(e) = self.zone().player.play() {
             error!("Failed to start playback: {e}");
         } else {
             self.navigate_to_now_playing();
@@ -1185,17 +2011,9 @@ impl Homeplayer {
     }
 
     fn enqueue_titles(&mut self, titles: Vec<MusicTitleItem>) {
-        let sound_items: Vec<SoundItem> = titles
-            .iter()
-            .map(|t| SoundItem {
-                artist: t.artist.clone(),
-                album: t.album.clone(),
-                title: t.name.clone(),
-                path: t.path.clone(),
-                cover: t.cover.clone(),
-            })
-            .collect();
-        self.player.append(sound_items);
+        self.zone()
+            .player
+            .append(titles.iter().map(queue_item).collect());
     }
 
     fn enqueue_album(&mut self, source_idx: usize, album_id: i32) {
@@ -1303,9 +2121,24 @@ impl eframe::App for Homeplayer {
         // Drain player channels for state updates
         self.drain_channels();
 
-        // Update cover texture if the cover path changed
-        if self.current_title.cover != self.cover_texture_path {
-            self.cover_texture_path = self.current_title.cover.clone();
+        // Night mode follows its schedule, so look at the clock regularly
+        // even if nothing else happens.
+        let now = chrono::Local::now().time();
+        for zone in &mut self.zones {
+            zone.update_night_mode(now);
+        }
+        ctx.request_repaint_after(NIGHT_MODE_CHECK_INTERVAL);
+
+        // Update cover texture if the cover path changed.  A matching title
+        // from the local library provides the cover for stream titles.
+        let cover = match self.zone().library_match {
+            Some(ref library_match) if Path::new(&library_match.title.cover).exists() => {
+                &library_match.title.cover
+            }
+            _ => &self.zone().current_title.cover,
+        };
+        if *cover != self.cover_texture_path {
+            self.cover_texture_path = cover.clone();
             if !self.cover_texture_path.is_empty() {
                 let cover_path = Path::new(&self.cover_texture_path);
                 if cover_path.exists() {
@@ -1338,6 +2171,36 @@ impl eframe::App for Homeplayer {
             }
         }
 
+        // Lazily load the covers offered by the CD editor
+        if let Some(editor) = self
+            .cd_editor
+            .as_ref()
+            .filter(|editor| editor.picking_cover)
+        {
+            for item in &editor.covers {
+                if !self.kids_cover_textures.contains_key(&item.cover)
+                    && Path::new(&item.cover).exists()
+                    && let Some(img) = load_image_from_path(Path::new(&item.cover))
+                {
+                    let tex = ctx.load_texture(
+                        format!("kids_cover_{}", item.cover),
+                        img,
+                        TextureOptions::LINEAR,
+                    );
+                    self.kids_cover_textures.insert(item.cover.clone(), tex);
+                }
+            }
+        }
+        if let Some(cover) = self.cd_editor.as_ref().map(|editor| &editor.metadata.cover)
+            && !cover.is_empty()
+            && !self.kids_cover_textures.contains_key(cover)
+            && Path::new(cover).exists()
+            && let Some(img) = load_image_from_path(Path::new(cover))
+        {
+            let tex = ctx.load_texture(format!("kids_cover_{cover}"), img, TextureOptions::LINEAR);
+            self.kids_cover_textures.insert(cover.clone(), tex);
+        }
+
         // Lazily load station icon textures
         for source in &self.config.sources {
             if source.source_type == ConfigSourceType::Stream {
@@ -1366,8 +2229,13 @@ impl eframe::App for Homeplayer {
             }
         }
 
-        // Request repaint while playing or scanning for live updates
-        if self.is_playing || self.scanning.load(Ordering::SeqCst) {
+        // Request repaint while playing, scanning or ripping for live updates
+        if self.zones.iter().any(|zone| zone.is_playing)
+            || self.scanning.load(Ordering::SeqCst)
+            || self.cd_rip.is_some()
+            || !self.cd_toc_rx.is_empty()
+            || !self.cd_files_rx.is_empty()
+        {
             ctx.request_repaint();
         }
     }
@@ -1389,7 +2257,7 @@ impl eframe::App for Homeplayer {
                 }
 
                 // Play / Pause
-                let play_pause_label = if self.is_playing && !self.is_paused {
+                let play_pause_label = if self.zone().is_playing && !self.zone().is_paused {
                     "⏸"
                 } else {
                     "▶"
@@ -1398,7 +2266,7 @@ impl eframe::App for Homeplayer {
                     .add_sized(button_size, egui::Button::new(play_pause_label))
                     .clicked()
                 {
-                    if self.is_playing && !self.is_paused {
+                    if self.zone().is_playing && !self.zone().is_paused {
                         actions.push(UiAction::PlayerPause);
                     } else {
                         actions.push(UiAction::PlayerPlay);
@@ -1420,8 +2288,10 @@ impl eframe::App for Homeplayer {
                 // Track info
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     // Volume controls on the right (laid out right-to-left, so PLUS is outermost)
-                    let max_vol = self.config.audio.max_volume as f32 / 100.0;
+                    let max_vol = self.zone().audio.max_volume();
                     let vol_step = max_vol / 20.0; // 5% steps relative to max
+                    // Lower than max_vol while night mode is active.
+                    let vol_limit = self.zone().max_volume();
 
                     // PLUS button
                     if ui
@@ -1431,14 +2301,14 @@ impl eframe::App for Homeplayer {
                         )
                         .clicked()
                     {
-                        let new_vol = (self.volume + vol_step).min(max_vol);
-                        self.volume = new_vol;
+                        let new_vol = (self.zone().volume + vol_step).min(vol_limit);
+                        self.zone_mut().volume = new_vol;
                         actions.push(UiAction::PlayerVolume(new_vol));
                     }
 
                     // Progress bar with percentage label
                     let vol_fraction = if max_vol > 0.0 {
-                        self.volume / max_vol
+                        self.zone().volume / max_vol
                     } else {
                         0.0
                     };
@@ -1469,6 +2339,15 @@ impl eframe::App for Homeplayer {
                             egui::CornerRadius::same(4),
                             visuals.selection.bg_fill,
                         );
+                        // Night mode limit
+                        if vol_limit < max_vol && max_vol > 0.0 {
+                            let x = bar_rect.left() + bar_rect.width() * vol_limit / max_vol;
+                            painter.vline(
+                                x,
+                                bar_rect.y_range(),
+                                egui::Stroke::new(2.0, visuals.warn_fg_color),
+                            );
+                        }
                         // Percentage text centred on bar
                         painter.text(
                             bar_rect.center(),
@@ -1487,13 +2366,13 @@ impl eframe::App for Homeplayer {
                         )
                         .clicked()
                     {
-                        let new_vol = (self.volume - vol_step).max(0.0);
-                        self.volume = new_vol;
+                        let new_vol = (self.zone().volume - vol_step).max(0.0);
+                        self.zone_mut().volume = new_vol;
                         actions.push(UiAction::PlayerVolume(new_vol));
                     }
 
                     // MUTE button
-                    let mute_icon = if self.is_muted { "🔇" } else { "🔊" };
+                    let mute_icon = if self.zone().is_muted { "🔇" } else { "🔊" };
                     if ui
                         .add_sized(
                             egui::vec2(40.0, 40.0),
@@ -1504,22 +2383,31 @@ impl eframe::App for Homeplayer {
                         actions.push(UiAction::PlayerMute);
                     }
 
+                    // Night mode indicator
+                    if self.zone().night_mode {
+                        ui.label(egui::RichText::new("🌙").size(20.0))
+                            .on_hover_text(egui_i18n::tr!("night_mode_active_hover", {
+                                max: (vol_limit / max_vol.max(0.01) * 100.0).round()
+                            }));
+                    }
+
                     // Title text in remaining space (left-to-right, truncated)
                     ui.with_layout(egui::Layout::left_to_right(egui::Align::Center), |ui| {
-                        let status = if self.is_playing && !self.is_paused {
+                        let status = if self.zone().is_playing && !self.zone().is_paused {
                             "▶"
-                        } else if self.is_paused {
+                        } else if self.zone().is_paused {
                             "⏸"
                         } else {
                             "⏹"
                         };
 
-                        let title_text = if self.current_title.artist.is_empty() {
-                            self.current_title.title.clone()
+                        let title_text = if self.zone().current_title.artist.is_empty() {
+                            self.zone().current_title.title.clone()
                         } else {
                             format!(
                                 "{} - {}",
-                                self.current_title.artist, self.current_title.title
+                                self.zone().current_title.artist,
+                                self.zone().current_title.title
                             )
                         };
 
@@ -1582,15 +2470,22 @@ impl eframe::App for Homeplayer {
         // Pre-clone/copy data needed for rendering
         let pages = self.pages.clone();
         let config = self.config.clone();
-        let current_title = self.current_title.clone();
+        let zone_labels: Vec<String> = self.zones.iter().map(Zone::label).collect();
+        let selected_zone = self.selected_zone;
+        let current_title = self.zone().current_title.clone();
+        let library_match = self.zone().library_match.clone();
+        // Streams always play at normal speed.
+        let practising = self.zone().is_playing && !current_title.is_stream;
+        let speed = practising.then(|| self.zone().speed);
+        let ab_loop = practising.then(|| self.zone().ab_loop);
         let is_scanning = self.scanning.load(Ordering::SeqCst);
 
         // Pre-extract cover texture reference to avoid borrow conflict with swipe_view
         let cover_texture = self.cover_texture.clone();
 
         // Snapshot playlist data for rendering (already updated by drain_channels)
-        let playlist_queue = self.playlist_queue.clone();
-        let playlist_index = self.playlist_index;
+        let playlist_queue = self.zone().playlist_queue.clone();
+        let playlist_index = self.zone().playlist_index;
 
         // Clone kids cover textures for rendering
         let kids_cover_textures = self.kids_cover_textures.clone();
@@ -1679,6 +2574,7 @@ impl eframe::App for Homeplayer {
             .collect();
 
         let settings_state = &mut self.settings_state;
+        let cd_editor = &mut self.cd_editor;
         let bluetooth_manager = &self.bluetooth_manager;
 
         egui::CentralPanel::default()
@@ -1777,15 +2673,27 @@ impl eframe::App for Homeplayer {
                                             source,
                                             *source_idx,
                                             &cd_state,
+                                            cd_editor.as_mut(),
+                                            &kids_cover_textures,
                                             &mut actions,
                                         );
                                     }
                                 }
                             }
                             DynamicPage::NowPlaying => {
-                                paint_now_playing(ui, &current_title, cover_texture.as_ref());
+                                paint_zone_selector(ui, &zone_labels, selected_zone, &mut actions);
+                                paint_now_playing(
+                                    ui,
+                                    &current_title,
+                                    cover_texture.as_ref(),
+                                    library_match.as_ref(),
+                                    speed,
+                                    ab_loop,
+                                    &mut actions,
+                                );
                             }
                             DynamicPage::Playlist => {
+                                paint_zone_selector(ui, &zone_labels, selected_zone, &mut actions);
                                 paint_playlist(ui, &playlist_queue, playlist_index, &mut actions);
                             }
                             DynamicPage::Settings => {
@@ -1796,6 +2704,8 @@ impl eframe::App for Homeplayer {
                 );
             });
 
+        self.toasts.show(ui.ctx());
+
         // Process collected actions
         for action in actions {
             self.process_action(action);
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
@@ -39,6 +40,26 @@ pub struct KidsAlbumItem {
     pub cover: String,
 }
 
+/// A title of the library that matches an artist/title pair announced by a
+/// stream, see [`MusicStore::find_title_match`].
+#[derive(Debug, Clone)]
+pub struct LibraryMatch {
+    pub title: MusicTitleItem,
+    pub source_id: i32,
+    pub artist_id: i32,
+}
+
+/// Names and cover of an audio CD, cached by disc ID.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CdMetadata {
+    pub artist: String,
+    pub album: String,
+    /// Path of the cover image, empty if there is none.
+    pub cover: String,
+    /// Track titles by track number.
+    pub tracks: BTreeMap<u8, String>,
+}
+
 #[derive(Debug)]
 pub struct NewMusicTitle {
     pub name: String,
@@ -122,6 +143,7 @@ impl MusicStore {
                     path   TEXT UNIQUE NOT NULL,
                     cover  INTEGER,
                     track  INTEGER NOT NULL,
+                    match_title TEXT,
                     FOREIGN KEY (source) REFERENCES sources(id),
                     FOREIGN KEY (artist) REFERENCES artists(id),
                     FOREIGN KEY (album) REFERENCES albums(id),
@@ -130,6 +152,62 @@ impl MusicStore {
                 )",
             (), // empty list of parameters.
         )?;
+
+        // The title as compared by `find_title_match`.  Libraries scanned
+        // before it was added get it here.
+        if db_connection
+            .prepare("SELECT match_title FROM titles LIMIT 0")
+            .is_err()
+        {
+            db_connection.execute("ALTER TABLE titles ADD COLUMN match_title TEXT", ())?;
+        }
+        db_connection.execute(
+            "CREATE INDEX IF NOT EXISTS titles_match_title ON titles (match_title)",
+            (),
+        )?;
+        let missing: Vec<(i32, String)> = db_connection
+            .prepare("SELECT id,title FROM titles WHERE match_title IS NULL")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_>>()?;
+        if !missing.is_empty() {
+            debug!("Add match titles to {} titles", missing.len());
+            let transaction = db_connection.unchecked_transaction()?;
+            for (id, title) in missing {
+                transaction.execute(
+                    "UPDATE titles SET match_title=(?1) WHERE id=(?2)",
+                    (normalize_for_match(&title), id),
+                )?;
+            }
+            transaction.commit()?;
+        }
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS playback_speeds (
+                    key    TEXT PRIMARY KEY,
+                    speed  REAL NOT NULL
+                )",
+            (), // empty list of parameters.
+        )?;
+
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS cd_discs (
+                    disc_id TEXT PRIMARY KEY,
+                    artist  TEXT NOT NULL,
+                    album   TEXT NOT NULL,
+                    cover   TEXT NOT NULL
+                )",
+            (), // empty list of parameters.
+        )?;
+        db_connection.execute(
+            "CREATE TABLE IF NOT EXISTS cd_tracks (
+                    disc_id TEXT NOT NULL,
+                    track   INTEGER NOT NULL,
+                    title   TEXT NOT NULL,
+                    FOREIGN KEY (disc_id) REFERENCES cd_discs(disc_id),
+                    PRIMARY KEY (disc_id, track)
+                )",
+            (), // empty list of parameters.
+        )?;
         drop(db_connection);
 
         Ok(())
@@ -180,7 +258,7 @@ impl MusicStore {
         let album_id = self.get_album_id(&title.album)?;
         let db_connection = self.db_connection.lock().expect("DB is locked");
         db_connection.execute(
-            "INSERT INTO titles (source, artist, album, title, path, cover, track) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "INSERT INTO titles (source, artist, album, title, path, cover, track, match_title) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             [
                 &source_id.to_string(),
                 &artist_id.to_string(),
@@ -189,6 +267,7 @@ impl MusicStore {
                 &title.path,
                 &cover_id.to_string(),
                 &title.track.to_string(),
+                &normalize_for_match(&title.name),
             ],
         )?;
         Ok(())
@@ -289,6 +368,27 @@ impl MusicStore {
         Ok(albums)
     }
 
+    /// Albums of all sources that have a cover, e.g. to pick a cover for a CD.
+    pub fn get_album_covers(&self) -> Result<Vec<KidsAlbumItem>> {
+        let db_connection = self.db_connection.lock().expect("DB is locked");
+        let mut stmt = db_connection.prepare(
+            "SELECT albums.id, albums.album, artists.artist, covers.path \
+             FROM albums \
+             INNER JOIN artists ON albums.artist = artists.id \
+             INNER JOIN covers ON albums.cover = covers.id \
+             ORDER BY artists.artist, albums.album",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(KidsAlbumItem {
+                id: row.get(0)?,
+                album_name: row.get(1)?,
+                artist_name: row.get(2)?,
+                cover: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
     pub fn get_albums_by_artist(&self, source: i32, artist_id: i32) -> Result<Vec<MusicItem>> {
         debug!("Get albums by artist {artist_id} and source {source} ...");
         let db_connection = self.db_connection.lock().expect("DB is locked");
@@ -432,6 +532,144 @@ impl MusicStore {
         })
     }
 
+    /// Find a title in the library by artist and title name, e.g. to match the
+    /// song currently announced by a radio station.
+    ///
+    /// The comparison is case- and punctuation-insensitive and ignores
+    /// bracketed additions like "(Radio Edit)" as well as featured artists.
+    /// An exact match of both artist and title is preferred; otherwise a title
+    /// whose artist contains the words of the given artist (or vice versa) is
+    /// accepted, e.g. "Queen" for "Queen & David Bowie", but not for
+    /// "Queensrÿche".
+    pub fn find_title_match(&self, artist: &str, title: &str) -> Result<Option<LibraryMatch>> {
+        let wanted_artist = normalize_for_match(artist);
+        let wanted_title = normalize_for_match(title);
+        if wanted_artist.is_empty() || wanted_title.is_empty() {
+            return Ok(None);
+        }
+
+        let db_connection = self.db_connection.lock().expect("DB is locked");
+        let mut stmt = db_connection.prepare(
+            "SELECT titles.id,titles.source,titles.artist,artists.artist FROM titles INNER JOIN artists ON titles.artist=artists.id WHERE titles.match_title=(?1)",
+        )?;
+        let rows = stmt.query_map([&wanted_title], |row| {
+            Ok((
+                row.get::<usize, i32>(0)?,
+                row.get::<usize, i32>(1)?,
+                row.get::<usize, i32>(2)?,
+                row.get::<usize, String>(3)?,
+            ))
+        })?;
+
+        let mut candidate: Option<(i32, i32, i32)> = None;
+        for row in rows {
+            let (id, source_id, artist_id, artist_name) = match row {
+                Ok(row) => row,
+                Err(e) => {
+                    error!("Error: {}", e);
+                    continue;
+                }
+            };
+            let artist_name = normalize_for_match(&artist_name);
+            if artist_name == wanted_artist {
+                candidate = Some((id, source_id, artist_id));
+                break;
+            }
+            if candidate.is_none()
+                && (contains_words(&artist_name, &wanted_artist)
+                    || contains_words(&wanted_artist, &artist_name))
+            {
+                candidate = Some((id, source_id, artist_id));
+            }
+        }
+        drop(stmt);
+        drop(db_connection);
+
+        match candidate {
+            Some((id, source_id, artist_id)) => {
+                debug!("Found library match for {artist} - {title}: title {id}");
+                Ok(Some(LibraryMatch {
+                    title: self.get_title_by_id(id)?,
+                    source_id,
+                    artist_id,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The playback speed remembered for the first of `keys` that has one.
+    pub fn get_playback_speed(&self, keys: &[String]) -> Result<Option<f32>> {
+        let db_connection = self.db_connection.lock().expect("DB is locked");
+        let mut stmt = db_connection.prepare("SELECT speed FROM playback_speeds WHERE key=(?1)")?;
+        for key in keys {
+            match stmt.query_row([key], |row| row.get::<usize, f64>(0)) {
+                Ok(speed) => return Ok(Some(speed as f32)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remember the playback speed for `key`.
+    pub fn set_playback_speed(&self, key: &str, speed: f32) -> Result<()> {
+        let db_connection = self.db_connection.lock().expect("DB is locked");
+        db_connection.execute(
+            "INSERT OR REPLACE INTO playback_speeds (key, speed) VALUES (?1, ?2)",
+            (key, speed as f64),
+        )?;
+        Ok(())
+    }
+
+    /// The cached names of the disc with the given ID.
+    pub fn get_cd_metadata(&self, disc_id: &str) -> Result<Option<CdMetadata>> {
+        let db_connection = self.db_connection.lock().expect("DB is locked");
+        let disc = db_connection.query_row(
+            "SELECT artist, album, cover FROM cd_discs WHERE disc_id=(?1)",
+            [disc_id],
+            |row| {
+                Ok(CdMetadata {
+                    artist: row.get(0)?,
+                    album: row.get(1)?,
+                    cover: row.get(2)?,
+                    tracks: BTreeMap::new(),
+                })
+            },
+        );
+        let mut disc = match disc {
+            Ok(disc) => disc,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut stmt =
+            db_connection.prepare("SELECT track, title FROM cd_tracks WHERE disc_id=(?1)")?;
+        let tracks = stmt.query_map([disc_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for track in tracks {
+            let (number, title) = track?;
+            disc.tracks.insert(number, title);
+        }
+        Ok(Some(disc))
+    }
+
+    /// Cache the names of the disc with the given ID, replacing older ones.
+    pub fn set_cd_metadata(&self, disc_id: &str, metadata: &CdMetadata) -> Result<()> {
+        let mut db_connection = self.db_connection.lock().expect("DB is locked");
+        let transaction = db_connection.transaction()?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO cd_discs (disc_id, artist, album, cover) VALUES (?1, ?2, ?3, ?4)",
+            (disc_id, &metadata.artist, &metadata.album, &metadata.cover),
+        )?;
+        transaction.execute("DELETE FROM cd_tracks WHERE disc_id=(?1)", [disc_id])?;
+        for (number, title) in &metadata.tracks {
+            transaction.execute(
+                "INSERT INTO cd_tracks (disc_id, track, title) VALUES (?1, ?2, ?3)",
+                (disc_id, number, title),
+            )?;
+        }
+        transaction.commit()
+    }
+
     pub fn update(&self, source_name: &String, path: &String) -> Result<(), Error> {
         if let Ok(source_id) = self.get_source_id(source_name) {
             debug!("Source ID is {source_id}");
@@ -525,13 +763,13 @@ impl MusicStore {
         Ok(())
     }
 
-    fn is_supported_extension(path: &Path) -> bool {
+    pub(crate) fn is_supported_extension(path: &Path) -> bool {
         matches!(
             path.extension()
                 .unwrap_or_default()
                 .to_ascii_lowercase()
                 .to_str(),
-            Some("mp3") | Some("flac")
+            Some("mp3") | Some("flac") | Some("wav")
         )
     }
 
@@ -578,6 +816,56 @@ impl MusicStore {
     }
 }
 
+/// Normalize an artist or title name for fuzzy comparison: lower case,
+/// without bracketed additions, featured artists, a leading "the" and any
+/// punctuation.  "&" is treated like "and".
+fn normalize_for_match(name: &str) -> String {
+    let mut without_brackets = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    for c in name.chars() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => without_brackets.push(c),
+            _ => (),
+        }
+    }
+
+    let cleaned: String = without_brackets
+        .replace('&', " and ")
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_lowercase().next().unwrap_or(c)
+            } else if c == '\'' || c == '’' {
+                '\0'
+            } else {
+                ' '
+            }
+        })
+        .filter(|c| *c != '\0')
+        .collect();
+
+    let mut words: Vec<&str> = Vec::new();
+    for word in cleaned.split_whitespace() {
+        if matches!(word, "feat" | "ft" | "featuring") {
+            break;
+        }
+        words.push(word);
+    }
+    if words.len() > 1 && words[0] == "the" {
+        words.remove(0);
+    }
+    words.join(" ")
+}
+
+/// Whether the words of `needle` follow each other in `haystack`.
+fn contains_words(haystack: &str, needle: &str) -> bool {
+    let haystack: Vec<&str> = haystack.split_whitespace().collect();
+    let needle: Vec<&str> = needle.split_whitespace().collect();
+    !needle.is_empty() && haystack.windows(needle.len()).any(|words| words == needle)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -596,9 +884,15 @@ mod tests {
         assert!(MusicStore::is_supported_extension(&path));
     }
 
+    #[test]
+    fn test_is_supported_extension_wav() {
+        let path = Path::new("test.WAV").to_path_buf();
+        assert!(MusicStore::is_supported_extension(&path));
+    }
+
     #[test]
     fn test_is_supported_extension_not_supported() {
-        let path = Path::new("test.wav").to_path_buf();
+        let path = Path::new("test.txt").to_path_buf();
         assert!(!MusicStore::is_supported_extension(&path));
     }
 
@@ -659,6 +953,113 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_normalize_for_match() {
+        assert_eq!(
+            normalize_for_match("Don't Stop Me Now!"),
+            "dont stop me now"
+        );
+        assert_eq!(normalize_for_match("  HELLO,   World  "), "hello world");
+        assert_eq!(
+            normalize_for_match("Bohemian Rhapsody (Remastered 2011)"),
+            "bohemian rhapsody"
+        );
+        assert_eq!(normalize_for_match("The Beatles"), "beatles");
+        assert_eq!(
+            normalize_for_match("Simon & Garfunkel"),
+            "simon and garfunkel"
+        );
+        assert_eq!(
+            normalize_for_match("Calvin Harris feat. Rihanna"),
+            "calvin harris"
+        );
+    }
+
+    fn add_test_title(store: &MusicStore, artist: &str, title: &str) {
+        store
+            .add_title(&NewMusicTitle {
+                name: title.to_string(),
+                path: format!("/music/{artist}/{title}.mp3"),
+                cover: format!("/music/{artist}/cover.jpg"),
+                artist: artist.to_string(),
+                album: format!("{artist} album"),
+                source: "Music".to_string(),
+                track: 1,
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_find_title_match() -> Result<()> {
+        let music_store = MusicStore::new(rusqlite::Connection::open_in_memory()?);
+        music_store.init()?;
+        add_test_title(&music_store, "Queen", "Don't Stop Me Now");
+        add_test_title(&music_store, "The Beatles", "Let It Be");
+
+        let found = music_store
+            .find_title_match("QUEEN", "Dont stop me now (Radio Edit)")?
+            .expect("title should match");
+        assert_eq!(found.title.name, "Don't Stop Me Now");
+        assert_eq!(found.title.cover, "/music/Queen/cover.jpg");
+
+        let found = music_store
+            .find_title_match("Beatles", "Let it be")?
+            .expect("title should match");
+        assert_eq!(found.title.artist, "The Beatles");
+
+        assert!(
+            music_store
+                .find_title_match("Queen", "Let It Be")?
+                .is_none()
+        );
+        assert!(music_store.find_title_match("-", "-")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_title_match_whole_words() -> Result<()> {
+        let music_store = MusicStore::new(rusqlite::Connection::open_in_memory()?);
+        music_store.init()?;
+        add_test_title(&music_store, "Queen & David Bowie", "Under Pressure");
+        add_test_title(&music_store, "Queensrÿche", "Silent Lucidity");
+
+        let found = music_store
+            .find_title_match("Queen", "Under Pressure")?
+            .expect("title should match");
+        assert_eq!(found.title.artist, "Queen & David Bowie");
+        assert!(
+            music_store
+                .find_title_match("Queen", "Silent Lucidity")?
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_titles_of_older_libraries() -> Result<()> {
+        let music_store = MusicStore::new(rusqlite::Connection::open_in_memory()?);
+        music_store.init()?;
+        add_test_title(&music_store, "Queen", "Don't Stop Me Now");
+        music_store
+            .db_connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE titles SET match_title=NULL", ())?;
+        assert!(
+            music_store
+                .find_title_match("Queen", "Don't Stop Me Now")?
+                .is_none()
+        );
+
+        music_store.init()?;
+        assert!(
+            music_store
+                .find_title_match("Queen", "Don't Stop Me Now")?
+                .is_some()
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_create_db() -> Result<()> {
         let test_db = rusqlite::Connection::open("./test_db.db3")?;
@@ -675,4 +1076,37 @@ mod tests {
         let _ = std::fs::remove_file("./test_db.db3");
         Ok(())
     }
+
+    #[test]
+    fn test_playback_speeds() -> Result<()> {
+        let music_store = MusicStore::new(rusqlite::Connection::open_in_memory()?);
+        music_store.init()?;
+        let keys = ["album:A\u{1f}B".to_string(), "source:Books".to_string()];
+        assert_eq!(music_store.get_playback_speed(&keys)?, None);
+        music_store.set_playback_speed("source:Books", 1.5)?;
+        assert_eq!(music_store.get_playback_speed(&keys)?, Some(1.5));
+        music_store.set_playback_speed("album:A\u{1f}B", 0.75)?;
+        music_store.set_playback_speed("album:A\u{1f}B", 1.0)?;
+        assert_eq!(music_store.get_playback_speed(&keys)?, Some(1.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cd_metadata() -> Result<()> {
+        let music_store = MusicStore::new(rusqlite::Connection::open_in_memory()?);
+        music_store.init()?;
+        assert_eq!(music_store.get_cd_metadata("disc")?, None);
+        let mut metadata = CdMetadata {
+            artist: "Simon & Garfunkel".to_string(),
+            album: "Bridge over Troubled Water".to_string(),
+            cover: "cd_covers/disc.jpg".to_string(),
+            tracks: BTreeMap::from([(1, "One".to_string()), (2, "Two".to_string())]),
+        };
+        music_store.set_cd_metadata("disc", &metadata)?;
+        assert_eq!(music_store.get_cd_metadata("disc")?, Some(metadata.clone()));
+        metadata.tracks.remove(&2);
+        music_store.set_cd_metadata("disc", &metadata)?;
+        assert_eq!(music_store.get_cd_metadata("disc")?, Some(metadata));
+        Ok(())
+    }
 }
@@ -1,45 +1,184 @@
+use std::collections::HashMap;
+
 use eframe::egui;
 
 use crate::UiAction;
+use crate::cd_ripper::{RipStatus, TrackOutcome};
 use crate::config::Source;
+use crate::data_disc::DiscFolder;
+use crate::music_store::{CdMetadata, KidsAlbumItem};
+use crate::pages::keyboard::{keyboard_height, paint_keyboard};
 use crate::pages::semi_transparent_fill;
-use rodio_player::cd_audio::CdTrackInfo;
+use rodio_player::cd_audio::{CdInfo, CdReadSettings, CdTrackInfo};
+use rodio_player::cd_rip::RipFormat;
+
+/// At most this many library covers are offered in the cover picker.
+pub const MAX_COVER_CHOICES: usize = 48;
 
 /// State for the CD source page, stored in the main application.
 #[derive(Debug, Clone)]
 pub struct CdSourceState {
-    /// The loaded track listing, if any.
-    pub tracks: Vec<CdTrackInfo>,
+    /// The loaded disc; without tracks if none was read.
+    pub disc: CdInfo,
     /// Whether a TOC read is currently in progress.
     pub loading: bool,
     /// Status / error message to display.
     pub status: String,
     /// Whether a disc was detected on last check.
     pub disc_present: bool,
+    /// File and KidsFile sources the disc can be ripped into: index and name.
+    pub rip_targets: Vec<(usize, String)>,
+    /// The running or last rip of the disc.
+    pub rip: Option<RipStatus>,
+    /// How the disc is read, for playing and ripping, and its read errors.
+    pub read: CdReadSettings,
+    /// The folder of a data disc or data session being browsed.
+    pub files: Option<DiscFolder>,
+    /// Progress or error of reading a folder of the disc.
+    pub files_status: String,
 }
 
 impl CdSourceState {
     pub fn new() -> Self {
         Self {
-            tracks: Vec::new(),
+            disc: CdInfo::default(),
             loading: false,
             status: String::new(),
             disc_present: false,
+            rip_targets: Vec::new(),
+            rip: None,
+            read: CdReadSettings::default(),
+            files: None,
+            files_status: String::new(),
+        }
+    }
+
+    /// Whether the disc is being ripped.
+    pub fn ripping(&self) -> bool {
+        self.rip.as_ref().is_some_and(|rip| !rip.finished)
+    }
+
+    /// Name the loaded disc and its tracks.  Empty names keep what the disc
+    /// says about itself (CD-Text); the cover is always replaced.
+    pub fn apply_metadata(&mut self, metadata: &CdMetadata) {
+        let known = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        if let Some(artist) = known(&metadata.artist) {
+            self.disc.performer = Some(artist);
+        }
+        if let Some(album) = known(&metadata.album) {
+            self.disc.title = Some(album);
+        }
+        self.disc.cover = known(&metadata.cover);
+        for track in &mut self.disc.tracks {
+            if let Some(title) = metadata.tracks.get(&track.number).and_then(|t| known(t)) {
+                track.title = Some(title);
+            }
         }
     }
 }
 
+/// Choices of the rip panel, kept in egui's memory while it is open.
+#[derive(Debug, Clone)]
+struct RipOptions {
+    target_idx: usize,
+    format: RipFormat,
+    verify: bool,
+}
+
+/// The field of the CD editor the keyboard types into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditorField {
+    Artist,
+    Album,
+    /// Title of the track with this number.
+    Track(u8),
+}
+
+/// Names and cover typed in for a disc that no database knows.
+#[derive(Debug, Clone)]
+pub struct CdEditorState {
+    pub source_idx: usize,
+    /// Disc ID (TOC fingerprint) the names are stored under.
+    pub disc_id: String,
+    pub metadata: CdMetadata,
+    /// Numbers of the audio tracks, in disc order.
+    pub track_numbers: Vec<u8>,
+    pub field: EditorField,
+    pub shift: bool,
+    /// Library albums whose cover can be picked for the disc.
+    pub covers: Vec<KidsAlbumItem>,
+    pub picking_cover: bool,
+}
+
+impl CdEditorState {
+    /// Start editing the names the disc has now.  `None` for discs without
+    /// audio tracks, which have no disc ID.
+    pub fn new(source_idx: usize, disc: &CdInfo, covers: Vec<KidsAlbumItem>) -> Option<Self> {
+        let disc_id = disc.disc_id()?.musicbrainz;
+        let audio_tracks = disc.audio_tracks();
+        Some(Self {
+            source_idx,
+            disc_id,
+            metadata: CdMetadata {
+                artist: disc.performer.clone().unwrap_or_default(),
+                album: disc.title.clone().unwrap_or_default(),
+                cover: disc.cover.clone().unwrap_or_default(),
+                tracks: audio_tracks
+                    .iter()
+                    .filter_map(|t| Some((t.number, t.title.clone()?)))
+                    .collect(),
+            },
+            track_numbers: audio_tracks.iter().map(|t| t.number).collect(),
+            field: EditorField::Album,
+            shift: true,
+            covers,
+            picking_cover: false,
+        })
+    }
+
+    fn text_mut(&mut self) -> &mut String {
+        match self.field {
+            EditorField::Artist => &mut self.metadata.artist,
+            EditorField::Album => &mut self.metadata.album,
+            EditorField::Track(number) => self.metadata.tracks.entry(number).or_default(),
+        }
+    }
+
+    /// The covers to offer: those of albums by the typed artist or with the
+    /// typed album name first.
+    fn cover_choices(&self) -> Vec<&KidsAlbumItem> {
+        let artist = self.metadata.artist.to_lowercase();
+        let album = self.metadata.album.to_lowercase();
+        let matches = |item: &KidsAlbumItem| {
+            (!artist.is_empty() && item.artist_name.to_lowercase().contains(&artist))
+                || (!album.is_empty() && item.album_name.to_lowercase().contains(&album))
+        };
+        let mut choices: Vec<&KidsAlbumItem> = self.covers.iter().collect();
+        choices.sort_by_key(|item| !matches(item));
+        choices.truncate(MAX_COVER_CHOICES);
+        choices
+    }
+}
+
 pub fn paint_cd_source(
     ui: &mut egui::Ui,
     _source: &Source,
     source_idx: usize,
     state: &CdSourceState,
+    editor: Option<&mut CdEditorState>,
+    cover_textures: &HashMap<String, egui::TextureHandle>,
     actions: &mut Vec<UiAction>,
 ) {
     ui.add_space(8.0);
 
+    if let Some(editor) = editor.filter(|editor| editor.source_idx == source_idx) {
+        paint_cd_editor(ui, editor, cover_textures, actions);
+        return;
+    }
+
     // Action bar: disc info on the left, Refresh / Eject on the right
     let action_button_size = egui::vec2(120.0, 48.0);
+    let rip_options_id = egui::Id::new(("cd_rip_options", source_idx));
     ui.horizontal(|ui| {
         if state.loading {
             ui.spinner();
@@ -50,16 +189,23 @@ pub fn paint_cd_source(
             );
         } else {
             // Disc summary on the left
-            if !state.tracks.is_empty() {
+            if !state.disc.tracks.is_empty() {
                 let audio_tracks: Vec<&CdTrackInfo> =
-                    state.tracks.iter().filter(|t| t.is_audio).collect();
-                let data_tracks = state.tracks.len() - audio_tracks.len();
+                    state.disc.tracks.iter().filter(|t| t.is_audio).collect();
+                let data_tracks = state.disc.tracks.len() - audio_tracks.len();
                 let total_duration: std::time::Duration =
                     audio_tracks.iter().map(|t| t.duration).sum();
                 let total_mins = total_duration.as_secs() / 60;
                 let total_secs = total_duration.as_secs() % 60;
 
                 ui.vertical(|ui| {
+                    if let Some(title) = &state.disc.title {
+                        let heading = match &state.disc.performer {
+                            Some(performer) => format!("{performer} – {title}"),
+                            None => title.clone(),
+                        };
+                        ui.label(egui::RichText::new(heading).size(16.0).strong());
+                    }
                     let duration_str = format!("{}:{:02}", total_mins, total_secs);
                     let track_key = if audio_tracks.len() == 1 {
                         "audio_tracks_one"
@@ -73,6 +219,23 @@ pub fn paint_cd_source(
                         }))
                         .size(14.0),
                     );
+                    let stats = *state.read.stats.lock().unwrap();
+                    if !stats.is_clean() {
+                        let concealed = stats.interpolated_sectors + stats.muted_sectors > 0;
+                        let text = egui::RichText::new(egui_i18n::tr!("cd_read_errors", {
+                            rereads: stats.rereads,
+                            jitter: stats.jitter_corrections,
+                            interpolated: stats.interpolated_sectors,
+                            muted: stats.muted_sectors
+                        }))
+                        .size(12.0);
+                        ui.label(if concealed {
+                            text.color(ui.visuals().warn_fg_color)
+                        } else {
+                            text.weak()
+                        })
+                        .on_hover_text(egui_i18n::tr!("cd_read_errors_hover"));
+                    }
                     if data_tracks > 0 {
                         let data_key = if data_tracks == 1 {
                             "data_tracks_one"
@@ -92,6 +255,21 @@ pub fn paint_cd_source(
 
             // Buttons on the right
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if state.ripping() {
+                    if ui
+                        .add_sized(
+                            action_button_size,
+                            egui::Button::new(
+                                egui::RichText::new(egui_i18n::tr!("cancel_button")).size(16.0),
+                            ),
+                        )
+                        .on_hover_text(egui_i18n::tr!("rip_cancel_hover"))
+                        .clicked()
+                    {
+                        actions.push(UiAction::CancelRip);
+                    }
+                    return;
+                }
                 if ui
                     .add_sized(
                         action_button_size,
@@ -116,14 +294,60 @@ pub fn paint_cd_source(
                 {
                     actions.push(UiAction::LoadCdToc { source_idx });
                 }
+                if !state.disc.audio_tracks().is_empty()
+                    && ui
+                        .add_sized(
+                            action_button_size,
+                            egui::Button::new(
+                                egui::RichText::new(egui_i18n::tr!("cd_edit_button")).size(16.0),
+                            ),
+                        )
+                        .on_hover_text(egui_i18n::tr!("cd_edit_hover"))
+                        .clicked()
+                {
+                    actions.push(UiAction::EditCdMetadata { source_idx });
+                }
+                if let Some((target_idx, _)) = state.rip_targets.first()
+                    && !state.disc.audio_tracks().is_empty()
+                    && ui
+                        .add_sized(
+                            action_button_size,
+                            egui::Button::new(
+                                egui::RichText::new(egui_i18n::tr!("rip_button")).size(16.0),
+                            ),
+                        )
+                        .on_hover_text(egui_i18n::tr!("rip_hover"))
+                        .clicked()
+                {
+                    let options = RipOptions {
+                        target_idx: *target_idx,
+                        format: RipFormat::default(),
+                        verify: false,
+                    };
+                    ui.data_mut(|data| data.insert_temp(rip_options_id, Some(options)));
+                }
             });
         }
     });
 
     ui.add_space(4.0);
 
+    if let Some(rip) = &state.rip {
+        paint_rip_status(ui, source_idx, state, rip, actions);
+    } else if !state.disc.audio_tracks().is_empty() {
+        let mut options: Option<RipOptions> =
+            ui.data(|data| data.get_temp(rip_options_id)).flatten();
+        if let Some(current) = options.as_mut() {
+            let close = paint_rip_options(ui, source_idx, state, current, actions);
+            if close {
+                options = None;
+            }
+            ui.data_mut(|data| data.insert_temp(rip_options_id, options));
+        }
+    }
+
     // Status message when there are no tracks
-    if state.tracks.is_empty() {
+    if state.disc.tracks.is_empty() {
         let status_text = if state.status.is_empty() {
             egui_i18n::tr!("insert_cd_hint")
         } else {
@@ -140,7 +364,28 @@ pub fn paint_cd_source(
 
     // Track listing
     let fill = semi_transparent_fill(ui);
-    for track in &state.tracks {
+    if let Some(hidden) = state.disc.hidden_track() {
+        ui.add_space(2.0);
+        let label = format!(
+            "  🎵   Track {:02}   {}   {}",
+            hidden.number,
+            egui_i18n::tr!("hidden_track_label"),
+            hidden.duration_display(),
+        );
+        if ui
+            .add(
+                egui::Button::new(egui::RichText::new(&label).size(16.0))
+                    .fill(fill)
+                    .frame(true)
+                    .min_size(egui::vec2(ui.available_width(), 48.0)),
+            )
+            .on_hover_text(egui_i18n::tr!("hidden_track_hover"))
+            .clicked()
+        {
+            actions.push(UiAction::PlayCdHiddenTrack { source_idx });
+        }
+    }
+    for track in &state.disc.tracks {
         if !track.is_audio {
             // Show data tracks as disabled
             ui.add_space(2.0);
@@ -162,11 +407,19 @@ pub fn paint_cd_source(
             continue;
         }
 
-        let label = format!(
-            "  🎵   Track {:02}   {}",
-            track.number,
-            track.duration_display(),
-        );
+        let label = match &track.title {
+            Some(title) => format!(
+                "  🎵   {:02}   {}   {}",
+                track.number,
+                title,
+                track.duration_display(),
+            ),
+            None => format!(
+                "  🎵   Track {:02}   {}",
+                track.number,
+                track.duration_display(),
+            ),
+        };
 
         ui.add_space(2.0);
         let response = ui.add(
@@ -179,6 +432,7 @@ pub fn paint_cd_source(
         if response.clicked() {
             // Find the index within audio-only tracks for this track
             let audio_index = state
+                .disc
                 .tracks
                 .iter()
                 .filter(|t| t.is_audio)
@@ -191,9 +445,399 @@ pub fn paint_cd_source(
             });
         }
 
-        response.on_hover_text(egui_i18n::tr!("play_from_track_hover", {
+        let mut hover = egui_i18n::tr!("play_from_track_hover", {
             number: track.number,
             sectors: track.sector_count()
-        }));
+        });
+        if track.pre_emphasis {
+            hover = format!("{hover}\n{}", egui_i18n::tr!("pre_emphasis_note"));
+        }
+        response.on_hover_text(hover);
+    }
+
+    paint_disc_files(ui, source_idx, state, actions);
+}
+
+/// Paint the folder of a data disc that is browsed: its sub folders and
+/// files.  Clicking an audio file plays the audio files of the folder from
+/// there.
+fn paint_disc_files(
+    ui: &mut egui::Ui,
+    source_idx: usize,
+    state: &CdSourceState,
+    actions: &mut Vec<UiAction>,
+) {
+    let fill = semi_transparent_fill(ui);
+    let row_size = egui::vec2(ui.available_width(), 48.0);
+    if let Some(folder) = &state.files {
+        ui.add_space(12.0);
+        ui.horizontal(|ui| {
+            if folder.path.len() > 1
+                && ui
+                    .add(egui::Button::new(
+                        egui::RichText::new(egui_i18n::tr!("disc_folder_up_button")).size(16.0),
+                    ))
+                    .on_hover_text(egui_i18n::tr!("disc_folder_up_hover"))
+                    .clicked()
+            {
+                actions.push(UiAction::OpenDiscFolder {
+                    source_idx,
+                    path: folder.path[..folder.path.len() - 1].to_vec(),
+                });
+            }
+            ui.label(egui::RichText::new(format!("📂  {}", folder.display_path())).size(16.0));
+        });
+        if folder.entries.is_empty() {
+            ui.label(egui::RichText::new(egui_i18n::tr!("disc_folder_empty")).weak());
+        }
+        let mut audio_index = 0;
+        for file in &folder.entries {
+            ui.add_space(2.0);
+            if file.entry.is_dir {
+                let label = format!("  📁   {}", file.entry.name);
+                if ui
+                    .add(
+                        egui::Button::new(egui::RichText::new(&label).size(16.0))
+                            .fill(fill)
+                            .min_size(row_size),
+                    )
+                    .clicked()
+                {
+                    let mut path = folder.path.clone();
+                    path.push(file.entry.clone());
+                    actions.push(UiAction::OpenDiscFolder { source_idx, path });
+                }
+            } else if let Some(tags) = &file.tags {
+                let mut label = format!("  🎵   {}", file.title());
+                if let Some(artist) = &tags.artist {
+                    label.push_str(&format!(" – {artist}"));
+                }
+                if !tags.duration.is_zero() {
+                    let secs = tags.duration.as_secs();
+                    label.push_str(&format!("   {}:{:02}", secs / 60, secs % 60));
+                }
+                let start = audio_index;
+                audio_index += 1;
+                if ui
+                    .add(
+                        egui::Button::new(egui::RichText::new(&label).size(16.0))
+                            .fill(fill)
+                            .min_size(row_size),
+                    )
+                    .on_hover_text(egui_i18n::tr!("play_from_file_hover", {
+                        name: file.entry.name
+                    }))
+                    .clicked()
+                {
+                    actions.push(UiAction::PlayDiscFiles { source_idx, start });
+                }
+            } else {
+                ui.add(
+                    egui::Button::new(
+                        egui::RichText::new(format!("  📄   {}", file.entry.name))
+                            .weak()
+                            .size(16.0),
+                    )
+                    .fill(fill)
+                    .min_size(row_size),
+                );
+            }
+        }
+    }
+    if !state.files_status.is_empty() {
+        ui.add_space(8.0);
+        ui.label(egui::RichText::new(&state.files_status).weak().size(14.0));
+    }
+}
+
+/// Paint the choices for ripping the disc.  Returns whether the panel
+/// should close.
+fn paint_rip_options(
+    ui: &mut egui::Ui,
+    source_idx: usize,
+    state: &CdSourceState,
+    options: &mut RipOptions,
+    actions: &mut Vec<UiAction>,
+) -> bool {
+    let mut close = false;
+    let fill = semi_transparent_fill(ui);
+    egui::Frame::new()
+        .fill(fill)
+        .inner_margin(8.0)
+        .corner_radius(4.0)
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(egui_i18n::tr!("rip_target"));
+                let selected = state
+                    .rip_targets
+                    .iter()
+                    .find(|(idx, _)| *idx == options.target_idx)
+                    .map(|(_, name)| name.clone())
+                    .unwrap_or_default();
+                egui::ComboBox::from_id_salt(("cd_rip_target", source_idx))
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for (idx, name) in &state.rip_targets {
+                            ui.selectable_value(&mut options.target_idx, *idx, name);
+                        }
+                    });
+                ui.add_space(16.0);
+                ui.selectable_value(&mut options.format, RipFormat::Flac, "FLAC");
+                ui.selectable_value(&mut options.format, RipFormat::Wav, "WAV");
+                ui.add_space(16.0);
+                ui.checkbox(&mut options.verify, egui_i18n::tr!("rip_verify"))
+                    .on_hover_text(egui_i18n::tr!("rip_verify_hover"));
+            });
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                if ui.button(egui_i18n::tr!("rip_start_button")).clicked() {
+                    actions.push(UiAction::RipCd {
+                        source_idx,
+                        target_idx: options.target_idx,
+                        format: options.format,
+                        verify: options.verify,
+                    });
+                    close = true;
+                }
+                if ui.button(egui_i18n::tr!("cancel_button")).clicked() {
+                    close = true;
+                }
+            });
+        });
+    ui.add_space(4.0);
+    close
+}
+
+/// Paint the progress of a rip, and the outcome of every track once it is
+/// done.
+fn paint_rip_status(
+    ui: &mut egui::Ui,
+    source_idx: usize,
+    state: &CdSourceState,
+    rip: &RipStatus,
+    actions: &mut Vec<UiAction>,
+) {
+    let fill = semi_transparent_fill(ui);
+    egui::Frame::new()
+        .fill(fill)
+        .inner_margin(8.0)
+        .corner_radius(4.0)
+        .show(ui, |ui| {
+            if !rip.finished {
+                let text = egui_i18n::tr!("rip_progress", {
+                    track: rip.index + 1,
+                    total: rip.total
+                });
+                ui.add(egui::ProgressBar::new(rip.done).text(text));
+            } else {
+                ui.horizontal(|ui| {
+                    let summary = if let Some(error) = &rip.error {
+                        egui_i18n::tr!("rip_failed", { error: error })
+                    } else if rip.cancelled {
+                        egui_i18n::tr!("rip_cancelled")
+                    } else {
+                        let target = state
+                            .rip_targets
+                            .iter()
+                            .find(|(idx, _)| *idx == rip.target_idx)
+                            .map(|(_, name)| name.as_str())
+                            .unwrap_or_default();
+                        egui_i18n::tr!("rip_done", { source: target })
+                    };
+                    ui.label(egui::RichText::new(summary).strong());
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button(egui_i18n::tr!("rip_dismiss_button")).clicked() {
+                            actions.push(UiAction::DismissRip { source_idx });
+                        }
+                    });
+                });
+            }
+            for (number, outcome) in &rip.results {
+                let (icon, text) = match outcome {
+                    TrackOutcome::Ripped { crc } => ("✔", format!("CRC {crc:08X}")),
+                    TrackOutcome::Verified { crc } => (
+                        "✔",
+                        egui_i18n::tr!("rip_track_verified", { crc: format!("{crc:08X}") }),
+                    ),
+                    TrackOutcome::Mismatch { crc, reread_crc } => (
+                        "⚠",
+                        egui_i18n::tr!("rip_track_mismatch", {
+                            crc: format!("{crc:08X}"),
+                            reread: format!("{reread_crc:08X}")
+                        }),
+                    ),
+                    TrackOutcome::Failed(error) => ("✖", error.clone()),
+                };
+                ui.label(format!("{icon}  Track {number:02}   {text}"));
+            }
+        });
+    ui.add_space(4.0);
+}
+
+/// Paint the editor for the names and cover of a disc, with the on-screen
+/// keyboard below the fields.
+fn paint_cd_editor(
+    ui: &mut egui::Ui,
+    editor: &mut CdEditorState,
+    cover_textures: &HashMap<String, egui::TextureHandle>,
+    actions: &mut Vec<UiAction>,
+) {
+    let action_button_size = egui::vec2(120.0, 48.0);
+    ui.horizontal(|ui| {
+        ui.label(egui::RichText::new(egui_i18n::tr!("cd_editor_heading")).size(16.0));
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            if ui
+                .add_sized(
+                    action_button_size,
+                    egui::Button::new(
+                        egui::RichText::new(egui_i18n::tr!("cancel_button")).size(16.0),
+                    ),
+                )
+                .clicked()
+            {
+                actions.push(UiAction::CloseCdEditor);
+            }
+            if ui
+                .add_sized(
+                    action_button_size,
+                    egui::Button::new(
+                        egui::RichText::new(egui_i18n::tr!("save_button")).size(16.0),
+                    ),
+                )
+                .clicked()
+            {
+                let mut metadata = editor.metadata.clone();
+                metadata.tracks.retain(|_, title| !title.trim().is_empty());
+                actions.push(UiAction::SaveCdMetadata {
+                    source_idx: editor.source_idx,
+                    disc_id: editor.disc_id.clone(),
+                    metadata,
+                });
+            }
+        });
+    });
+    ui.add_space(4.0);
+
+    if editor.picking_cover {
+        paint_cover_picker(ui, editor, cover_textures);
+        return;
+    }
+
+    // Leave room for the keyboard below the fields.
+    let keyboard_height = keyboard_height(ui) + 8.0;
+    let fill = semi_transparent_fill(ui);
+    egui::ScrollArea::vertical()
+        .max_height((ui.available_height() - keyboard_height).max(96.0))
+        .auto_shrink([false, true])
+        .show(ui, |ui| {
+            egui::Grid::new("cd_editor_fields")
+                .num_columns(2)
+                .spacing([8.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label(egui_i18n::tr!("cd_editor_cover"));
+                    ui.horizontal(|ui| {
+                        if let Some(texture) = cover_textures.get(&editor.metadata.cover) {
+                            ui.add(
+                                egui::Image::new(texture).fit_to_exact_size(egui::vec2(40.0, 40.0)),
+                            );
+                        }
+                        if ui
+                            .add(egui::Button::new(egui_i18n::tr!("cd_editor_pick_cover")))
+                            .clicked()
+                        {
+                            editor.picking_cover = true;
+                        }
+                    });
+                    ui.end_row();
+
+                    let mut fields = vec![
+                        (egui_i18n::tr!("cd_editor_artist"), EditorField::Artist),
+                        (egui_i18n::tr!("cd_editor_album"), EditorField::Album),
+                    ];
+                    fields.extend(editor.track_numbers.iter().map(|&number| {
+                        (
+                            egui_i18n::tr!("cd_editor_track", { number: number }),
+                            EditorField::Track(number),
+                        )
+                    }));
+                    for (label, field) in fields {
+                        ui.label(label);
+                        let value = match field {
+                            EditorField::Artist => &editor.metadata.artist,
+                            EditorField::Album => &editor.metadata.album,
+                            EditorField::Track(number) => editor
+                                .metadata
+                                .tracks
+                                .get(&number)
+                                .map(String::as_str)
+                                .unwrap_or_default(),
+                        };
+                        let selected = editor.field == field;
+                        // Show where the keyboard types.
+                        let text = if selected {
+                            format!("{value}|")
+                        } else {
+                            value.to_string()
+                        };
+                        let button = egui::Button::selectable(selected, text)
+                            .fill(fill)
+                            .min_size(egui::vec2(ui.available_width(), 36.0));
+                        if ui.add(button).clicked() {
+                            editor.field = field;
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+
+    ui.add_space(8.0);
+    let mut shift = editor.shift;
+    paint_keyboard(ui, editor.text_mut(), &mut shift);
+    editor.shift = shift;
+}
+
+/// Paint a grid of library covers; a tap picks one for the disc.
+fn paint_cover_picker(
+    ui: &mut egui::Ui,
+    editor: &mut CdEditorState,
+    cover_textures: &HashMap<String, egui::TextureHandle>,
+) {
+    let mut picked = None;
+    ui.horizontal(|ui| {
+        if ui.button(egui_i18n::tr!("cd_editor_no_cover")).clicked() {
+            picked = Some(String::new());
+        }
+        if ui.button(egui_i18n::tr!("cd_editor_back")).clicked() {
+            editor.picking_cover = false;
+        }
+    });
+    ui.add_space(4.0);
+    let choices = editor.cover_choices();
+    if choices.is_empty() {
+        ui.label(egui::RichText::new(egui_i18n::tr!("cd_editor_no_covers")).weak());
+    }
+    let tile = 96.0;
+    egui::ScrollArea::vertical()
+        .auto_shrink([false, false])
+        .show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for item in choices {
+                    let Some(texture) = cover_textures.get(&item.cover) else {
+                        continue;
+                    };
+                    let image = egui::Image::new(texture).fit_to_exact_size(egui::vec2(tile, tile));
+                    if ui
+                        .add(egui::Button::image(image))
+                        .on_hover_text(format!("{} – {}", item.artist_name, item.album_name))
+                        .clicked()
+                    {
+                        picked = Some(item.cover.clone());
+                    }
+                }
+            });
+        });
+    if let Some(cover) = picked {
+        editor.metadata.cover = cover;
+        editor.picking_cover = false;
     }
 }
@@ -0,0 +1,85 @@
+use eframe::egui;
+
+/// Letter rows of the keyboard.  The umlauts and ß are on the keyboard
+/// because many titles in a German household need them.
+const ROWS: [&str; 4] = ["1234567890ß", "qwertyuiopü", "asdfghjklöä", "zxcvbnm-'&."];
+
+const KEY_HEIGHT: f32 = 44.0;
+
+/// Height of the keyboard: the letter rows and the row with the space bar.
+pub fn keyboard_height(ui: &egui::Ui) -> f32 {
+    (ROWS.len() + 1) as f32 * (KEY_HEIGHT + ui.spacing().item_spacing.y)
+}
+
+/// On-screen keyboard for typing on the touchscreen.  Types into `text`;
+/// `shift` capitalises the next letter and is released after it.
+pub fn paint_keyboard(ui: &mut egui::Ui, text: &mut String, shift: &mut bool) {
+    let spacing = ui.spacing().item_spacing.x;
+    let columns = ROWS
+        .iter()
+        .map(|row| row.chars().count())
+        .max()
+        .unwrap_or(1) as f32;
+    let key_width = ((ui.available_width() - spacing * (columns - 1.0)) / columns).max(24.0);
+    let key_size = egui::vec2(key_width, KEY_HEIGHT);
+
+    for row in ROWS {
+        ui.horizontal(|ui| {
+            for key in row.chars() {
+                let label = if *shift && key != 'ß' {
+                    key.to_uppercase().collect()
+                } else {
+                    key.to_string()
+                };
+                if ui
+                    .add_sized(
+                        key_size,
+                        egui::Button::new(egui::RichText::new(&label).size(18.0)),
+                    )
+                    .clicked()
+                {
+                    text.push_str(&label);
+                    *shift = false;
+                }
+            }
+        });
+    }
+
+    ui.horizontal(|ui| {
+        let shift_button = egui::Button::selectable(*shift, egui::RichText::new("⇧").size(18.0))
+            .min_size(egui::vec2(key_width * 1.5, KEY_HEIGHT));
+        if ui.add(shift_button).clicked() {
+            *shift = !*shift;
+        }
+        let space_width = key_width * 7.0 + spacing * 6.0;
+        if ui
+            .add_sized(
+                egui::vec2(space_width, KEY_HEIGHT),
+                egui::Button::new(egui::RichText::new(egui_i18n::tr!("keyboard_space")).size(16.0)),
+            )
+            .clicked()
+        {
+            text.push(' ');
+        }
+        if ui
+            .add_sized(
+                egui::vec2(key_width * 1.5, KEY_HEIGHT),
+                egui::Button::new(egui::RichText::new("⌫").size(18.0)),
+            )
+            .on_hover_text(egui_i18n::tr!("keyboard_backspace_hover"))
+            .clicked()
+        {
+            text.pop();
+        }
+        if ui
+            .add_sized(
+                egui::vec2(key_width * 1.5, KEY_HEIGHT),
+                egui::Button::new(egui::RichText::new("🗑").size(18.0)),
+            )
+            .on_hover_text(egui_i18n::tr!("keyboard_clear_hover"))
+            .clicked()
+        {
+            text.clear();
+        }
+    });
+}
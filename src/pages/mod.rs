@@ -1,16 +1,18 @@
 pub mod bluetooth_settings;
 pub mod cd_source;
 pub mod file_source;
+pub mod keyboard;
 pub mod kids_file_source;
 pub mod now_playing;
 pub mod playlist;
 pub mod settings;
 pub mod stream_source;
 
+use crate::UiAction;
 use crate::config::ConfigSourceType;
 use eframe::egui;
 
-pub use cd_source::{CdSourceState, paint_cd_source};
+pub use cd_source::{CdEditorState, CdSourceState, paint_cd_source};
 pub use file_source::{FileRenderData, paint_file_source};
 pub use kids_file_source::{KidsFileRenderData, paint_kids_file_source};
 pub use now_playing::paint_now_playing;
@@ -41,3 +43,27 @@ pub fn semi_transparent_group_frame(ui: &egui::Ui) -> egui::Frame {
     let transparent_fill = egui::Color32::from_rgba_unmultiplied(fill.r(), fill.g(), fill.b(), 160);
     egui::Frame::group(ui.style()).fill(transparent_fill)
 }
+
+/// Row of buttons to choose the zone the player controls apply to.  Nothing
+/// is shown if there is only one zone.
+pub fn paint_zone_selector(
+    ui: &mut egui::Ui,
+    zones: &[String],
+    selected: usize,
+    actions: &mut Vec<UiAction>,
+) {
+    if zones.len() < 2 {
+        return;
+    }
+    ui.horizontal(|ui| {
+        ui.label(egui::RichText::new("🔈").size(18.0));
+        for (idx, name) in zones.iter().enumerate() {
+            let text = egui::RichText::new(name).size(16.0);
+            let button =
+                egui::Button::selectable(idx == selected, text).min_size(egui::vec2(80.0, 36.0));
+            if ui.add(button).clicked() && idx != selected {
+                actions.push(UiAction::SelectZone(idx));
+            }
+        }
+    });
+}
@@ -1,19 +1,38 @@
 use eframe::egui;
 use egui::Color32;
-use rodio_player::TitleChanged;
+use rodio_player::{AbLoop, TitleChanged};
 
+use crate::UiAction;
+use crate::music_store::LibraryMatch;
 use crate::pages::semi_transparent_fill;
+use crate::playback_speed::SPEEDS;
 
 pub fn paint_now_playing(
     ui: &mut egui::Ui,
     current_title: &TitleChanged,
     cover_texture: Option<&egui::TextureHandle>,
+    library_match: Option<&LibraryMatch>,
+    speed: Option<f32>,
+    ab_loop: Option<AbLoop>,
+    actions: &mut Vec<UiAction>,
 ) {
     let art_size = egui::vec2(150.0, 150.0);
+    let library_row_height = 64.0;
+    let speed_row_height = 48.0;
 
     // Estimate the height of the content block so we can vertically center it.
-    // The content is a horizontal row whose tallest element is the album art (150px).
-    let content_height = art_size.y;
+    // The content is a horizontal row whose tallest element is the album art (150px),
+    // followed by the library actions when the streamed title is in the library.
+    let mut content_height = art_size.y;
+    if library_match.is_some() {
+        content_height += library_row_height;
+    }
+    if speed.is_some() {
+        content_height += speed_row_height;
+    }
+    if ab_loop.is_some() {
+        content_height += speed_row_height;
+    }
 
     let available_height = ui.available_height();
     let top_padding = ((available_height - content_height) / 2.0).max(0.0);
@@ -113,4 +132,106 @@ pub fn paint_now_playing(
             }
         });
     });
+    if let Some(library_match) = library_match {
+        ui.add_space(16.0);
+        ui.horizontal(|ui| {
+            let available_width = ui.available_width();
+            ui.add_space(available_width / 10.0);
+            let button_size = egui::vec2(220.0, 48.0);
+            if ui
+                .add_sized(
+                    button_size,
+                    egui::Button::new(
+                        egui::RichText::new(egui_i18n::tr!("play_from_library_button")).size(16.0),
+                    ),
+                )
+                .on_hover_text(library_match.title.path.as_str())
+                .clicked()
+            {
+                actions.push(UiAction::PlayTitles {
+                    titles: vec![library_match.title.clone()],
+                    start_index: 0,
+                });
+            }
+            if ui
+                .add_sized(
+                    button_size,
+                    egui::Button::new(
+                        egui::RichText::new(egui_i18n::tr!("go_to_artist_button")).size(16.0),
+                    ),
+                )
+                .clicked()
+            {
+                actions.push(UiAction::GoToArtist {
+                    source_id: library_match.source_id,
+                    artist_id: library_match.artist_id,
+                    artist_name: library_match.title.artist.clone(),
+                });
+            }
+        });
+    }
+    if let Some(speed) = speed {
+        ui.add_space(16.0);
+        ui.horizontal(|ui| {
+            let available_width = ui.available_width();
+            ui.add_space(available_width / 10.0);
+            ui.label(egui::RichText::new(egui_i18n::tr!("playback_speed")).size(16.0))
+                .on_hover_text(egui_i18n::tr!("playback_speed_hover"));
+            for option in SPEEDS {
+                let label = egui::RichText::new(format!("{option}×")).size(16.0);
+                if ui
+                    .add_sized(
+                        egui::vec2(56.0, 40.0),
+                        egui::Button::selectable(speed == option, label),
+                    )
+                    .clicked()
+                    && speed != option
+                {
+                    actions.push(UiAction::SetSpeed(option));
+                }
+            }
+        });
+    }
+    if let Some(ab_loop) = ab_loop {
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            let available_width = ui.available_width();
+            ui.add_space(available_width / 10.0);
+            let (label, hover) = match ab_loop {
+                AbLoop::Off => ("🔁 A", egui_i18n::tr!("ab_loop_set_a_hover")),
+                AbLoop::StartSet => ("🔁 A–B", egui_i18n::tr!("ab_loop_set_b_hover")),
+                AbLoop::Looping => ("🔁 A–B", egui_i18n::tr!("ab_loop_end_hover")),
+            };
+            if ui
+                .add_sized(
+                    egui::vec2(120.0, 40.0),
+                    egui::Button::selectable(
+                        ab_loop == AbLoop::Looping,
+                        egui::RichText::new(label).size(16.0),
+                    ),
+                )
+                .on_hover_text(hover)
+                .clicked()
+            {
+                actions.push(UiAction::AbLoopMark);
+            }
+            match ab_loop {
+                AbLoop::Off => {}
+                AbLoop::StartSet => {
+                    ui.label(egui::RichText::new(egui_i18n::tr!("ab_loop_waiting_for_b")).weak());
+                }
+                AbLoop::Looping => {
+                    ui.label(egui::RichText::new(egui_i18n::tr!("ab_loop_repeating")).weak());
+                }
+            }
+            if ab_loop != AbLoop::Off
+                && ui
+                    .add_sized(egui::vec2(40.0, 40.0), egui::Button::new("✕"))
+                    .on_hover_text(egui_i18n::tr!("ab_loop_clear_hover"))
+                    .clicked()
+            {
+                actions.push(UiAction::AbLoopClear);
+            }
+        });
+    }
 }
@@ -1,5 +1,5 @@
 use eframe::egui;
-use rodio_player::SoundItem;
+use rodio_player::{QueueEntry, QueueItem};
 
 use crate::UiAction;
 use crate::pages::semi_transparent_fill;
@@ -11,7 +11,7 @@ use crate::pages::semi_transparent_fill;
 /// the currently-playing item sits at `current_index.saturating_sub(1)`.
 pub fn paint_playlist(
     ui: &mut egui::Ui,
-    queue: &[SoundItem],
+    queue: &[QueueEntry],
     current_index: usize,
     actions: &mut Vec<UiAction>,
 ) {
@@ -57,31 +57,35 @@ pub fn paint_playlist(
 
     let fill = semi_transparent_fill(ui);
 
-    for (i, item) in queue.iter().enumerate() {
+    for (i, entry) in queue.iter().enumerate() {
+        let item = &entry.item;
         let is_playing = i == playing_idx && current_index > 0;
         let is_past = current_index > 0 && i < playing_idx;
 
         // Build the label text
         let track_icon = if is_playing {
             "▶"
+        } else if entry.error.is_some() {
+            "⚠"
         } else if is_past {
             "✓"
         } else {
-            "🎵"
+            kind_icon(item)
         };
 
-        let title_part = if item.title.is_empty() {
+        let title_part = if item.title().is_empty() {
             egui_i18n::tr!("no_track_selected")
         } else {
-            item.title.clone()
+            item.title().to_string()
         };
 
-        let meta_part = if !item.artist.is_empty() && !item.album.is_empty() {
-            format!("{}  —  {}", item.artist, item.album)
-        } else if !item.artist.is_empty() {
-            item.artist.clone()
-        } else if !item.album.is_empty() {
-            item.album.clone()
+        let (artist, album) = (item.artist(), item.album());
+        let meta_part = if !artist.is_empty() && !album.is_empty() {
+            format!("{}  —  {}", artist, album)
+        } else if !artist.is_empty() {
+            artist.to_string()
+        } else if !album.is_empty() {
+            album.to_string()
         } else {
             String::new()
         };
@@ -127,6 +131,10 @@ pub fn paint_playlist(
         };
         let label = if is_playing {
             egui::RichText::new(label_text).strong().size(15.0)
+        } else if entry.error.is_some() {
+            egui::RichText::new(label_text)
+                .color(ui.visuals().warn_fg_color)
+                .size(15.0)
         } else if is_past {
             egui::RichText::new(label_text).weak().size(15.0)
         } else {
@@ -134,7 +142,7 @@ pub fn paint_playlist(
         };
 
         // Main area: plain Button exactly like row_with_add_button in file_source.
-        let main_clicked = ui
+        let mut main_response = ui
             .new_child(
                 egui::UiBuilder::new()
                     .max_rect(main_rect)
@@ -145,10 +153,14 @@ pub fn paint_playlist(
                     .fill(row_fill)
                     .frame(true)
                     .min_size(main_rect.size()),
-            )
-            .clicked();
+            );
+        // Tell why a broken entry was skipped.
+        if let Some(ref error) = entry.error {
+            main_response = main_response
+                .on_hover_text(egui_i18n::tr!("playlist_failed_hover", {reason: error}));
+        }
 
-        if main_clicked {
+        if main_response.clicked() {
             actions.push(UiAction::PlaylistPlayFrom { index: i });
         }
 
@@ -166,3 +178,13 @@ pub fn paint_playlist(
         }
     }
 }
+
+/// Icon for the kind of a queue item that is not playing yet.
+fn kind_icon(item: &QueueItem) -> &'static str {
+    match item {
+        QueueItem::File(_) => "🎵",
+        QueueItem::HttpFile(_) => "🌐",
+        QueueItem::Station(_) => "📻",
+        QueueItem::CdTrack(_) | QueueItem::DiscFile(_) => "💿",
+    }
+}
@@ -1,9 +1,12 @@
 use eframe::egui;
-use rodio_player::list_output_devices;
+use rodio_player::{OutputStatus, list_output_devices};
 
 use crate::UiAction;
 use crate::bluetooth::BluetoothManager;
-use crate::config::{Config, ConfigSourceType, Source, Station};
+use crate::config::{
+    AudioBackend, Config, ConfigSourceType, DEFAULT_STREAM_LOUDNESS_LUFS, HardwareMixerConfig,
+    NightModeConfig, OutputDeviceConfig, Source, Station, StationMetadataConfig,
+};
 use crate::pages::bluetooth_settings::{BluetoothSettingsState, paint_bluetooth_settings};
 use crate::pages::{semi_transparent_group_frame, source_type_icon};
 
@@ -33,6 +36,8 @@ pub struct SettingsState {
     pub confirm_remove_station: Option<(usize, usize)>,
     /// Cached list of available audio output device names.
     pub available_devices: Vec<String>,
+    /// Live state of the audio output, updated by the player.
+    pub output_status: OutputStatus,
     /// State for the Bluetooth pairing section.
     pub bluetooth: BluetoothSettingsState,
 }
@@ -55,6 +60,7 @@ impl SettingsState {
             confirm_remove_source: None,
             confirm_remove_station: None,
             available_devices: list_output_devices(),
+            output_status: OutputStatus::NoOutput,
             bluetooth: BluetoothSettingsState::default(),
         }
     }
@@ -90,6 +96,87 @@ fn source_type_label(source_type: &ConfigSourceType) -> String {
     }
 }
 
+/// Checkbox list of the output devices to play on, with volume trim and
+/// delay for each selected device.
+fn paint_output_devices(ui: &mut egui::Ui, state: &mut SettingsState) {
+    let audio = &mut state.config.audio;
+    // Configured devices that are unplugged right now are listed too, so they
+    // can be deselected.
+    let mut names = state.available_devices.clone();
+    let configured = audio.devices.iter().map(|device| &device.name);
+    for name in configured.chain(audio.device.as_ref()) {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    let mut changed = false;
+    for name in &names {
+        let selected_idx = audio.devices.iter().position(|device| &device.name == name);
+        // A single `device` from before devices could be combined.
+        let legacy_selected =
+            audio.devices.is_empty() && audio.device.as_deref().unwrap_or("Default") == name;
+        let mut selected = selected_idx.is_some() || legacy_selected;
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut selected, name).changed() {
+                if audio.devices.is_empty() {
+                    audio.devices.push(OutputDeviceConfig {
+                        name: audio.device.take().unwrap_or_else(|| "Default".to_string()),
+                        trim_db: 0.0,
+                        delay_ms: 0,
+                    });
+                }
+                if selected {
+                    audio.devices.push(OutputDeviceConfig {
+                        name: name.clone(),
+                        trim_db: 0.0,
+                        delay_ms: 0,
+                    });
+                } else {
+                    audio.devices.retain(|device| &device.name != name);
+                }
+                changed = true;
+                return;
+            }
+            let Some(device) = selected_idx.map(|idx| &mut audio.devices[idx]) else {
+                return;
+            };
+            ui.label(egui_i18n::tr!("settings_device_trim"));
+            changed |= ui
+                .add(egui::Slider::new(&mut device.trim_db, -24.0..=0.0).suffix(" dB"))
+                .changed();
+            ui.label(egui_i18n::tr!("settings_device_delay"));
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut device.delay_ms)
+                        .range(0..=1000)
+                        .suffix(" ms"),
+                )
+                .on_hover_text(egui_i18n::tr!("settings_device_delay_hover"))
+                .changed();
+        });
+    }
+    if changed {
+        state.dirty = true;
+        state.save_message = None;
+    }
+}
+
+fn backend_label(backend: &AudioBackend) -> String {
+    match backend {
+        AudioBackend::Device => egui_i18n::tr!("audio_backend_device"),
+        AudioBackend::Pipe => egui_i18n::tr!("audio_backend_pipe"),
+        AudioBackend::WavFile => egui_i18n::tr!("audio_backend_wav"),
+        AudioBackend::Null => egui_i18n::tr!("audio_backend_null"),
+    }
+}
+
+const ALL_AUDIO_BACKENDS: [AudioBackend; 4] = [
+    AudioBackend::Device,
+    AudioBackend::Pipe,
+    AudioBackend::WavFile,
+    AudioBackend::Null,
+];
+
 const ALL_SOURCE_TYPES: [ConfigSourceType; 4] = [
     ConfigSourceType::File,
     ConfigSourceType::Stream,
@@ -188,30 +275,221 @@ pub fn paint_settings(
         });
         ui.add_space(4.0);
         ui.horizontal(|ui| {
-            ui.label(egui_i18n::tr!("settings_output_device"));
-            let current = state.config.audio.device.as_deref().unwrap_or("Default");
-            egui::ComboBox::from_id_salt("audio_device")
-                .selected_text(current)
+            ui.label(egui_i18n::tr!("settings_output_backend"));
+            egui::ComboBox::from_id_salt("audio_backend")
+                .selected_text(backend_label(&state.config.audio.backend))
                 .show_ui(ui, |ui| {
-                    for name in &state.available_devices {
-                        let is_default = name == "Default";
-                        let new_value: Option<String> =
-                            if is_default { None } else { Some(name.clone()) };
+                    for backend in ALL_AUDIO_BACKENDS {
                         if ui
                             .selectable_label(
-                                state.config.audio.device.as_deref().unwrap_or("Default") == name,
-                                name,
+                                state.config.audio.backend == backend,
+                                backend_label(&backend),
                             )
                             .clicked()
-                            && state.config.audio.device != new_value
+                            && state.config.audio.backend != backend
                         {
-                            state.config.audio.device = new_value;
+                            state.config.audio.backend = backend;
                             state.dirty = true;
                             state.save_message = None;
                         }
                     }
                 });
         });
+        match state.config.audio.backend {
+            AudioBackend::Device => {
+                ui.add_space(4.0);
+                ui.label(egui_i18n::tr!("settings_output_devices"));
+                paint_output_devices(ui, state);
+            }
+            AudioBackend::Pipe | AudioBackend::WavFile => {
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    ui.label(egui_i18n::tr!("settings_output_path"));
+                    if ui
+                        .add(
+                            egui::TextEdit::singleline(&mut state.config.audio.output_path)
+                                .desired_width(300.0),
+                        )
+                        .on_hover_text(egui_i18n::tr!("settings_output_path_hover"))
+                        .changed()
+                    {
+                        state.dirty = true;
+                        state.save_message = None;
+                    }
+                });
+            }
+            AudioBackend::Null => {}
+        }
+        ui.add_space(4.0);
+        ui.horizontal(|ui| {
+            ui.label(egui_i18n::tr!("settings_output_status"));
+            let (text, color) = match &state.output_status {
+                OutputStatus::Connected(name) => (
+                    egui_i18n::tr!("output_status_connected", {device: name}),
+                    egui::Color32::from_rgb(80, 180, 80),
+                ),
+                OutputStatus::Fallback(name) => (
+                    egui_i18n::tr!("output_status_fallback", {device: name}),
+                    ui.visuals().warn_fg_color,
+                ),
+                OutputStatus::Lost(name) => (
+                    egui_i18n::tr!("output_status_lost", {device: name}),
+                    ui.visuals().error_fg_color,
+                ),
+                OutputStatus::Partial { playing, missing } => (
+                    egui_i18n::tr!("output_status_partial", {devices: playing, missing: missing}),
+                    ui.visuals().warn_fg_color,
+                ),
+                OutputStatus::NoOutput => (
+                    egui_i18n::tr!("output_status_none"),
+                    ui.visuals().error_fg_color,
+                ),
+            };
+            ui.label(egui::RichText::new(text).color(color));
+        });
+        ui.add_space(4.0);
+        let mut use_hardware_mixer = state.config.audio.hardware_mixer.is_some();
+        if ui
+            .checkbox(
+                &mut use_hardware_mixer,
+                egui_i18n::tr!("settings_hardware_mixer"),
+            )
+            .on_hover_text(egui_i18n::tr!("settings_hardware_mixer_hover"))
+            .changed()
+        {
+            state.config.audio.hardware_mixer =
+                use_hardware_mixer.then(HardwareMixerConfig::default);
+            state.dirty = true;
+            state.save_message = None;
+        }
+        if let Some(mixer) = &mut state.config.audio.hardware_mixer {
+            ui.horizontal(|ui| {
+                ui.label(egui_i18n::tr!("settings_mixer_card"));
+                let card_changed = ui
+                    .add(egui::TextEdit::singleline(&mut mixer.card).desired_width(120.0))
+                    .changed();
+                ui.label(egui_i18n::tr!("settings_mixer_control"));
+                let control_changed = ui
+                    .add(egui::TextEdit::singleline(&mut mixer.control).desired_width(120.0))
+                    .changed();
+                if card_changed || control_changed {
+                    state.dirty = true;
+                    state.save_message = None;
+                }
+            });
+        }
+        ui.add_space(4.0);
+        ui.horizontal(|ui| {
+            ui.label(egui_i18n::tr!("settings_duck_level"));
+            if ui
+                .add(egui::Slider::new(&mut state.config.audio.duck_db, -40.0..=0.0).suffix(" dB"))
+                .on_hover_text(egui_i18n::tr!("settings_duck_level_hover"))
+                .changed()
+            {
+                state.dirty = true;
+                state.save_message = None;
+            }
+            if ui
+                .button(egui_i18n::tr!("settings_test_announcement"))
+                .clicked()
+            {
+                actions.push(UiAction::TestAnnouncement);
+            }
+        });
+        ui.add_space(4.0);
+        ui.horizontal(|ui| {
+            let mut normalize = state.config.audio.stream_loudness_lufs.is_some();
+            if ui
+                .checkbox(&mut normalize, egui_i18n::tr!("settings_stream_loudness"))
+                .on_hover_text(egui_i18n::tr!("settings_stream_loudness_hover"))
+                .changed()
+            {
+                state.config.audio.stream_loudness_lufs =
+                    normalize.then_some(DEFAULT_STREAM_LOUDNESS_LUFS);
+                state.dirty = true;
+                state.save_message = None;
+            }
+            if let Some(target) = &mut state.config.audio.stream_loudness_lufs
+                && ui
+                    .add(egui::Slider::new(target, -30.0..=-10.0).suffix(" LUFS"))
+                    .changed()
+            {
+                state.dirty = true;
+                state.save_message = None;
+            }
+        });
+        ui.add_space(4.0);
+        if ui
+            .checkbox(
+                &mut state.config.audio.trim_silence,
+                egui_i18n::tr!("settings_trim_silence"),
+            )
+            .on_hover_text(egui_i18n::tr!("settings_trim_silence_hover"))
+            .changed()
+        {
+            state.dirty = true;
+            state.save_message = None;
+        }
+        ui.horizontal(|ui| {
+            ui.label(egui_i18n::tr!("settings_silence_threshold"));
+            let threshold =
+                egui::Slider::new(&mut state.config.audio.silence_threshold_db, -90.0..=-30.0)
+                    .suffix(" dB");
+            let threshold_changed = ui.add(threshold).changed();
+            ui.label(egui_i18n::tr!("settings_dead_air"));
+            let dead_air_changed = ui
+                .add(
+                    egui::DragValue::new(&mut state.config.audio.dead_air_secs)
+                        .range(0..=600)
+                        .suffix(" s"),
+                )
+                .on_hover_text(egui_i18n::tr!("settings_dead_air_hover"))
+                .changed();
+            if threshold_changed || dead_air_changed {
+                state.dirty = true;
+                state.save_message = None;
+            }
+        });
+        ui.add_space(4.0);
+        let mut use_night_mode = state.config.audio.night_mode.is_some();
+        if ui
+            .checkbox(&mut use_night_mode, egui_i18n::tr!("settings_night_mode"))
+            .on_hover_text(egui_i18n::tr!("settings_night_mode_hover"))
+            .changed()
+        {
+            state.config.audio.night_mode = use_night_mode.then(NightModeConfig::default);
+            state.dirty = true;
+            state.save_message = None;
+        }
+        if let Some(night) = &mut state.config.audio.night_mode {
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                ui.label(egui_i18n::tr!("settings_night_start"));
+                changed |= ui
+                    .add(egui::TextEdit::singleline(&mut night.start).desired_width(60.0))
+                    .changed();
+                ui.label(egui_i18n::tr!("settings_night_end"));
+                changed |= ui
+                    .add(egui::TextEdit::singleline(&mut night.end).desired_width(60.0))
+                    .changed();
+            });
+            ui.horizontal(|ui| {
+                ui.label(egui_i18n::tr!("settings_night_max_volume"));
+                let slider = egui::Slider::new(&mut night.max_volume, 0..=100).suffix("%");
+                changed |= ui.add(slider).changed();
+            });
+            changed |= ui
+                .checkbox(
+                    &mut night.compress,
+                    egui_i18n::tr!("settings_night_compress"),
+                )
+                .on_hover_text(egui_i18n::tr!("settings_night_compress_hover"))
+                .changed();
+            if changed {
+                state.dirty = true;
+                state.save_message = None;
+            }
+        }
     });
 
     ui.add_space(8.0);
@@ -675,6 +953,31 @@ fn paint_stations(ui: &mut egui::Ui, state: &mut SettingsState, source_idx: usiz
                         state.save_message = None;
                     }
                     ui.end_row();
+
+                    ui.label(egui_i18n::tr!("station_gain_label"));
+                    if ui
+                        .add(
+                            egui::Slider::new(
+                                &mut state.config.sources[source_idx].stations[j].gain_db,
+                                -12.0..=12.0,
+                            )
+                            .suffix(" dB"),
+                        )
+                        .on_hover_text(egui_i18n::tr!("station_gain_hover"))
+                        .changed()
+                    {
+                        state.dirty = true;
+                        state.save_message = None;
+                    }
+                    ui.end_row();
+
+                    if paint_station_metadata(
+                        ui,
+                        &mut state.config.sources[source_idx].stations[j].metadata,
+                    ) {
+                        state.dirty = true;
+                        state.save_message = None;
+                    }
                 });
         });
     }
@@ -734,6 +1037,8 @@ fn paint_stations(ui: &mut egui::Ui, state: &mut SettingsState, source_idx: usiz
                         name: state.new_station_name.trim().to_string(),
                         url: state.new_station_url.trim().to_string(),
                         icon: state.new_station_icon.trim().to_string(),
+                        metadata: None,
+                        gain_db: 0.0,
                     });
                     state.adding_station_for = None;
                     state.dirty = true;
@@ -749,3 +1054,49 @@ fn paint_stations(ui: &mut egui::Ui, state: &mut SettingsState, source_idx: usiz
         });
     }
 }
+
+/// Paint the grid rows for a station's "now playing" endpoint.  The field
+/// mapping rows are only shown once a metadata URL is entered.  Returns `true`
+/// if anything was changed.
+fn paint_station_metadata(ui: &mut egui::Ui, metadata: &mut Option<StationMetadataConfig>) -> bool {
+    let mut changed = false;
+
+    ui.label(egui_i18n::tr!("station_metadata_url_label"));
+    let mut url = metadata.as_ref().map(|m| m.url.clone()).unwrap_or_default();
+    if ui
+        .add(egui::TextEdit::singleline(&mut url).desired_width(ui.available_width() - 8.0))
+        .on_hover_text(egui_i18n::tr!("station_metadata_url_hover"))
+        .changed()
+    {
+        if url.trim().is_empty() {
+            *metadata = None;
+        } else {
+            metadata
+                .get_or_insert_with(StationMetadataConfig::default)
+                .url = url;
+        }
+        changed = true;
+    }
+    ui.end_row();
+
+    let Some(metadata) = metadata else {
+        return changed;
+    };
+    let fields = [
+        ("station_metadata_artist_label", &mut metadata.artist_path),
+        ("station_metadata_title_label", &mut metadata.title_path),
+        ("station_metadata_cover_label", &mut metadata.cover_path),
+    ];
+    for (label, path) in fields {
+        ui.label(egui_i18n::tr!(label));
+        if ui
+            .add(egui::TextEdit::singleline(path).desired_width(ui.available_width() - 8.0))
+            .on_hover_text(egui_i18n::tr!("station_metadata_path_hover"))
+            .changed()
+        {
+            changed = true;
+        }
+        ui.end_row();
+    }
+    changed
+}
@@ -40,12 +40,30 @@ pub fn paint_stream_source(
     let fill = semi_transparent_fill(ui);
     let icon_size = 40.0;
     let row_height = 48.0;
+    let add_btn_width = 48.0;
 
     for station in &source.stations {
         ui.add_space(2.0);
 
-        let desired_size = egui::vec2(ui.available_width(), row_height);
-        let (rect, response) = ui.allocate_exact_size(desired_size, egui::Sense::click());
+        // Split the row into the station area (play) and an add-to-playlist
+        // button, like the file source rows.
+        let gap = ui.spacing().item_spacing.x;
+        let total_width = ui.available_width();
+        let (row_rect, _) =
+            ui.allocate_exact_size(egui::vec2(total_width, row_height), egui::Sense::hover());
+        let rect = egui::Rect::from_min_size(
+            row_rect.min,
+            egui::vec2((total_width - add_btn_width - gap).max(0.0), row_height),
+        );
+        let add_rect = egui::Rect::from_min_size(
+            egui::pos2(row_rect.max.x - add_btn_width, row_rect.min.y),
+            egui::vec2(add_btn_width, row_height),
+        );
+        let response = ui.interact(
+            rect,
+            ui.id().with(("station", &station.url)),
+            egui::Sense::click(),
+        );
 
         if ui.is_rect_visible(rect) {
             let painter = ui.painter();
@@ -126,12 +144,24 @@ pub fn paint_stream_source(
 
         if response.clicked() {
             actions.push(UiAction::PlayStream {
-                url: station.url.clone(),
-                icon: station.icon.clone(),
+                station: station.clone(),
             });
         }
 
         // Show URL as hover tooltip
         response.on_hover_text(&station.url);
+
+        let add_clicked = ui
+            .put(
+                add_rect,
+                egui::Button::new(egui::RichText::new("➕").size(14.0)),
+            )
+            .on_hover_text(egui_i18n::tr!("add_to_playlist_hover"))
+            .clicked();
+        if add_clicked {
+            actions.push(UiAction::AddStationToPlaylist {
+                station: station.clone(),
+            });
+        }
     }
 }
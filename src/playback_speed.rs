@@ -0,0 +1,85 @@
+// ---------------------------------------------------------------------------
+// Playback speed – remembered per album, or per source for titles without one
+// ---------------------------------------------------------------------------
+
+use rodio_player::{QueueItem, TitleChanged};
+
+use crate::config::{Config, ConfigSourceType};
+
+/// Speeds offered on the now-playing page.
+pub const SPEEDS: [f32; 6] = [0.5, 0.75, 1.0, 1.25, 1.5, 2.0];
+
+/// Keys under which the speed of the current title may be remembered, the
+/// most specific first: its album, then the source it comes from.
+pub fn speed_keys(config: &Config, item: &QueueItem, title: &TitleChanged) -> Vec<String> {
+    let mut keys = Vec::new();
+    let known = |value: &str| !value.is_empty() && value != "-";
+    if known(&title.album) {
+        keys.push(format!("album:{}\u{1f}{}", title.artist, title.album));
+    }
+    let source = config.sources.iter().find(|source| {
+        !source.path.is_empty()
+            && match (item, &source.source_type) {
+                (
+                    QueueItem::File(sound) | QueueItem::HttpFile(sound),
+                    ConfigSourceType::File | ConfigSourceType::KidsFile,
+                ) => sound.path.starts_with(&source.path),
+                (QueueItem::CdTrack(track), ConfigSourceType::CD) => track.device == source.path,
+                (QueueItem::DiscFile(file), ConfigSourceType::CD) => file.device == source.path,
+                _ => false,
+            }
+    });
+    if let Some(source) = source {
+        keys.push(format!("source:{}", source.name));
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio_player::SoundItem;
+
+    fn config() -> Config {
+        serde_json::from_str(
+            r#"{"sources": [
+                {"source_type": "Stream", "name": "Radio", "path": "", "stations": []},
+                {"source_type": "KidsFile", "name": "Hörbücher", "path": "/music/kids", "stations": []}
+            ], "audio": {"start_volume": 30}}"#,
+        )
+        .unwrap()
+    }
+
+    fn item(path: &str) -> QueueItem {
+        QueueItem::from(SoundItem {
+            artist: String::new(),
+            album: String::new(),
+            title: String::new(),
+            path: path.to_string(),
+            cover: String::new(),
+        })
+    }
+
+    #[test]
+    fn test_album_before_source() {
+        let title = TitleChanged {
+            artist: "Astrid Lindgren".to_string(),
+            album: "Pippi".to_string(),
+            ..TitleChanged::default()
+        };
+        let keys = speed_keys(&config(), &item("/music/kids/pippi/01.mp3"), &title);
+        assert_eq!(
+            keys,
+            ["album:Astrid Lindgren\u{1f}Pippi", "source:Hörbücher"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_album_and_source() {
+        let title = TitleChanged {
+            album: "-".to_string(),
+            ..TitleChanged::default()
+        };
+        assert!(speed_keys(&config(), &item("/tmp/a.mp3"), &title).is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+// ---------------------------------------------------------------------------
+// Toasts – short, non-blocking messages at the bottom of the screen
+// ---------------------------------------------------------------------------
+
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+
+/// How long a toast stays visible.
+const TOAST_DURATION: Duration = Duration::from_secs(6);
+
+/// Maximum number of toasts shown at the same time; older ones are dropped.
+const MAX_TOASTS: usize = 3;
+
+#[derive(Default)]
+pub struct Toasts {
+    entries: Vec<(String, Instant)>,
+}
+
+impl Toasts {
+    /// Queue a message to be shown for a few seconds.
+    pub fn push(&mut self, message: String) {
+        self.entries.push((message, Instant::now()));
+        if self.entries.len() > MAX_TOASTS {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Paint the current toasts on top of everything else.  They don't take
+    /// any input, so the UI below stays usable.
+    pub fn show(&mut self, ctx: &egui::Context) {
+        self.entries
+            .retain(|(_, shown_at)| shown_at.elapsed() < TOAST_DURATION);
+        let Some(oldest) = self.entries.first().map(|(_, shown_at)| *shown_at) else {
+            return;
+        };
+
+        egui::Area::new(egui::Id::new("toasts"))
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -72.0))
+            .order(egui::Order::Tooltip)
+            .interactable(false)
+            .show(ctx, |ui| {
+                for (message, _) in &self.entries {
+                    egui::Frame::popup(ui.style())
+                        .fill(egui::Color32::from_rgba_unmultiplied(40, 40, 40, 230))
+                        .show(ui, |ui| {
+                            ui.set_max_width(480.0);
+                            ui.label(
+                                egui::RichText::new(message)
+                                    .color(egui::Color32::WHITE)
+                                    .size(15.0),
+                            );
+                        });
+                    ui.add_space(4.0);
+                }
+            });
+
+        // Repaint when the oldest toast expires, even if nothing else happens.
+        ctx.request_repaint_after(TOAST_DURATION.saturating_sub(oldest.elapsed()));
+    }
+}
@@ -0,0 +1,216 @@
+// ---------------------------------------------------------------------------
+// Zones – independent players on one box, e.g. one per DAC
+// ---------------------------------------------------------------------------
+
+use std::sync::mpsc::{self, Receiver};
+
+use chrono::{Local, NaiveTime};
+use eframe::egui;
+use rodio_player::{AbLoop, OutputStatus, PlayerState, QueueEntry, RodioPlayer, TitleChanged};
+use tokio::runtime::Handle;
+use tracing::{error, info};
+
+use crate::config::{AudioConfig, Config};
+use crate::music_store::LibraryMatch;
+
+/// A player with its own output, queue and volume, and the UI state that
+/// goes with it.
+pub struct Zone {
+    /// Name from the configuration; empty for the main zone.
+    pub name: String,
+    pub audio: AudioConfig,
+    pub player: RodioPlayer,
+    pub title_rx: Receiver<TitleChanged>,
+    pub button_state_rx: Receiver<PlayerState>,
+    pub is_playing: bool,
+    pub is_paused: bool,
+    pub is_muted: bool,
+    pub current_title: TitleChanged,
+    /// Local library title matching the song currently announced by a stream.
+    pub library_match: Option<LibraryMatch>,
+    /// Lookup of `library_match` running in the background.
+    pub library_match_rx: Option<Receiver<Option<LibraryMatch>>>,
+    pub volume: f32,
+    /// Snapshot of the player queue, updated every frame.
+    pub playlist_queue: Vec<QueueEntry>,
+    /// Index of the next item the playback thread will pick up.
+    pub playlist_index: usize,
+    pub output_status: OutputStatus,
+    /// Whether the night mode schedule of the zone is active right now.
+    pub night_mode: bool,
+    /// Playback speed of files and CD tracks.
+    pub speed: f32,
+    /// A-B repeat of the current file or CD track, updated every frame.
+    pub ab_loop: AbLoop,
+}
+
+impl Zone {
+    /// Create the player of a zone.  Player events are bridged through
+    /// threads that wake up the UI, so changes show up immediately even if
+    /// no other interaction is happening.
+    pub fn new(name: String, audio: AudioConfig, runtime: Handle, ctx: &egui::Context) -> Self {
+        let (title_tx, title_rx) = mpsc::channel();
+        let (button_tx, button_rx) = mpsc::channel();
+        let player = RodioPlayer::new(title_tx, button_tx, audio.output_backend(), runtime);
+        if let Err(e) = player.use_hardware_mixer(audio.hardware_mixer()) {
+            error!("Could not open hardware mixer, using software volume: {e}");
+        }
+        player.set_duck_level(audio.duck_db);
+        player.set_stream_loudness_target(audio.stream_loudness_lufs);
+        player.set_silence_settings(audio.silence_settings());
+        let volume = audio.initial_volume();
+        player.set_volume(volume);
+        let output_status = player.output_status();
+
+        let label = if name.is_empty() { "main" } else { &name };
+        let (bridged_title_tx, bridged_title_rx) = mpsc::channel();
+        let ctx_for_titles = ctx.clone();
+        std::thread::Builder::new()
+            .name(format!("title-bridge-{label}"))
+            .spawn(move || {
+                while let Ok(msg) = title_rx.recv() {
+                    let _ = bridged_title_tx.send(msg);
+                    ctx_for_titles.request_repaint();
+                }
+            })
+            .expect("Failed to spawn title bridge thread");
+
+        let (bridged_button_tx, bridged_button_rx) = mpsc::channel();
+        let ctx = ctx.clone();
+        std::thread::Builder::new()
+            .name(format!("button-state-bridge-{label}"))
+            .spawn(move || {
+                while let Ok(msg) = button_rx.recv() {
+                    let _ = bridged_button_tx.send(msg);
+                    ctx.request_repaint();
+                }
+            })
+            .expect("Failed to spawn button-state bridge thread");
+
+        Self {
+            name,
+            audio,
+            player,
+            title_rx: bridged_title_rx,
+            button_state_rx: bridged_button_rx,
+            is_playing: false,
+            is_paused: false,
+            is_muted: false,
+            current_title: TitleChanged::default(),
+            library_match: None,
+            library_match_rx: None,
+            volume,
+            playlist_queue: Vec::new(),
+            playlist_index: 0,
+            output_status,
+            night_mode: false,
+            speed: 1.0,
+            ab_loop: AbLoop::Off,
+        }
+    }
+
+    /// Name to show in the zone selector.
+    pub fn label(&self) -> String {
+        if self.name.is_empty() {
+            egui_i18n::tr!("zone_main")
+        } else {
+            self.name.clone()
+        }
+    }
+
+    /// Maximum volume right now, lower while night mode is active.
+    pub fn max_volume(&self) -> f32 {
+        match &self.audio.night_mode {
+            Some(night) if self.night_mode => {
+                self.audio.max_volume().min(night.max_volume as f32 / 100.0)
+            }
+            _ => self.audio.max_volume(),
+        }
+    }
+
+    /// Switch night mode on or off according to its schedule.  Returns
+    /// whether it changed.
+    pub fn update_night_mode(&mut self, now: NaiveTime) -> bool {
+        let active = self
+            .audio
+            .night_mode
+            .as_ref()
+            .is_some_and(|night| night.is_active_at(now));
+        if active == self.night_mode {
+            return false;
+        }
+        info!(
+            "Night mode {} in zone {}",
+            if active { "started" } else { "ended" },
+            self.label()
+        );
+        self.night_mode = active;
+        let compress = self
+            .audio
+            .night_mode
+            .as_ref()
+            .is_some_and(|night| night.compress);
+        self.player.set_night_mode(active && compress);
+        self.clamp_volume();
+        true
+    }
+
+    /// Turn the volume down if it is above the current maximum.
+    fn clamp_volume(&mut self) {
+        let max_vol = self.max_volume();
+        if self.volume > max_vol {
+            self.volume = max_vol;
+            self.player.set_volume(max_vol);
+        }
+    }
+
+    /// Apply a changed audio configuration: switch the output and the
+    /// hardware mixer, and keep the volume below the maximum.
+    pub fn apply_audio_config(&mut self, audio: AudioConfig) -> Result<(), anyhow::Error> {
+        self.player.switch_output(audio.output_backend());
+        let mixer_result = self.player.use_hardware_mixer(audio.hardware_mixer());
+        self.player.set_duck_level(audio.duck_db);
+        self.player
+            .set_stream_loudness_target(audio.stream_loudness_lufs);
+        self.player.set_silence_settings(audio.silence_settings());
+        self.audio = audio;
+        // Evaluate the (possibly changed) schedule from scratch.
+        self.night_mode = false;
+        self.player.set_night_mode(false);
+        self.update_night_mode(Local::now().time());
+        self.clamp_volume();
+        mixer_result
+    }
+}
+
+/// Name and audio configuration of every zone, the main zone first.
+pub fn zone_configs(config: &Config) -> Vec<(String, AudioConfig)> {
+    std::iter::once((String::new(), config.audio.clone()))
+        .chain(
+            config
+                .zones
+                .iter()
+                .map(|zone| (zone.name.clone(), zone.audio.clone())),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zone_configs_main_zone_first() {
+        let audio = r#"{"start_volume": 30, "max_volume": 80}"#;
+        let config: Config = serde_json::from_str(&format!(
+            r#"{{"sources": [], "audio": {audio},
+                "zones": [{{"name": "Kitchen", "audio": {audio}}}]}}"#
+        ))
+        .unwrap();
+        let zones = zone_configs(&config);
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones[0].0, "");
+        assert_eq!(zones[1].0, "Kitchen");
+        assert_eq!(zones[1].1.initial_volume(), 0.3);
+    }
+}